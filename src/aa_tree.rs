@@ -0,0 +1,421 @@
+use std::borrow::Borrow;
+use std::cmp::{min, Ordering};
+use std::fmt::Debug;
+use std::mem::{replace, swap};
+
+use super::iter::{InOrder, TreeNode};
+use super::BST;
+#[cfg(test)]
+use super::{iter::print_tree, unsorted};
+#[derive(Debug)]
+struct Node<K: Ord> {
+  key: K,
+  level: usize,
+  size: usize,
+  lchild: Option<Box<Node<K>>>,
+  rchild: Option<Box<Node<K>>>,
+}
+
+type Link<K> = Option<Box<Node<K>>>;
+
+/// An AA tree after Andersson. Every node has a level, leaves are at level
+/// one, a left child is one level below its parent and a right child is at
+/// most one level below, but never a right grandchild. This is a red-black tree
+/// in which only right children may be red, and the two operations `skew` and
+/// `split` are enough to rebalance it.
+pub struct AaTree<K: Ord + Debug> {
+  root: Option<Box<Node<K>>>,
+}
+
+impl<K: Ord + Debug> AaTree<K> {
+  pub fn new() -> AaTree<K> {
+    AaTree { root: None }
+  }
+  fn level(tree: &Option<Box<Node<K>>>) -> usize {
+    tree.as_ref().map_or(0, |t| t.level)
+  }
+  fn size(tree: &Option<Box<Node<K>>>) -> usize {
+    tree.as_ref().map_or(0, |t| t.size)
+  }
+  fn update(tree: &mut Node<K>) {
+    tree.size = 1 + AaTree::size(&tree.lchild) + AaTree::size(&tree.rchild);
+  }
+
+  fn rotate_right(tree: &mut Box<Node<K>>) {
+    let lnode = tree.lchild.as_mut().unwrap();
+    let llnode = lnode.lchild.take();
+    let lrnode = lnode.rchild.take();
+    let mut rnode = replace(&mut tree.lchild, llnode);
+    swap(
+      &mut tree.as_mut().key,
+      &mut rnode.as_deref_mut().unwrap().key,
+    );
+    swap(
+      &mut tree.as_mut().level,
+      &mut rnode.as_deref_mut().unwrap().level,
+    );
+    rnode.as_deref_mut().unwrap().lchild = lrnode;
+    rnode.as_deref_mut().unwrap().rchild = tree.rchild.take();
+    AaTree::update(rnode.as_deref_mut().unwrap());
+    tree.rchild = rnode;
+    AaTree::update(tree);
+  }
+
+  fn rotate_left(tree: &mut Box<Node<K>>) {
+    let rnode = tree.rchild.as_mut().unwrap();
+    let rlnode = rnode.lchild.take();
+    let rrnode = rnode.rchild.take();
+    let mut lnode = replace(&mut tree.rchild, rrnode);
+    swap(
+      &mut tree.as_mut().key,
+      &mut lnode.as_deref_mut().unwrap().key,
+    );
+    swap(
+      &mut tree.as_mut().level,
+      &mut lnode.as_deref_mut().unwrap().level,
+    );
+    lnode.as_deref_mut().unwrap().lchild = tree.lchild.take();
+    lnode.as_deref_mut().unwrap().rchild = rlnode;
+    AaTree::update(lnode.as_deref_mut().unwrap());
+    tree.lchild = lnode;
+    AaTree::update(tree);
+  }
+  /// Removes a left child on the same level by rotating it up.
+  fn skew(tree: &mut Box<Node<K>>) {
+    //      t       l
+    //     /         \
+    //    l    =>     t
+    if AaTree::level(&tree.lchild) == tree.level {
+      AaTree::rotate_right(tree);
+    }
+  }
+  /// Removes two consecutive right children on the same level by rotating the
+  /// middle one up and raising it by one level.
+  fn split(tree: &mut Box<Node<K>>) {
+    //  t             r
+    //   \           / \
+    //    r    =>   t   x
+    //     \
+    //      x
+    let rrlevel = tree.rchild.as_ref().map_or(0, |r| AaTree::level(&r.rchild));
+    if rrlevel == tree.level {
+      AaTree::rotate_left(tree);
+      tree.level += 1;
+    }
+  }
+  /// Restores the invariants at `tree` after one of its subtrees lost a node.
+  fn rebalance(tree: &mut Box<Node<K>>) {
+    AaTree::update(tree);
+    let level = min(AaTree::level(&tree.lchild), AaTree::level(&tree.rchild)) + 1;
+    if level < tree.level {
+      tree.level = level;
+      if let Some(r) = tree.rchild.as_mut() {
+        r.level = min(r.level, level);
+      }
+    }
+    AaTree::skew(tree);
+    if let Some(r) = tree.rchild.as_mut() {
+      AaTree::skew(r);
+      if let Some(rr) = r.rchild.as_mut() {
+        AaTree::skew(rr);
+      }
+    }
+    AaTree::split(tree);
+    if let Some(r) = tree.rchild.as_mut() {
+      AaTree::split(r);
+    }
+  }
+
+  fn _insert(key: K, tree: &mut Link<K>) -> bool {
+    let t = match tree {
+      None => {
+        *tree = Some(Box::new(Node {
+          key,
+          level: 1,
+          size: 1,
+          lchild: None,
+          rchild: None,
+        }));
+        return true;
+      }
+      Some(t) => t,
+    };
+    let inserted = match key.cmp(&t.key) {
+      Ordering::Less => AaTree::_insert(key, &mut t.lchild),
+      Ordering::Greater => AaTree::_insert(key, &mut t.rchild),
+      Ordering::Equal => return false,
+    };
+    AaTree::update(t);
+    AaTree::skew(t);
+    AaTree::split(t);
+    inserted
+  }
+  fn _find<'a, Q>(key: &Q, tree: &'a Link<K>) -> Option<&'a Node<K>>
+  where
+    K: Borrow<Q>,
+    Q: Ord + ?Sized,
+  {
+    let mut tree = tree;
+    while let Some(t) = tree {
+      match key.cmp(t.key.borrow()) {
+        Ordering::Less => tree = &t.lchild,
+        Ordering::Greater => tree = &t.rchild,
+        Ordering::Equal => return Some(t),
+      }
+    }
+    None
+  }
+  /// Removes the largest key from the non-empty `tree`.
+  fn delete_max(tree: &mut Link<K>) -> K {
+    let t = tree.as_mut().unwrap();
+    if t.rchild.is_none() {
+      let t = tree.take().unwrap();
+      *tree = t.lchild;
+      return t.key;
+    }
+    let max = AaTree::delete_max(&mut t.rchild);
+    AaTree::rebalance(t);
+    max
+  }
+  /// Removes `key`. A node with a left child takes the key of its predecessor
+  /// instead, and one without is a level one node replaced by its right child.
+  fn _delete<Q>(key: &Q, tree: &mut Link<K>) -> Option<K>
+  where
+    K: Borrow<Q>,
+    Q: Ord + ?Sized,
+  {
+    let t = tree.as_mut()?;
+    let removed = match key.cmp(t.key.borrow()) {
+      Ordering::Less => AaTree::_delete(key, &mut t.lchild)?,
+      Ordering::Greater => AaTree::_delete(key, &mut t.rchild)?,
+      Ordering::Equal if t.lchild.is_none() => {
+        let t = tree.take().unwrap();
+        *tree = t.rchild;
+        return Some(t.key);
+      }
+      Ordering::Equal => {
+        let max = AaTree::delete_max(&mut t.lchild);
+        replace(&mut t.key, max)
+      }
+    };
+    AaTree::rebalance(t);
+    Some(removed)
+  }
+
+  pub fn len(&self) -> usize {
+    AaTree::size(&self.root)
+  }
+  pub fn is_empty(&self) -> bool {
+    self.root.is_none()
+  }
+  /// Returns an iterator over the keys in ascending order.
+  pub fn iter(&self) -> Iter<'_, K> {
+    Iter {
+      walk: InOrder::new(&self.root, self.len()),
+    }
+  }
+  fn _invalid_level(tree: &Option<Box<Node<K>>>) -> bool {
+    match tree {
+      None => false,
+      Some(t) => {
+        let rrlevel = t.rchild.as_ref().map_or(0, |r| AaTree::level(&r.rchild));
+        (t.lchild.is_none() && t.rchild.is_none() && t.level != 1)
+          || AaTree::level(&t.lchild) + 1 != t.level
+          || AaTree::level(&t.rchild) + 1 < t.level
+          || AaTree::level(&t.rchild) > t.level
+          || rrlevel >= t.level
+          || AaTree::_invalid_level(&t.lchild)
+          || AaTree::_invalid_level(&t.rchild)
+      }
+    }
+  }
+  /// Checks the levels of the tree. Returns `true` if a leaf is not at level
+  /// one, a left child is not exactly one level below its parent, a right child
+  /// is more than one level below its parent or above it, or a right grandchild
+  /// is on the same level as its grandparent.
+  pub fn invalid_level(&self) -> bool {
+    AaTree::_invalid_level(&self.root)
+  }
+  #[cfg(test)]
+  pub fn print(&self) -> String {
+    print_tree(self.root.as_deref().unwrap(), &|t| {
+      format!("{:?}:{}", t.key, t.level)
+    })
+  }
+  #[cfg(test)]
+  fn _invalid_size(tree: &Option<Box<Node<K>>>) -> bool {
+    match tree {
+      None => false,
+      Some(t) => {
+        t.size != 1 + AaTree::size(&t.lchild) + AaTree::size(&t.rchild)
+          || AaTree::_invalid_size(&t.lchild)
+          || AaTree::_invalid_size(&t.rchild)
+      }
+    }
+  }
+  #[cfg(test)]
+  pub fn invalid_size(&self) -> bool {
+    AaTree::_invalid_size(&self.root)
+  }
+  #[cfg(test)]
+  pub fn invalid_key(&self) -> bool {
+    unsorted(self.iter())
+  }
+}
+
+impl<K: Ord + Debug> Default for AaTree<K> {
+  fn default() -> AaTree<K> {
+    AaTree::new()
+  }
+}
+
+impl<K: Ord + Debug> BST<K> for AaTree<K> {
+  fn insert(&mut self, key: K) -> bool {
+    AaTree::_insert(key, &mut self.root)
+  }
+  fn find<Q>(&mut self, key: &Q) -> bool
+  where
+    K: Borrow<Q>,
+    Q: Ord + ?Sized,
+  {
+    AaTree::_find(key, &self.root).is_some()
+  }
+  fn delete<Q>(&mut self, key: &Q) -> Option<K>
+  where
+    K: Borrow<Q>,
+    Q: Ord + ?Sized,
+  {
+    AaTree::_delete(key, &mut self.root)
+  }
+}
+
+impl<K: Ord> TreeNode for Node<K> {
+  type Key = K;
+  fn key(&self) -> &K {
+    &self.key
+  }
+  fn lchild(&self) -> &Link<K> {
+    &self.lchild
+  }
+  fn rchild(&self) -> &Link<K> {
+    &self.rchild
+  }
+  fn lchild_mut(&mut self) -> &mut Link<K> {
+    &mut self.lchild
+  }
+  fn rchild_mut(&mut self) -> &mut Link<K> {
+    &mut self.rchild
+  }
+}
+
+/// An in-order iterator over the keys of an `AaTree`.
+pub struct Iter<'a, K: Ord> {
+  walk: InOrder<'a, Node<K>>,
+}
+
+impl<'a, K: Ord> Iterator for Iter<'a, K> {
+  type Item = &'a K;
+
+  fn next(&mut self) -> Option<&'a K> {
+    self.walk.next().map(|t| &t.key)
+  }
+  fn size_hint(&self) -> (usize, Option<usize>) {
+    self.walk.size_hint()
+  }
+}
+
+impl<'a, K: Ord> DoubleEndedIterator for Iter<'a, K> {
+  fn next_back(&mut self) -> Option<&'a K> {
+    self.walk.next_back().map(|t| &t.key)
+  }
+}
+
+impl<'a, K: Ord> ExactSizeIterator for Iter<'a, K> {}
+
+impl<'a, K: Ord + Debug> IntoIterator for &'a AaTree<K> {
+  type Item = &'a K;
+  type IntoIter = Iter<'a, K>;
+
+  fn into_iter(self) -> Iter<'a, K> {
+    self.iter()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::AaTree;
+  use super::BST;
+  use crate::conformance;
+  use assert_str::assert_str_eq;
+
+  #[test]
+  fn test_insert() {
+    let mut tree: AaTree<u64> = AaTree::new();
+    assert!(tree.insert(10));
+    assert!(tree.insert(50));
+    assert!(!tree.insert(10));
+    assert_str_eq!(tree.print(), "[10:1()([50:1()()])]");
+    // split
+    assert!(tree.insert(100));
+    assert_str_eq!(tree.print(), "[50:2([10:1()()])([100:1()()])]");
+    // skew
+    assert!(tree.insert(5));
+    assert_str_eq!(tree.print(), "[50:2([5:1()([10:1()()])])([100:1()()])]");
+    // skew and split
+    assert!(tree.insert(7));
+    assert_str_eq!(
+      tree.print(),
+      "[7:2([5:1()()])([50:2([10:1()()])([100:1()()])])]"
+    );
+    assert!(!tree.invalid_level());
+    assert!(!tree.invalid_size());
+  }
+  #[test]
+  fn test_find() {
+    let mut tree: AaTree<u64> = AaTree::new();
+    for key in [10, 50, 5, 200, 400, 100].iter() {
+      tree.insert(*key);
+    }
+    assert!(tree.find(&5));
+    assert!(tree.find(&400));
+    assert!(!tree.find(&7));
+    assert!(!tree.invalid_level());
+    assert!(!tree.invalid_key());
+  }
+  #[test]
+  fn test_delete() {
+    let mut tree: AaTree<u64> = AaTree::new();
+    for key in [10, 50, 100, 5, 7].iter() {
+      tree.insert(*key);
+    }
+    // an inner node takes the key of its predecessor
+    assert_eq!(tree.delete(&7), Some(7));
+    assert_str_eq!(tree.print(), "[10:2([5:1()()])([50:1()([100:1()()])])]");
+    assert!(!tree.invalid_level());
+    // a level one node is replaced by its right child
+    assert_eq!(tree.delete(&50), Some(50));
+    assert_eq!(tree.delete(&50), None);
+    assert!(!tree.invalid_level());
+    assert_eq!(tree.len(), 3);
+  }
+  #[test]
+  fn test_find_borrowed() {
+    conformance::find_borrowed(AaTree::new());
+  }
+  #[test]
+  fn test_invariants() {
+    let tree = conformance::invariants(AaTree::new(), |tree| {
+      assert!(!tree.invalid_level());
+      assert!(!tree.invalid_size());
+      assert!(!tree.invalid_key());
+    });
+    assert!(tree.is_empty());
+  }
+  #[test]
+  fn test_sorted_insert_large() {
+    let tree = conformance::sorted_insert_large(AaTree::new(), |tree| {
+      assert!(!tree.invalid_level());
+    });
+    assert!(tree.iter().cloned().eq((1..100_000).step_by(2)));
+  }
+}
@@ -0,0 +1,429 @@
+use std::borrow::Borrow;
+use std::cmp::{max, Ordering};
+use std::fmt::Debug;
+use std::mem::{replace, swap};
+
+use super::iter::{InOrder, TreeNode};
+use super::BST;
+#[cfg(test)]
+use super::{iter::print_tree, unsorted};
+#[derive(Debug)]
+struct Node<K: Ord> {
+  key: K,
+  height: usize,
+  size: usize,
+  lchild: Option<Box<Node<K>>>,
+  rchild: Option<Box<Node<K>>>,
+}
+
+type Link<K> = Option<Box<Node<K>>>;
+type Path<K> = Vec<(Box<Node<K>>, Ordering)>;
+
+/// A height-balanced binary search tree. The heights of the two subtrees of
+/// every node differ by at most one, so every operation takes O(log n) time in
+/// the worst case.
+pub struct Avl<K: Ord + Debug> {
+  root: Option<Box<Node<K>>>,
+}
+
+impl<K: Ord + Debug> Avl<K> {
+  pub fn new() -> Avl<K> {
+    Avl { root: None }
+  }
+  fn height(tree: &Option<Box<Node<K>>>) -> usize {
+    tree.as_ref().map_or(0, |t| t.height)
+  }
+  fn size(tree: &Option<Box<Node<K>>>) -> usize {
+    tree.as_ref().map_or(0, |t| t.size)
+  }
+  fn update(tree: &mut Node<K>) {
+    tree.height = 1 + max(Avl::height(&tree.lchild), Avl::height(&tree.rchild));
+    tree.size = 1 + Avl::size(&tree.lchild) + Avl::size(&tree.rchild);
+  }
+  /// Returns the height of the left subtree minus the height of the right one.
+  fn balance_factor(tree: &Node<K>) -> isize {
+    Avl::height(&tree.lchild) as isize - Avl::height(&tree.rchild) as isize
+  }
+
+  fn rotate_right(tree: &mut Box<Node<K>>) {
+    let lnode = tree.lchild.as_mut().unwrap();
+    let llnode = lnode.lchild.take();
+    let lrnode = lnode.rchild.take();
+    let mut rnode = replace(&mut tree.lchild, llnode);
+    swap(
+      &mut tree.as_mut().key,
+      &mut rnode.as_deref_mut().unwrap().key,
+    );
+    rnode.as_deref_mut().unwrap().lchild = lrnode;
+    rnode.as_deref_mut().unwrap().rchild = tree.rchild.take();
+    Avl::update(rnode.as_deref_mut().unwrap());
+    tree.rchild = rnode;
+    Avl::update(tree);
+  }
+
+  fn rotate_left(tree: &mut Box<Node<K>>) {
+    let rnode = tree.rchild.as_mut().unwrap();
+    let rlnode = rnode.lchild.take();
+    let rrnode = rnode.rchild.take();
+    let mut lnode = replace(&mut tree.rchild, rrnode);
+    swap(
+      &mut tree.as_mut().key,
+      &mut lnode.as_deref_mut().unwrap().key,
+    );
+    lnode.as_deref_mut().unwrap().lchild = tree.lchild.take();
+    lnode.as_deref_mut().unwrap().rchild = rlnode;
+    Avl::update(lnode.as_deref_mut().unwrap());
+    tree.lchild = lnode;
+    Avl::update(tree);
+  }
+  /// Recomputes the height of `tree` and restores its balance with a single or
+  /// double rotation if its subtrees differ in height by two.
+  fn rebalance(tree: &mut Box<Node<K>>) {
+    Avl::update(tree);
+    let factor = Avl::balance_factor(tree);
+    if factor > 1 {
+      let lnode = tree.lchild.as_mut().unwrap();
+      if Avl::balance_factor(lnode) < 0 {
+        Avl::rotate_left(lnode);
+      }
+      Avl::rotate_right(tree);
+    } else if factor < -1 {
+      let rnode = tree.rchild.as_mut().unwrap();
+      if Avl::balance_factor(rnode) > 0 {
+        Avl::rotate_right(rnode);
+      }
+      Avl::rotate_left(tree);
+    }
+  }
+  /// Walks down from `tree` following `cmp`, detaching every visited node from
+  /// its parent. Returns the detached ancestors, each with the direction taken
+  /// below it, and the node where `cmp` returned `Equal` if there is one.
+  fn descend<F>(tree: Link<K>, mut cmp: F) -> (Path<K>, Link<K>)
+  where
+    F: FnMut(&K) -> Ordering,
+  {
+    let mut path = Vec::new();
+    let mut tree = tree;
+    while let Some(mut t) = tree {
+      match cmp(&t.key) {
+        Ordering::Less => {
+          tree = t.lchild.take();
+          path.push((t, Ordering::Less));
+        }
+        Ordering::Greater => {
+          tree = t.rchild.take();
+          path.push((t, Ordering::Greater));
+        }
+        Ordering::Equal => return (path, Some(t)),
+      }
+    }
+    (path, None)
+  }
+  /// Reattaches `tree` below the ancestors in `path`, rebalancing each of them
+  /// on the way up.
+  fn rebuild(mut path: Path<K>, tree: Link<K>) -> Link<K> {
+    let mut tree = tree;
+    while let Some((mut p, dir)) = path.pop() {
+      match dir {
+        Ordering::Less => p.lchild = tree,
+        _ => p.rchild = tree,
+      }
+      Avl::rebalance(&mut p);
+      tree = Some(p);
+    }
+    tree
+  }
+
+  fn _insert(key: K, tree: &mut Link<K>) -> bool {
+    let (path, found) = Avl::descend(tree.take(), |k| key.cmp(k));
+    if found.is_some() {
+      *tree = Avl::rebuild(path, found);
+      return false;
+    }
+    let leaf = Box::new(Node {
+      key,
+      height: 1,
+      size: 1,
+      lchild: None,
+      rchild: None,
+    });
+    *tree = Avl::rebuild(path, Some(leaf));
+    true
+  }
+  fn _find<'a, Q>(key: &Q, tree: &'a Link<K>) -> Option<&'a Node<K>>
+  where
+    K: Borrow<Q>,
+    Q: Ord + ?Sized,
+  {
+    let mut tree = tree;
+    while let Some(t) = tree {
+      match key.cmp(t.key.borrow()) {
+        Ordering::Less => tree = &t.lchild,
+        Ordering::Greater => tree = &t.rchild,
+        Ordering::Equal => return Some(t),
+      }
+    }
+    None
+  }
+  /// Detaches the node with the smallest key from `tree`, returning it and the
+  /// rebalanced rest of the tree.
+  fn remove_min(tree: Box<Node<K>>) -> (Box<Node<K>>, Link<K>) {
+    let (mut path, _) = Avl::descend(Some(tree), |_| Ordering::Less);
+    let (mut min, _) = path.pop().unwrap();
+    let rest = min.rchild.take();
+    (min, Avl::rebuild(path, rest))
+  }
+  /// Removes the node with `key`. A node with two children is replaced by the
+  /// minimum of its right subtree.
+  fn _delete<Q>(key: &Q, tree: &mut Link<K>) -> Option<K>
+  where
+    K: Borrow<Q>,
+    Q: Ord + ?Sized,
+  {
+    let (path, found) = Avl::descend(tree.take(), |k| key.cmp(k.borrow()));
+    let mut t = match found {
+      None => {
+        *tree = Avl::rebuild(path, None);
+        return None;
+      }
+      Some(t) => t,
+    };
+    let replacement = match (t.lchild.take(), t.rchild.take()) {
+      (None, r) => r,
+      (l, None) => l,
+      (l, Some(r)) => {
+        let (mut min, rest) = Avl::remove_min(r);
+        min.lchild = l;
+        min.rchild = rest;
+        Avl::rebalance(&mut min);
+        Some(min)
+      }
+    };
+    *tree = Avl::rebuild(path, replacement);
+    Some(t.key)
+  }
+
+  pub fn len(&self) -> usize {
+    Avl::size(&self.root)
+  }
+  pub fn is_empty(&self) -> bool {
+    self.root.is_none()
+  }
+  /// Returns the height of the tree, which is zero when it is empty.
+  pub fn depth(&self) -> usize {
+    Avl::height(&self.root)
+  }
+  /// Returns an iterator over the keys in ascending order.
+  pub fn iter(&self) -> Iter<'_, K> {
+    Iter {
+      walk: InOrder::new(&self.root, self.len()),
+    }
+  }
+  #[cfg(test)]
+  pub fn print(&self) -> String {
+    print_tree(self.root.as_deref().unwrap(), &|t| format!("{:?}", t.key))
+  }
+  #[cfg(test)]
+  fn _invalid_balance(tree: &Option<Box<Node<K>>>) -> bool {
+    match tree {
+      None => false,
+      Some(t) => {
+        t.height != 1 + max(Avl::height(&t.lchild), Avl::height(&t.rchild))
+          || t.size != 1 + Avl::size(&t.lchild) + Avl::size(&t.rchild)
+          || Avl::balance_factor(t).abs() > 1
+          || Avl::_invalid_balance(&t.lchild)
+          || Avl::_invalid_balance(&t.rchild)
+      }
+    }
+  }
+  #[cfg(test)]
+  pub fn invalid_balance(&self) -> bool {
+    Avl::_invalid_balance(&self.root)
+  }
+  #[cfg(test)]
+  pub fn invalid_key(&self) -> bool {
+    unsorted(self.iter())
+  }
+}
+
+impl<K: Ord + Debug> Default for Avl<K> {
+  fn default() -> Avl<K> {
+    Avl::new()
+  }
+}
+
+impl<K: Ord + Debug> BST<K> for Avl<K> {
+  fn insert(&mut self, key: K) -> bool {
+    Avl::_insert(key, &mut self.root)
+  }
+  fn find<Q>(&mut self, key: &Q) -> bool
+  where
+    K: Borrow<Q>,
+    Q: Ord + ?Sized,
+  {
+    Avl::_find(key, &self.root).is_some()
+  }
+  fn delete<Q>(&mut self, key: &Q) -> Option<K>
+  where
+    K: Borrow<Q>,
+    Q: Ord + ?Sized,
+  {
+    Avl::_delete(key, &mut self.root)
+  }
+}
+
+impl<K: Ord> TreeNode for Node<K> {
+  type Key = K;
+  fn key(&self) -> &K {
+    &self.key
+  }
+  fn lchild(&self) -> &Link<K> {
+    &self.lchild
+  }
+  fn rchild(&self) -> &Link<K> {
+    &self.rchild
+  }
+  fn lchild_mut(&mut self) -> &mut Link<K> {
+    &mut self.lchild
+  }
+  fn rchild_mut(&mut self) -> &mut Link<K> {
+    &mut self.rchild
+  }
+}
+
+/// An in-order iterator over the keys of an `Avl`.
+pub struct Iter<'a, K: Ord> {
+  walk: InOrder<'a, Node<K>>,
+}
+
+impl<'a, K: Ord> Iterator for Iter<'a, K> {
+  type Item = &'a K;
+
+  fn next(&mut self) -> Option<&'a K> {
+    self.walk.next().map(|t| &t.key)
+  }
+  fn size_hint(&self) -> (usize, Option<usize>) {
+    self.walk.size_hint()
+  }
+}
+
+impl<'a, K: Ord> DoubleEndedIterator for Iter<'a, K> {
+  fn next_back(&mut self) -> Option<&'a K> {
+    self.walk.next_back().map(|t| &t.key)
+  }
+}
+
+impl<'a, K: Ord> ExactSizeIterator for Iter<'a, K> {}
+
+impl<'a, K: Ord + Debug> IntoIterator for &'a Avl<K> {
+  type Item = &'a K;
+  type IntoIter = Iter<'a, K>;
+
+  fn into_iter(self) -> Iter<'a, K> {
+    self.iter()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::Avl;
+  use super::BST;
+  use crate::conformance;
+  use assert_str::assert_str_eq;
+
+  #[test]
+  fn test_insert() {
+    let mut tree: Avl<u64> = Avl::new();
+    assert!(tree.insert(10));
+    assert_str_eq!(tree.print(), "[10()()]");
+    assert!(tree.insert(50));
+    assert!(!tree.insert(10));
+    assert_str_eq!(tree.print(), "[10()([50()()])]");
+    // right-right: single rotation
+    assert!(tree.insert(100));
+    assert_str_eq!(tree.print(), "[50([10()()])([100()()])]");
+    assert!(!tree.invalid_balance());
+  }
+  #[test]
+  fn test_insert_double_rotation() {
+    let mut tree: Avl<u64> = Avl::new();
+    tree.insert(10);
+    tree.insert(50);
+    // right-left: double rotation
+    tree.insert(30);
+    assert_str_eq!(tree.print(), "[30([10()()])([50()()])]");
+    tree.insert(5);
+    tree.insert(7);
+    assert_str_eq!(tree.print(), "[30([7([5()()])([10()()])])([50()()])]");
+    assert!(!tree.invalid_balance());
+    assert!(!tree.invalid_key());
+  }
+  #[test]
+  fn test_find() {
+    let mut tree: Avl<u64> = Avl::new();
+    for key in [10, 50, 5, 200, 400, 100].iter() {
+      tree.insert(*key);
+    }
+    assert!(tree.find(&5));
+    assert!(tree.find(&400));
+    assert!(!tree.find(&7));
+    assert_str_eq!(
+      tree.print(),
+      "[50([10([5()()])()])([200([100()()])([400()()])])]"
+    );
+  }
+  #[test]
+  fn test_delete() {
+    let mut tree: Avl<u64> = Avl::new();
+    for key in [10, 50, 5, 200, 400, 100].iter() {
+      tree.insert(*key);
+    }
+    assert_eq!(tree.delete(&50), Some(50));
+    assert_str_eq!(tree.print(), "[100([10([5()()])()])([200()([400()()])])]");
+    assert_eq!(tree.delete(&50), None);
+    assert_eq!(tree.delete(&200), Some(200));
+    assert_eq!(tree.delete(&400), Some(400));
+    // left-left after deletion: single rotation
+    assert_str_eq!(tree.print(), "[10([5()()])([100()()])]");
+    assert!(!tree.invalid_balance());
+    assert!(!tree.find(&50));
+    assert_eq!(tree.len(), 3);
+  }
+  #[test]
+  fn test_iter() {
+    let mut tree: Avl<u64> = Avl::new();
+    assert_eq!(tree.iter().next(), None);
+    for key in [10, 50, 5, 200, 400, 100].iter() {
+      tree.insert(*key);
+    }
+    let keys: Vec<u64> = tree.iter().cloned().collect();
+    assert_eq!(keys, vec![5, 10, 50, 100, 200, 400]);
+    assert_eq!(tree.iter().len(), 6);
+    let mut sum = 0;
+    for key in &tree {
+      sum += key;
+    }
+    assert_eq!(sum, 765);
+  }
+  #[test]
+  fn test_find_borrowed() {
+    conformance::find_borrowed(Avl::new());
+  }
+  #[test]
+  fn test_invariants() {
+    let tree = conformance::invariants(Avl::new(), |tree| {
+      assert!(!tree.invalid_balance());
+      assert!(!tree.invalid_key());
+    });
+    assert!(tree.is_empty());
+  }
+  #[test]
+  fn test_sorted_insert_large() {
+    let tree = conformance::sorted_insert_large(Avl::new(), |tree| {
+      // An AVL tree with n nodes is less than 1.45 log2(n + 2) high.
+      assert!(tree.depth() <= 24);
+      assert!(!tree.invalid_balance());
+    });
+    assert!(tree.iter().cloned().eq((1..100_000).step_by(2)));
+  }
+}
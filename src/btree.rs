@@ -0,0 +1,687 @@
+use std::borrow::Borrow;
+use std::collections::VecDeque;
+use std::fmt::Debug;
+use std::iter::FromIterator;
+use std::mem::replace;
+use std::ops::RangeBounds;
+
+#[cfg(test)]
+use super::unsorted;
+use super::{above_start, below_end, BST};
+#[derive(Debug)]
+struct Node<K: Ord> {
+  keys: Vec<K>,
+  // Empty for leaves, otherwise one more than `keys`.
+  children: Vec<Node<K>>,
+}
+
+/// A B-tree storing up to `order - 1` keys per node in a contiguous vector, so
+/// lookups touch O(log n / log order) cache-friendly nodes instead of one boxed
+/// node per key. Nodes are split when they overflow on insertion and refilled
+/// from or merged with a sibling when they underflow on deletion.
+pub struct BTree<K: Ord + Debug> {
+  root: Node<K>,
+  order: usize,
+  len: usize,
+}
+
+impl<K: Ord> Node<K> {
+  fn new() -> Node<K> {
+    Node {
+      keys: Vec::new(),
+      children: Vec::new(),
+    }
+  }
+  fn is_leaf(&self) -> bool {
+    self.children.is_empty()
+  }
+  /// Returns `Ok` with the index of `key`, or `Err` with the index of the child
+  /// that would hold it.
+  fn search<Q>(&self, key: &Q) -> Result<usize, usize>
+  where
+    K: Borrow<Q>,
+    Q: Ord + ?Sized,
+  {
+    self.keys.binary_search_by(|k| k.borrow().cmp(key))
+  }
+}
+
+impl<K: Ord + Debug> BTree<K> {
+  /// Creates a B-tree whose nodes have at most `order` children.
+  ///
+  /// # Panics
+  ///
+  /// Panics if `order` is less than 3.
+  pub fn new(order: usize) -> BTree<K> {
+    assert!(order >= 3, "new: order must be at least 3");
+    BTree {
+      root: Node::new(),
+      order,
+      len: 0,
+    }
+  }
+  /// The fewest keys a node other than the root may hold.
+  fn min_keys(&self) -> usize {
+    self.order.div_ceil(2) - 1
+  }
+
+  /// Splits the overflowing child `i` of `tree` around its median key, which
+  /// moves up into `tree`.
+  fn split_child(tree: &mut Node<K>, i: usize) {
+    let child = &mut tree.children[i];
+    let mid = child.keys.len() / 2;
+    let keys = child.keys.split_off(mid + 1);
+    let median = child.keys.pop().unwrap();
+    let children = if child.is_leaf() {
+      Vec::new()
+    } else {
+      child.children.split_off(mid + 1)
+    };
+    tree.keys.insert(i, median);
+    tree.children.insert(i + 1, Node { keys, children });
+  }
+  fn _insert(key: K, tree: &mut Node<K>, order: usize) -> bool {
+    let i = match tree.search(&key) {
+      Ok(_) => return false,
+      Err(i) => i,
+    };
+    if tree.is_leaf() {
+      tree.keys.insert(i, key);
+      return true;
+    }
+    if !BTree::_insert(key, &mut tree.children[i], order) {
+      return false;
+    }
+    if tree.children[i].keys.len() >= order {
+      BTree::split_child(tree, i);
+    }
+    true
+  }
+
+  /// Refills child `i` of `tree` if it has fewer than `min` keys, borrowing a
+  /// key through the parent from a sibling that can spare one, or otherwise
+  /// merging it with a sibling and the key between them.
+  fn fix_child(tree: &mut Node<K>, i: usize, min: usize) {
+    if tree.children[i].keys.len() >= min {
+      return;
+    }
+    if i > 0 && tree.children[i - 1].keys.len() > min {
+      let (left, right) = tree.children.split_at_mut(i);
+      let (left, child) = (&mut left[i - 1], &mut right[0]);
+      let key = replace(&mut tree.keys[i - 1], left.keys.pop().unwrap());
+      child.keys.insert(0, key);
+      if !left.is_leaf() {
+        child.children.insert(0, left.children.pop().unwrap());
+      }
+    } else if i + 1 < tree.children.len() && tree.children[i + 1].keys.len() > min {
+      let (left, right) = tree.children.split_at_mut(i + 1);
+      let (child, right) = (&mut left[i], &mut right[0]);
+      let key = replace(&mut tree.keys[i], right.keys.remove(0));
+      child.keys.push(key);
+      if !right.is_leaf() {
+        child.children.push(right.children.remove(0));
+      }
+    } else {
+      let j = if i > 0 { i - 1 } else { i };
+      let key = tree.keys.remove(j);
+      let right = tree.children.remove(j + 1);
+      let left = &mut tree.children[j];
+      left.keys.push(key);
+      left.keys.extend(right.keys);
+      left.children.extend(right.children);
+    }
+  }
+  fn remove_max(tree: &mut Node<K>, min: usize) -> K {
+    if tree.is_leaf() {
+      return tree.keys.pop().unwrap();
+    }
+    let i = tree.children.len() - 1;
+    let key = BTree::remove_max(&mut tree.children[i], min);
+    BTree::fix_child(tree, i, min);
+    key
+  }
+  fn _delete<Q>(key: &Q, tree: &mut Node<K>, min: usize) -> Option<K>
+  where
+    K: Borrow<Q>,
+    Q: Ord + ?Sized,
+  {
+    let (i, removed) = match tree.search(key) {
+      Ok(i) if tree.is_leaf() => return Some(tree.keys.remove(i)),
+      Err(_) if tree.is_leaf() => return None,
+      // An inner key is replaced by its predecessor.
+      Ok(i) => {
+        let predecessor = BTree::remove_max(&mut tree.children[i], min);
+        (i, replace(&mut tree.keys[i], predecessor))
+      }
+      Err(i) => (i, BTree::_delete(key, &mut tree.children[i], min)?),
+    };
+    BTree::fix_child(tree, i, min);
+    Some(removed)
+  }
+  fn _find<'a, Q>(key: &Q, tree: &'a Node<K>) -> Option<&'a K>
+  where
+    K: Borrow<Q>,
+    Q: Ord + ?Sized,
+  {
+    let mut tree = tree;
+    loop {
+      match tree.search(key) {
+        Ok(i) => return Some(&tree.keys[i]),
+        Err(_) if tree.is_leaf() => return None,
+        Err(i) => tree = &tree.children[i],
+      }
+    }
+  }
+
+  pub fn len(&self) -> usize {
+    self.len
+  }
+  pub fn is_empty(&self) -> bool {
+    self.len == 0
+  }
+  pub fn order(&self) -> usize {
+    self.order
+  }
+  /// Returns the number of nodes on a root-to-leaf path, all of which have the
+  /// same length.
+  pub fn depth(&self) -> usize {
+    let mut depth = 1;
+    let mut tree = &self.root;
+    while let Some(child) = tree.children.first() {
+      depth += 1;
+      tree = child;
+    }
+    depth
+  }
+  /// Returns an iterator over the keys in ascending order.
+  pub fn iter(&self) -> Iter<'_, K> {
+    Iter {
+      range: Range::new::<K, _>(&self.root, ..),
+      remaining: self.len,
+    }
+  }
+  /// Returns an iterator over the keys within `range` in ascending order.
+  pub fn range<Q, R>(&self, range: R) -> Range<'_, K>
+  where
+    K: Borrow<Q>,
+    Q: Ord + ?Sized,
+    R: RangeBounds<Q>,
+  {
+    Range::new(&self.root, range)
+  }
+  #[cfg(test)]
+  fn _print(tree: &Node<K>) -> String {
+    let keys: Vec<String> = tree.keys.iter().map(|k| format!("{:?}", k)).collect();
+    let mut message = format!("[{}]", keys.join(","));
+    if !tree.is_leaf() {
+      let children: Vec<String> = tree.children.iter().map(BTree::_print).collect();
+      message = format!("{}({})", message, children.concat());
+    }
+    message
+  }
+  #[cfg(test)]
+  pub fn print(&self) -> String {
+    BTree::_print(&self.root)
+  }
+  /// Returns the depth of `tree`, or `None` if any node below it holds the wrong
+  /// number of keys or children or has leaves at different depths.
+  #[cfg(test)]
+  fn _check(&self, tree: &Node<K>) -> Option<usize> {
+    if tree.keys.len() >= self.order
+      || (!tree.is_leaf() && tree.children.len() != tree.keys.len() + 1)
+    {
+      return None;
+    }
+    let mut depths = tree.children.iter().map(|c| {
+      if c.keys.len() < self.min_keys() {
+        None
+      } else {
+        self._check(c)
+      }
+    });
+    match depths.next() {
+      None => Some(1),
+      Some(depth) => {
+        let depth = depth?;
+        depths.all(|d| d == Some(depth)).then_some(depth + 1)
+      }
+    }
+  }
+  #[cfg(test)]
+  pub fn invalid_structure(&self) -> bool {
+    self._check(&self.root).is_none() || self.iter().count() != self.len
+  }
+  #[cfg(test)]
+  pub fn invalid_key(&self) -> bool {
+    unsorted(self.iter())
+  }
+}
+
+impl<K: Ord + Debug> BST<K> for BTree<K> {
+  fn insert(&mut self, key: K) -> bool {
+    if !BTree::_insert(key, &mut self.root, self.order) {
+      return false;
+    }
+    if self.root.keys.len() >= self.order {
+      let root = replace(&mut self.root, Node::new());
+      self.root.children.push(root);
+      BTree::split_child(&mut self.root, 0);
+    }
+    self.len += 1;
+    true
+  }
+  fn find<Q>(&mut self, key: &Q) -> bool
+  where
+    K: Borrow<Q>,
+    Q: Ord + ?Sized,
+  {
+    BTree::_find(key, &self.root).is_some()
+  }
+  fn delete<Q>(&mut self, key: &Q) -> Option<K>
+  where
+    K: Borrow<Q>,
+    Q: Ord + ?Sized,
+  {
+    let min = self.min_keys();
+    let removed = BTree::_delete(key, &mut self.root, min)?;
+    if self.root.keys.is_empty() && !self.root.is_leaf() {
+      self.root = self.root.children.pop().unwrap();
+    }
+    self.len -= 1;
+    Some(removed)
+  }
+}
+
+/// An in-order iterator over a sub-range of the keys of a `BTree`.
+pub struct Range<'a, K: Ord> {
+  // Each entry is a node and the index of the next key to yield from it once
+  // the entries above it are exhausted.
+  front: Vec<(&'a Node<K>, usize)>,
+  back: Vec<(&'a Node<K>, usize)>,
+}
+
+impl<'a, K: Ord> Range<'a, K> {
+  fn new<Q, R>(root: &'a Node<K>, range: R) -> Range<'a, K>
+  where
+    K: Borrow<Q>,
+    Q: Ord + ?Sized,
+    R: RangeBounds<Q>,
+  {
+    let mut front = Vec::new();
+    let mut tree = root;
+    loop {
+      let i = tree.keys.partition_point(|k| !above_start(&range, k));
+      if i < tree.keys.len() {
+        front.push((tree, i));
+      }
+      match tree.children.get(i) {
+        None => break,
+        Some(child) => tree = child,
+      }
+    }
+    let mut back = Vec::new();
+    let mut tree = root;
+    loop {
+      let i = tree.keys.partition_point(|k| below_end(&range, k));
+      if i > 0 {
+        back.push((tree, i - 1));
+      }
+      match tree.children.get(i) {
+        None => break,
+        Some(child) => tree = child,
+      }
+    }
+    Range { front, back }
+  }
+  fn push_front_spine(&mut self, mut tree: &'a Node<K>) {
+    loop {
+      self.front.push((tree, 0));
+      match tree.children.first() {
+        None => break,
+        Some(child) => tree = child,
+      }
+    }
+  }
+  fn push_back_spine(&mut self, mut tree: &'a Node<K>) {
+    loop {
+      self.back.push((tree, tree.keys.len() - 1));
+      match tree.children.last() {
+        None => break,
+        Some(child) => tree = child,
+      }
+    }
+  }
+  fn crossed(&self) -> bool {
+    match (self.front.last(), self.back.last()) {
+      (Some((f, i)), Some((b, j))) => f.keys[*i] > b.keys[*j],
+      _ => true,
+    }
+  }
+}
+
+impl<'a, K: Ord> Iterator for Range<'a, K> {
+  type Item = &'a K;
+
+  fn next(&mut self) -> Option<&'a K> {
+    if self.crossed() {
+      return None;
+    }
+    let (t, i) = self.front.pop().unwrap();
+    if i + 1 < t.keys.len() {
+      self.front.push((t, i + 1));
+    }
+    if let Some(child) = t.children.get(i + 1) {
+      self.push_front_spine(child);
+    }
+    Some(&t.keys[i])
+  }
+}
+
+impl<'a, K: Ord> DoubleEndedIterator for Range<'a, K> {
+  fn next_back(&mut self) -> Option<&'a K> {
+    if self.crossed() {
+      return None;
+    }
+    let (t, i) = self.back.pop().unwrap();
+    if i > 0 {
+      self.back.push((t, i - 1));
+    }
+    if let Some(child) = t.children.get(i) {
+      self.push_back_spine(child);
+    }
+    Some(&t.keys[i])
+  }
+}
+
+/// An in-order iterator over the keys of a `BTree`.
+pub struct Iter<'a, K: Ord> {
+  range: Range<'a, K>,
+  remaining: usize,
+}
+
+impl<'a, K: Ord> Iterator for Iter<'a, K> {
+  type Item = &'a K;
+
+  fn next(&mut self) -> Option<&'a K> {
+    let key = self.range.next()?;
+    self.remaining -= 1;
+    Some(key)
+  }
+  fn size_hint(&self) -> (usize, Option<usize>) {
+    (self.remaining, Some(self.remaining))
+  }
+}
+
+impl<'a, K: Ord> DoubleEndedIterator for Iter<'a, K> {
+  fn next_back(&mut self) -> Option<&'a K> {
+    let key = self.range.next_back()?;
+    self.remaining -= 1;
+    Some(key)
+  }
+}
+
+impl<'a, K: Ord> ExactSizeIterator for Iter<'a, K> {}
+
+impl<'a, K: Ord + Debug> IntoIterator for &'a BTree<K> {
+  type Item = &'a K;
+  type IntoIter = Iter<'a, K>;
+
+  fn into_iter(self) -> Iter<'a, K> {
+    self.iter()
+  }
+}
+
+enum Pending<K: Ord> {
+  Key(K),
+  Node(Node<K>),
+}
+
+/// An owning in-order iterator over the keys of a `BTree`.
+pub struct IntoIter<K: Ord> {
+  // The keys and whole subtrees still to come, in order. A subtree is only
+  // taken apart once it reaches either end.
+  pending: VecDeque<Pending<K>>,
+  remaining: usize,
+}
+
+impl<K: Ord> IntoIter<K> {
+  /// Takes `node` apart into its children and keys, interleaved in order.
+  fn unfold(node: Node<K>) -> Vec<Pending<K>> {
+    let mut children = node.children.into_iter().map(Pending::Node);
+    let mut items = Vec::with_capacity(2 * node.keys.len() + 1);
+    items.extend(children.next());
+    for key in node.keys {
+      items.push(Pending::Key(key));
+      items.extend(children.next());
+    }
+    items
+  }
+}
+
+impl<K: Ord> Iterator for IntoIter<K> {
+  type Item = K;
+
+  fn next(&mut self) -> Option<K> {
+    loop {
+      match self.pending.pop_front()? {
+        Pending::Key(key) => {
+          self.remaining -= 1;
+          return Some(key);
+        }
+        Pending::Node(node) => {
+          for item in IntoIter::unfold(node).into_iter().rev() {
+            self.pending.push_front(item);
+          }
+        }
+      }
+    }
+  }
+  fn size_hint(&self) -> (usize, Option<usize>) {
+    (self.remaining, Some(self.remaining))
+  }
+}
+
+impl<K: Ord> DoubleEndedIterator for IntoIter<K> {
+  fn next_back(&mut self) -> Option<K> {
+    loop {
+      match self.pending.pop_back()? {
+        Pending::Key(key) => {
+          self.remaining -= 1;
+          return Some(key);
+        }
+        Pending::Node(node) => self.pending.extend(IntoIter::unfold(node)),
+      }
+    }
+  }
+}
+
+impl<K: Ord> ExactSizeIterator for IntoIter<K> {}
+
+impl<K: Ord + Debug> IntoIterator for BTree<K> {
+  type Item = K;
+  type IntoIter = IntoIter<K>;
+
+  fn into_iter(self) -> IntoIter<K> {
+    IntoIter {
+      pending: VecDeque::from(vec![Pending::Node(self.root)]),
+      remaining: self.len,
+    }
+  }
+}
+
+/// Builds a tree of order 64 by inserting the keys one at a time.
+impl<K: Ord + Debug> FromIterator<K> for BTree<K> {
+  fn from_iter<I: IntoIterator<Item = K>>(iter: I) -> BTree<K> {
+    let mut tree = BTree::new(64);
+    tree.extend(iter);
+    tree
+  }
+}
+
+impl<K: Ord + Debug> Extend<K> for BTree<K> {
+  fn extend<I: IntoIterator<Item = K>>(&mut self, iter: I) {
+    for key in iter {
+      self.insert(key);
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::BTree;
+  use super::BST;
+  use crate::conformance;
+  use assert_str::assert_str_eq;
+  use std::ops::Bound;
+
+  #[test]
+  fn test_insert() {
+    let mut tree: BTree<u64> = BTree::new(3);
+    assert!(tree.insert(10));
+    assert!(tree.insert(20));
+    assert!(!tree.insert(10));
+    assert_str_eq!(tree.print(), "[10,20]");
+    // split the root
+    assert!(tree.insert(30));
+    assert_str_eq!(tree.print(), "[20]([10][30])");
+    assert!(tree.insert(40));
+    assert!(tree.insert(50));
+    assert_str_eq!(tree.print(), "[20,40]([10][30][50])");
+    // split a leaf, then the root
+    assert!(tree.insert(60));
+    assert!(tree.insert(70));
+    assert_str_eq!(tree.print(), "[40]([20]([10][30])[60]([50][70]))");
+    assert!(!tree.invalid_structure());
+  }
+  #[test]
+  fn test_delete() {
+    let mut tree: BTree<u64> = BTree::new(3);
+    for key in [10, 20, 30, 40, 50, 60, 70].iter() {
+      tree.insert(*key);
+    }
+    assert_eq!(tree.delete(&35), None);
+    // merge leaves, then the root's children
+    assert_eq!(tree.delete(&10), Some(10));
+    assert_str_eq!(tree.print(), "[40,60]([20,30][50][70])");
+    // borrow from the left sibling
+    assert_eq!(tree.delete(&50), Some(50));
+    assert_str_eq!(tree.print(), "[30,60]([20][40][70])");
+    // replace an inner key by its predecessor
+    assert_eq!(tree.delete(&60), Some(60));
+    assert_str_eq!(tree.print(), "[40]([20,30][70])");
+    assert_eq!(tree.delete(&70), Some(70));
+    assert_str_eq!(tree.print(), "[30]([20][40])");
+    // borrow from the right sibling
+    assert!(tree.insert(50));
+    assert_eq!(tree.delete(&20), Some(20));
+    assert_str_eq!(tree.print(), "[40]([30][50])");
+    assert_eq!(tree.delete(&40), Some(40));
+    assert_str_eq!(tree.print(), "[30,50]");
+    assert_eq!(tree.len(), 2);
+    assert!(!tree.invalid_structure());
+  }
+  #[test]
+  fn test_find_borrowed() {
+    conformance::find_borrowed(BTree::new(4));
+    let mut tree: BTree<String> = BTree::new(4);
+    tree.insert(String::from("apple"));
+    tree.insert(String::from("cherry"));
+    assert_eq!(
+      tree
+        .range::<str, _>((Bound::Included("b"), Bound::Unbounded))
+        .count(),
+      1
+    );
+  }
+  #[test]
+  fn test_into_iter() {
+    let mut tree: BTree<u64> = BTree::new(3);
+    for key in [10, 50, 5, 200, 400, 100].iter() {
+      tree.insert(*key);
+    }
+    let mut iter = tree.into_iter();
+    assert_eq!(iter.len(), 6);
+    assert_eq!(iter.next_back(), Some(400));
+    let keys: Vec<u64> = iter.collect();
+    assert_eq!(keys, vec![5, 10, 50, 100, 200]);
+    let mut tree: BTree<u64> = BTree::new(4);
+    for i in 0..10_000 {
+      tree.insert(i * 389 % 10_000);
+    }
+    let mut iter = tree.into_iter();
+    for i in 0..2_000 {
+      assert_eq!(iter.next(), Some(i));
+      assert_eq!(iter.next_back(), Some(9_999 - i));
+      assert_eq!(iter.len(), 10_000 - 2 * (i as usize + 1));
+    }
+    assert!(iter.rev().eq((2_000..8_000).rev()));
+  }
+  #[test]
+  fn test_from_iter_extend() {
+    let mut tree: BTree<u64> = vec![50, 10, 400, 5, 10, 200, 50].into_iter().collect();
+    assert_eq!(tree.len(), 5);
+    assert_eq!(tree.order(), 64);
+    assert!(!tree.invalid_structure());
+    tree.extend(vec![100, 5, 300]);
+    let keys: Vec<u64> = tree.iter().cloned().collect();
+    assert_eq!(keys, vec![5, 10, 50, 100, 200, 300, 400]);
+    assert!(!tree.invalid_structure());
+  }
+  #[test]
+  fn test_iter_range() {
+    let mut tree: BTree<u64> = BTree::new(4);
+    assert_eq!(tree.iter().next(), None);
+    assert_eq!(tree.range(..).next(), None);
+    for i in 0..500 {
+      tree.insert(i * 389 % 1000);
+    }
+    let mut sorted: Vec<u64> = (0..500).map(|i| i * 389 % 1000).collect();
+    sorted.sort();
+    assert!(tree.iter().eq(sorted.iter()));
+    assert!(tree.iter().rev().eq(sorted.iter().rev()));
+    assert_eq!(tree.iter().len(), 500);
+    for (lo, hi) in [(0, 1000), (100, 200), (101, 102), (500, 400), (999, 1000)].iter() {
+      let expected: Vec<&u64> = sorted.iter().filter(|k| lo <= *k && *k < hi).collect();
+      let forward: Vec<&u64> = tree.range(lo..hi).collect();
+      let mut backward: Vec<&u64> = tree.range(lo..hi).rev().collect();
+      backward.reverse();
+      assert_eq!(forward, expected);
+      assert_eq!(backward, expected);
+    }
+    assert!(tree.range(..=3).eq(sorted.iter().take_while(|k| **k <= 3)));
+    let mut range = tree.range(100..200);
+    let expected: Vec<&u64> = sorted.iter().filter(|k| (100..200).contains(*k)).collect();
+    // Alternating ends meet in the middle without repeating a key.
+    let mut seen = Vec::new();
+    while let Some(key) = range.next() {
+      seen.push(key);
+      seen.extend(range.next_back());
+    }
+    seen.sort();
+    assert_eq!(seen, expected);
+  }
+  #[test]
+  #[should_panic]
+  fn test_order_too_small() {
+    let _tree: BTree<u64> = BTree::new(2);
+  }
+  #[test]
+  fn test_invariants() {
+    for order in 3..8 {
+      let tree = conformance::invariants(BTree::new(order), |tree| {
+        assert!(!tree.invalid_structure());
+        assert!(!tree.invalid_key());
+      });
+      assert!(tree.is_empty());
+    }
+  }
+  #[test]
+  fn test_sorted_insert_large() {
+    let tree = conformance::sorted_insert_large(BTree::new(64), |tree| {
+      // Every node but the root holds at least 31 keys.
+      assert!(tree.depth() <= 4);
+      assert!(!tree.invalid_structure());
+    });
+    assert!(tree.iter().cloned().eq((1..100_000).step_by(2)));
+  }
+}
@@ -0,0 +1,291 @@
+use std::borrow::Borrow;
+use std::collections::VecDeque;
+use std::ops::RangeBounds;
+
+use super::{above_start, below_end};
+
+/// The parts of a binary tree node that the shared in-order walks need.
+pub(crate) trait TreeNode: Sized {
+  type Key;
+  fn key(&self) -> &Self::Key;
+  fn lchild(&self) -> &Option<Box<Self>>;
+  fn rchild(&self) -> &Option<Box<Self>>;
+  fn lchild_mut(&mut self) -> &mut Option<Box<Self>>;
+  fn rchild_mut(&mut self) -> &mut Option<Box<Self>>;
+}
+
+/// A node of a key-value map, which the owning walk takes apart.
+pub(crate) trait EntryNode: TreeNode {
+  type Value;
+  /// Hands anything still pending for the children down to them.
+  fn push(&mut self);
+  fn into_entry(self) -> (Self::Key, Self::Value);
+}
+
+/// Drops a tree without recursing, which would overflow the stack on deep trees.
+pub(crate) fn drop_tree<N: TreeNode>(root: Option<Box<N>>) {
+  let mut stack: Vec<Box<N>> = root.into_iter().collect();
+  while let Some(mut t) = stack.pop() {
+    stack.extend(t.lchild_mut().take());
+    stack.extend(t.rchild_mut().take());
+  }
+}
+
+/// Prints a tree as `[label(left)(right)]`, where `label` describes a single
+/// node and empty subtrees print as nothing.
+#[cfg(test)]
+pub(crate) fn print_tree<N: TreeNode>(tree: &N, label: &impl Fn(&N) -> String) -> String {
+  let mut message = format!("[{}(", label(tree));
+  if let Some(l) = tree.lchild() {
+    message.push_str(&print_tree(l.as_ref(), label));
+  }
+  message.push_str(")(");
+  if let Some(r) = tree.rchild() {
+    message.push_str(&print_tree(r.as_ref(), label));
+  }
+  message.push_str(")]");
+  message
+}
+
+/// A double-ended in-order walk over the nodes of a tree of known size.
+pub(crate) struct InOrder<'a, N> {
+  front: Vec<&'a N>,
+  back: Vec<&'a N>,
+  remaining: usize,
+}
+
+impl<'a, N: TreeNode> InOrder<'a, N> {
+  pub(crate) fn new(root: &'a Option<Box<N>>, len: usize) -> InOrder<'a, N> {
+    let mut walk = InOrder {
+      front: Vec::new(),
+      back: Vec::new(),
+      remaining: len,
+    };
+    walk.push_front_spine(root);
+    walk.push_back_spine(root);
+    walk
+  }
+  fn push_front_spine(&mut self, mut tree: &'a Option<Box<N>>) {
+    while let Some(t) = tree {
+      self.front.push(t);
+      tree = t.lchild();
+    }
+  }
+  fn push_back_spine(&mut self, mut tree: &'a Option<Box<N>>) {
+    while let Some(t) = tree {
+      self.back.push(t);
+      tree = t.rchild();
+    }
+  }
+}
+
+impl<'a, N: TreeNode> Iterator for InOrder<'a, N> {
+  type Item = &'a N;
+
+  fn next(&mut self) -> Option<&'a N> {
+    if self.remaining == 0 {
+      return None;
+    }
+    let t = self.front.pop().unwrap();
+    self.push_front_spine(t.rchild());
+    self.remaining -= 1;
+    Some(t)
+  }
+  fn size_hint(&self) -> (usize, Option<usize>) {
+    (self.remaining, Some(self.remaining))
+  }
+}
+
+impl<'a, N: TreeNode> DoubleEndedIterator for InOrder<'a, N> {
+  fn next_back(&mut self) -> Option<&'a N> {
+    if self.remaining == 0 {
+      return None;
+    }
+    let t = self.back.pop().unwrap();
+    self.push_back_spine(t.lchild());
+    self.remaining -= 1;
+    Some(t)
+  }
+}
+
+/// A double-ended in-order walk over the nodes whose keys lie in a range.
+pub(crate) struct RangeInOrder<'a, N> {
+  front: Vec<&'a N>,
+  back: Vec<&'a N>,
+}
+
+impl<'a, N: TreeNode> RangeInOrder<'a, N>
+where
+  N::Key: Ord,
+{
+  pub(crate) fn new<Q, R>(root: &'a Option<Box<N>>, range: R) -> RangeInOrder<'a, N>
+  where
+    N::Key: Borrow<Q>,
+    Q: Ord + ?Sized,
+    R: RangeBounds<Q>,
+  {
+    let mut front = Vec::new();
+    let mut tree = root;
+    while let Some(t) = tree {
+      if above_start(&range, t.key()) {
+        front.push(t.as_ref());
+        tree = t.lchild();
+      } else {
+        tree = t.rchild();
+      }
+    }
+    let mut back = Vec::new();
+    let mut tree = root;
+    while let Some(t) = tree {
+      if below_end(&range, t.key()) {
+        back.push(t.as_ref());
+        tree = t.rchild();
+      } else {
+        tree = t.lchild();
+      }
+    }
+    RangeInOrder { front, back }
+  }
+  /// The tops of both stacks are the next nodes from either end, so the range
+  /// is exhausted as soon as they cross.
+  fn exhausted(&self) -> bool {
+    match (self.front.last(), self.back.last()) {
+      (Some(f), Some(b)) => f.key() > b.key(),
+      _ => true,
+    }
+  }
+}
+
+impl<'a, N: TreeNode> Iterator for RangeInOrder<'a, N>
+where
+  N::Key: Ord,
+{
+  type Item = &'a N;
+
+  fn next(&mut self) -> Option<&'a N> {
+    if self.exhausted() {
+      return None;
+    }
+    let t = self.front.pop().unwrap();
+    let mut tree = t.rchild();
+    while let Some(r) = tree {
+      self.front.push(r);
+      tree = r.lchild();
+    }
+    Some(t)
+  }
+}
+
+impl<'a, N: TreeNode> DoubleEndedIterator for RangeInOrder<'a, N>
+where
+  N::Key: Ord,
+{
+  fn next_back(&mut self) -> Option<&'a N> {
+    if self.exhausted() {
+      return None;
+    }
+    let t = self.back.pop().unwrap();
+    let mut tree = t.lchild();
+    while let Some(l) = tree {
+      self.back.push(l);
+      tree = l.rchild();
+    }
+    Some(t)
+  }
+}
+
+/// A double-ended in-order walk that takes a tree apart as it goes, so that
+/// entries are moved out one at a time instead of being collected up front.
+///
+/// The entries still to come are those of `front` from the top down, each
+/// followed by the subtree stored with it, and then those of `back` from the
+/// bottom up, each preceded by the subtree stored with it. Both are stacks
+/// whose top is the back of the deque; once one runs dry the other is taken
+/// from its bottom, which the deque makes O(1).
+pub(crate) struct IntoInOrder<N: TreeNode> {
+  // Each node with its right subtree, smallest node on top.
+  front: VecDeque<(Box<N>, Option<Box<N>>)>,
+  // Each node with its left subtree, largest node on top.
+  back: VecDeque<(Box<N>, Option<Box<N>>)>,
+  remaining: usize,
+}
+
+impl<N: EntryNode> IntoInOrder<N> {
+  pub(crate) fn new(root: Option<Box<N>>, len: usize) -> IntoInOrder<N> {
+    let mut walk = IntoInOrder {
+      front: VecDeque::new(),
+      back: VecDeque::new(),
+      remaining: len,
+    };
+    walk.push_front_spine(root);
+    walk
+  }
+  fn push_front_spine(&mut self, mut tree: Option<Box<N>>) {
+    while let Some(mut t) = tree {
+      t.push();
+      tree = t.lchild_mut().take();
+      let r = t.rchild_mut().take();
+      self.front.push_back((t, r));
+    }
+  }
+  fn push_back_spine(&mut self, mut tree: Option<Box<N>>) {
+    while let Some(mut t) = tree {
+      t.push();
+      tree = t.rchild_mut().take();
+      let l = t.lchild_mut().take();
+      self.back.push_back((t, l));
+    }
+  }
+}
+
+impl<N: EntryNode> Iterator for IntoInOrder<N> {
+  type Item = (N::Key, N::Value);
+
+  fn next(&mut self) -> Option<(N::Key, N::Value)> {
+    if self.remaining == 0 {
+      return None;
+    }
+    self.remaining -= 1;
+    if self.front.is_empty() {
+      // The smallest entry left is the bottom of `back` or in its subtree.
+      match self.back[0].1.take() {
+        None => return Some(self.back.pop_front().unwrap().0.into_entry()),
+        Some(l) => self.push_front_spine(Some(l)),
+      }
+    }
+    let (t, r) = self.front.pop_back().unwrap();
+    self.push_front_spine(r);
+    Some(t.into_entry())
+  }
+  fn size_hint(&self) -> (usize, Option<usize>) {
+    (self.remaining, Some(self.remaining))
+  }
+}
+
+impl<N: EntryNode> DoubleEndedIterator for IntoInOrder<N> {
+  fn next_back(&mut self) -> Option<(N::Key, N::Value)> {
+    if self.remaining == 0 {
+      return None;
+    }
+    self.remaining -= 1;
+    if self.back.is_empty() {
+      // The largest entry left is the bottom of `front` or in its subtree.
+      match self.front[0].1.take() {
+        None => return Some(self.front.pop_front().unwrap().0.into_entry()),
+        Some(r) => self.push_back_spine(Some(r)),
+      }
+    }
+    let (t, l) = self.back.pop_back().unwrap();
+    self.push_back_spine(l);
+    Some(t.into_entry())
+  }
+}
+
+impl<N: TreeNode> Drop for IntoInOrder<N> {
+  fn drop(&mut self) {
+    for (t, rest) in self.front.drain(..).chain(self.back.drain(..)) {
+      drop_tree(Some(t));
+      drop_tree(rest);
+    }
+  }
+}
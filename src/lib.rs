@@ -1,8 +1,338 @@
+use std::borrow::Borrow;
+use std::convert::TryFrom;
+use std::ops::{Add, Bound, Mul, RangeBounds};
+
+pub mod aa_tree;
+pub mod avl;
+pub mod btree;
+mod iter;
+pub mod llrb;
+pub mod red_black;
+pub mod scapegoat;
 pub mod splay;
 pub mod treap;
+pub mod weight_balanced;
+pub mod zip;
 
 pub trait BST<K> {
-    fn insert(&mut self, key: K);
-    fn find(&mut self, key: K) -> bool;
-    fn delete(&mut self, key: K);
+    /// Inserts a key, returning `true` if it was not already present.
+    fn insert(&mut self, key: K) -> bool;
+    fn find<Q>(&mut self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized;
+    /// Deletes a key, returning the removed key if it was present.
+    fn delete<Q>(&mut self, key: &Q) -> Option<K>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized;
+}
+
+/// An associative operation with an identity, used by `TreapMap` and
+/// `SplayMap` to keep an aggregate of the values of every subtree so that
+/// `fold` can summarize a key range in O(log n). The unit type keeps no
+/// aggregate and is the default.
+pub trait Monoid<V> {
+    type Summary: Clone;
+    /// Returns the aggregate of no values.
+    fn identity() -> Self::Summary;
+    /// Returns the aggregate of a single value.
+    fn lift(value: &V) -> Self::Summary;
+    /// Combines the aggregates of two adjacent runs of values, `left` first.
+    fn combine(left: &Self::Summary, right: &Self::Summary) -> Self::Summary;
+}
+
+impl<V> Monoid<V> for () {
+    type Summary = ();
+    fn identity() {}
+    fn lift(_: &V) {}
+    fn combine(_: &(), _: &()) {}
+}
+
+/// Sums the values, starting from `V::default()`.
+pub struct Sum;
+
+impl<V: Clone + Default + Add<Output = V>> Monoid<V> for Sum {
+    type Summary = V;
+    fn identity() -> V {
+        V::default()
+    }
+    fn lift(value: &V) -> V {
+        value.clone()
+    }
+    fn combine(left: &V, right: &V) -> V {
+        left.clone() + right.clone()
+    }
+}
+
+/// Takes the smallest value, or `None` for no values.
+pub struct Min;
+
+impl<V: Clone + Ord> Monoid<V> for Min {
+    type Summary = Option<V>;
+    fn identity() -> Option<V> {
+        None
+    }
+    fn lift(value: &V) -> Option<V> {
+        Some(value.clone())
+    }
+    fn combine(left: &Option<V>, right: &Option<V>) -> Option<V> {
+        match (left, right) {
+            (Some(l), Some(r)) => Some(l.min(r).clone()),
+            (l, None) => l.clone(),
+            (None, r) => r.clone(),
+        }
+    }
+}
+
+/// Takes the largest value, or `None` for no values.
+pub struct Max;
+
+impl<V: Clone + Ord> Monoid<V> for Max {
+    type Summary = Option<V>;
+    fn identity() -> Option<V> {
+        None
+    }
+    fn lift(value: &V) -> Option<V> {
+        Some(value.clone())
+    }
+    fn combine(left: &Option<V>, right: &Option<V>) -> Option<V> {
+        match (left, right) {
+            (Some(l), Some(r)) => Some(l.max(r).clone()),
+            (l, None) => l.clone(),
+            (None, r) => r.clone(),
+        }
+    }
+}
+
+/// An update applied to every value in a key range of a `TreapMap` or
+/// `SplayMap` at once. The maps tag the roots of the affected subtrees and push
+/// the tags down lazily, so the update also has to be applicable to the
+/// aggregate `M` of a subtree directly. The unit type updates nothing and is
+/// the default.
+pub trait Action<V, M: Monoid<V>>: Clone {
+    /// Applies the update to a single value.
+    fn apply(&self, value: &mut V);
+    /// Returns the aggregate of `len` values after the update, given their
+    /// aggregate before it.
+    fn apply_summary(&self, summary: &M::Summary, len: usize) -> M::Summary;
+    /// Returns the update that applies `inner` and then `self`.
+    fn compose(&self, inner: &Self) -> Self;
+}
+
+impl<V, M: Monoid<V>> Action<V, M> for () {
+    fn apply(&self, _: &mut V) {}
+    fn apply_summary(&self, summary: &M::Summary, _: usize) -> M::Summary {
+        summary.clone()
+    }
+    fn compose(&self, _: &()) {}
+}
+
+/// Adds to or overwrites every value in a range. It acts on maps without an
+/// aggregate and on `Sum`, `Min` and `Max` aggregates.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Update<V> {
+    Add(V),
+    Assign(V),
+}
+
+impl<V: Clone + Add<Output = V>> Update<V> {
+    fn apply_value(&self, value: &mut V) {
+        *value = match self {
+            Update::Add(delta) => value.clone() + delta.clone(),
+            Update::Assign(x) => x.clone(),
+        };
+    }
+    fn then(&self, inner: &Update<V>) -> Update<V> {
+        match (self, inner) {
+            (Update::Assign(x), _) => Update::Assign(x.clone()),
+            (Update::Add(d), Update::Add(e)) => Update::Add(e.clone() + d.clone()),
+            (Update::Add(d), Update::Assign(x)) => Update::Assign(x.clone() + d.clone()),
+        }
+    }
+}
+
+impl<V: Clone + Add<Output = V>> Action<V, ()> for Update<V> {
+    fn apply(&self, value: &mut V) {
+        self.apply_value(value);
+    }
+    fn apply_summary(&self, _: &(), _: usize) {}
+    fn compose(&self, inner: &Update<V>) -> Update<V> {
+        self.then(inner)
+    }
+}
+
+impl<V> Action<V, Sum> for Update<V>
+where
+    V: Clone + Default + Add<Output = V> + Mul<Output = V> + TryFrom<usize>,
+{
+    fn apply(&self, value: &mut V) {
+        self.apply_value(value);
+    }
+    fn apply_summary(&self, summary: &V, len: usize) -> V {
+        let len = V::try_from(len).unwrap_or_else(|_| panic!("apply_summary: length overflows"));
+        match self {
+            Update::Add(delta) => summary.clone() + delta.clone() * len,
+            Update::Assign(x) => x.clone() * len,
+        }
+    }
+    fn compose(&self, inner: &Update<V>) -> Update<V> {
+        self.then(inner)
+    }
+}
+
+impl<V: Clone + Ord + Add<Output = V>> Action<V, Min> for Update<V> {
+    fn apply(&self, value: &mut V) {
+        self.apply_value(value);
+    }
+    fn apply_summary(&self, summary: &Option<V>, _: usize) -> Option<V> {
+        summary.as_ref().map(|min| {
+            let mut min = min.clone();
+            self.apply_value(&mut min);
+            min
+        })
+    }
+    fn compose(&self, inner: &Update<V>) -> Update<V> {
+        self.then(inner)
+    }
+}
+
+impl<V: Clone + Ord + Add<Output = V>> Action<V, Max> for Update<V> {
+    fn apply(&self, value: &mut V) {
+        self.apply_value(value);
+    }
+    fn apply_summary(&self, summary: &Option<V>, _: usize) -> Option<V> {
+        summary.as_ref().map(|max| {
+            let mut max = max.clone();
+            self.apply_value(&mut max);
+            max
+        })
+    }
+    fn compose(&self, inner: &Update<V>) -> Update<V> {
+        self.then(inner)
+    }
+}
+
+/// Collects `iter` into a vector sorted by key, keeping the last entry among equal keys.
+pub(crate) fn sorted_entries<K, V, I>(iter: I) -> Vec<(K, V)>
+where
+    K: Ord,
+    I: IntoIterator<Item = (K, V)>,
+{
+    let mut entries: Vec<(K, V)> = iter.into_iter().collect();
+    entries.sort_by(|a, b| a.0.cmp(&b.0));
+    let mut sorted: Vec<(K, V)> = Vec::with_capacity(entries.len());
+    for entry in entries {
+        match sorted.last_mut() {
+            Some(last) if last.0 == entry.0 => *last = entry,
+            _ => sorted.push(entry),
+        }
+    }
+    sorted
+}
+
+/// Returns whether `key` is not below the start of `range`.
+pub(crate) fn above_start<K, Q, R>(range: &R, key: &K) -> bool
+where
+    K: Borrow<Q>,
+    Q: Ord + ?Sized,
+    R: RangeBounds<Q>,
+{
+    match range.start_bound() {
+        Bound::Included(start) => key.borrow() >= start,
+        Bound::Excluded(start) => key.borrow() > start,
+        Bound::Unbounded => true,
+    }
+}
+
+/// Returns whether `key` is not above the end of `range`.
+pub(crate) fn below_end<K, Q, R>(range: &R, key: &K) -> bool
+where
+    K: Borrow<Q>,
+    Q: Ord + ?Sized,
+    R: RangeBounds<Q>,
+{
+    match range.end_bound() {
+        Bound::Included(end) => key.borrow() <= end,
+        Bound::Excluded(end) => key.borrow() < end,
+        Bound::Unbounded => true,
+    }
+}
+
+/// Returns whether `keys` are not in strictly ascending order.
+#[cfg(test)]
+pub(crate) fn unsorted<'a, K, I>(keys: I) -> bool
+where
+    K: Ord + 'a,
+    I: IntoIterator<Item = &'a K>,
+{
+    let mut keys = keys.into_iter();
+    let mut prev = match keys.next() {
+        Some(key) => key,
+        None => return false,
+    };
+    for key in keys {
+        if prev >= key {
+            return true;
+        }
+        prev = key;
+    }
+    false
+}
+
+/// Checks that every `BST` implementation runs from its own tests. The checks
+/// only go through the trait; the invariants of each structure come in through
+/// `check`, which is called after every change and should panic if the tree is
+/// malformed.
+#[cfg(test)]
+pub(crate) mod conformance {
+    use super::BST;
+
+    /// Looks up and deletes `String` keys through `&str`.
+    pub(crate) fn find_borrowed<T: BST<String>>(mut tree: T) {
+        assert!(tree.insert(String::from("apple")));
+        assert!(tree.insert(String::from("cherry")));
+        assert!(!tree.insert(String::from("apple")));
+        assert!(tree.find("apple"));
+        assert!(!tree.find("banana"));
+        assert_eq!(tree.delete("banana"), None);
+        assert_eq!(tree.delete("apple"), Some(String::from("apple")));
+        assert!(!tree.find("apple"));
+        assert!(tree.find("cherry"));
+    }
+
+    /// Inserts the keys below 1000 and deletes them again, each time in a
+    /// different scrambled order. Returns the emptied tree.
+    pub(crate) fn invariants<T: BST<u64>>(mut tree: T, check: impl Fn(&T)) -> T {
+        for i in 0..1000 {
+            assert!(tree.insert(i * 389 % 1000));
+            check(&tree);
+        }
+        for key in 0..1000 {
+            assert!(tree.find(&key));
+            assert!(!tree.insert(key));
+        }
+        for i in 0..1000 {
+            let key = i * 577 % 1000;
+            assert_eq!(tree.delete(&key), Some(key));
+            assert_eq!(tree.delete(&key), None);
+            check(&tree);
+        }
+        tree
+    }
+
+    /// Inserts 100_000 keys in ascending order, the worst case for an
+    /// unbalanced tree, and then deletes the even ones, calling `check` after
+    /// each pass. Returns the tree, which holds the odd keys.
+    pub(crate) fn sorted_insert_large<T: BST<u64>>(mut tree: T, check: impl Fn(&T)) -> T {
+        for key in 0..100_000 {
+            assert!(tree.insert(key));
+        }
+        check(&tree);
+        for key in (0..100_000).step_by(2) {
+            assert_eq!(tree.delete(&key), Some(key));
+        }
+        check(&tree);
+        tree
+    }
 }
@@ -0,0 +1,453 @@
+use std::borrow::Borrow;
+use std::cmp::Ordering;
+use std::fmt::Debug;
+use std::mem::{replace, swap};
+
+use super::iter::{InOrder, TreeNode};
+use super::BST;
+#[cfg(test)]
+use super::{iter::print_tree, unsorted};
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Color {
+  Red,
+  Black,
+}
+
+impl Color {
+  fn flip(&mut self) {
+    *self = match self {
+      Color::Red => Color::Black,
+      Color::Black => Color::Red,
+    };
+  }
+}
+
+#[derive(Debug)]
+struct Node<K: Ord> {
+  key: K,
+  color: Color,
+  size: usize,
+  lchild: Option<Box<Node<K>>>,
+  rchild: Option<Box<Node<K>>>,
+}
+
+type Link<K> = Option<Box<Node<K>>>;
+
+/// A left-leaning red-black tree after Sedgewick. Besides the red-black
+/// invariants, a red node is always a left child, which makes the tree a
+/// one-to-one encoding of a 2-3 tree and keeps the update code short.
+pub struct Llrb<K: Ord + Debug> {
+  root: Option<Box<Node<K>>>,
+}
+
+impl<K: Ord + Debug> Llrb<K> {
+  pub fn new() -> Llrb<K> {
+    Llrb { root: None }
+  }
+  fn size(tree: &Option<Box<Node<K>>>) -> usize {
+    tree.as_ref().map_or(0, |t| t.size)
+  }
+  fn update(tree: &mut Node<K>) {
+    tree.size = 1 + Llrb::size(&tree.lchild) + Llrb::size(&tree.rchild);
+  }
+  fn is_red(tree: &Option<Box<Node<K>>>) -> bool {
+    tree.as_ref().is_some_and(|t| t.color == Color::Red)
+  }
+
+  /// Rotates the left child of `tree` up. The new root takes the color of the
+  /// old one, which goes down as a red right child.
+  fn rotate_right(tree: &mut Box<Node<K>>) {
+    let lnode = tree.lchild.as_mut().unwrap();
+    let llnode = lnode.lchild.take();
+    let lrnode = lnode.rchild.take();
+    let mut rnode = replace(&mut tree.lchild, llnode);
+    swap(
+      &mut tree.as_mut().key,
+      &mut rnode.as_deref_mut().unwrap().key,
+    );
+    rnode.as_deref_mut().unwrap().color = Color::Red;
+    rnode.as_deref_mut().unwrap().lchild = lrnode;
+    rnode.as_deref_mut().unwrap().rchild = tree.rchild.take();
+    Llrb::update(rnode.as_deref_mut().unwrap());
+    tree.rchild = rnode;
+    Llrb::update(tree);
+  }
+
+  /// Rotates the right child of `tree` up. The new root takes the color of the
+  /// old one, which goes down as a red left child.
+  fn rotate_left(tree: &mut Box<Node<K>>) {
+    let rnode = tree.rchild.as_mut().unwrap();
+    let rlnode = rnode.lchild.take();
+    let rrnode = rnode.rchild.take();
+    let mut lnode = replace(&mut tree.rchild, rrnode);
+    swap(
+      &mut tree.as_mut().key,
+      &mut lnode.as_deref_mut().unwrap().key,
+    );
+    lnode.as_deref_mut().unwrap().color = Color::Red;
+    lnode.as_deref_mut().unwrap().lchild = tree.lchild.take();
+    lnode.as_deref_mut().unwrap().rchild = rlnode;
+    Llrb::update(lnode.as_deref_mut().unwrap());
+    tree.lchild = lnode;
+    Llrb::update(tree);
+  }
+  /// Flips the colors of `tree` and its children, splitting a temporary 4-node
+  /// or joining three 2-nodes into one.
+  fn flip_colors(tree: &mut Node<K>) {
+    tree.color.flip();
+    if let Some(l) = tree.lchild.as_mut() {
+      l.color.flip();
+    }
+    if let Some(r) = tree.rchild.as_mut() {
+      r.color.flip();
+    }
+  }
+  /// Restores the left-leaning invariants at `tree` on the way up.
+  fn balance(tree: &mut Box<Node<K>>) {
+    Llrb::update(tree);
+    if Llrb::is_red(&tree.rchild) && !Llrb::is_red(&tree.lchild) {
+      Llrb::rotate_left(tree);
+    }
+    if Llrb::is_red(&tree.lchild) && Llrb::is_red(&tree.lchild.as_ref().unwrap().lchild) {
+      Llrb::rotate_right(tree);
+    }
+    if Llrb::is_red(&tree.lchild) && Llrb::is_red(&tree.rchild) {
+      Llrb::flip_colors(tree);
+    }
+  }
+  /// Makes the left child of `tree` or one of its children red, assuming that
+  /// `tree` is red and both `tree.lchild` and its left child are black.
+  fn move_red_left(tree: &mut Box<Node<K>>) {
+    Llrb::flip_colors(tree);
+    if Llrb::is_red(&tree.rchild.as_ref().unwrap().lchild) {
+      Llrb::rotate_right(tree.rchild.as_mut().unwrap());
+      Llrb::rotate_left(tree);
+      Llrb::flip_colors(tree);
+    }
+  }
+  /// Makes the right child of `tree` or one of its children red, assuming that
+  /// `tree` is red and both `tree.rchild` and its left child are black.
+  fn move_red_right(tree: &mut Box<Node<K>>) {
+    Llrb::flip_colors(tree);
+    if Llrb::is_red(&tree.lchild.as_ref().unwrap().lchild) {
+      Llrb::rotate_right(tree);
+      Llrb::flip_colors(tree);
+    }
+  }
+
+  fn _insert(key: K, tree: &mut Link<K>) -> bool {
+    let t = match tree {
+      None => {
+        *tree = Some(Box::new(Node {
+          key,
+          color: Color::Red,
+          size: 1,
+          lchild: None,
+          rchild: None,
+        }));
+        return true;
+      }
+      Some(t) => t,
+    };
+    let inserted = match key.cmp(&t.key) {
+      Ordering::Less => Llrb::_insert(key, &mut t.lchild),
+      Ordering::Greater => Llrb::_insert(key, &mut t.rchild),
+      Ordering::Equal => false,
+    };
+    Llrb::balance(t);
+    inserted
+  }
+  fn _find<'a, Q>(key: &Q, tree: &'a Link<K>) -> Option<&'a Node<K>>
+  where
+    K: Borrow<Q>,
+    Q: Ord + ?Sized,
+  {
+    let mut tree = tree;
+    while let Some(t) = tree {
+      match key.cmp(t.key.borrow()) {
+        Ordering::Less => tree = &t.lchild,
+        Ordering::Greater => tree = &t.rchild,
+        Ordering::Equal => return Some(t),
+      }
+    }
+    None
+  }
+  /// Removes the smallest key from the non-empty `tree`, keeping the node
+  /// visited on the way down red or with a red left child.
+  fn delete_min(tree: &mut Link<K>) -> K {
+    let t = tree.as_mut().unwrap();
+    if t.lchild.is_none() {
+      return tree.take().unwrap().key;
+    }
+    if !Llrb::is_red(&t.lchild) && !Llrb::is_red(&t.lchild.as_ref().unwrap().lchild) {
+      Llrb::move_red_left(t);
+    }
+    let min = Llrb::delete_min(&mut t.lchild);
+    Llrb::balance(t);
+    min
+  }
+  /// Removes `key`, which must be present in `tree`, in the same way as
+  /// `delete_min` keeps a red link on the way down.
+  fn _delete<Q>(key: &Q, tree: &mut Link<K>) -> K
+  where
+    K: Borrow<Q>,
+    Q: Ord + ?Sized,
+  {
+    let t = tree.as_mut().unwrap();
+    if key < t.key.borrow() {
+      if !Llrb::is_red(&t.lchild) && !Llrb::is_red(&t.lchild.as_ref().unwrap().lchild) {
+        Llrb::move_red_left(t);
+      }
+      let removed = Llrb::_delete(key, &mut t.lchild);
+      Llrb::balance(t);
+      return removed;
+    }
+    if Llrb::is_red(&t.lchild) {
+      Llrb::rotate_right(t);
+    }
+    if key == t.key.borrow() && t.rchild.is_none() {
+      return tree.take().unwrap().key;
+    }
+    if !Llrb::is_red(&t.rchild) && !Llrb::is_red(&t.rchild.as_ref().unwrap().lchild) {
+      Llrb::move_red_right(t);
+    }
+    let removed = if key == t.key.borrow() {
+      let min = Llrb::delete_min(&mut t.rchild);
+      replace(&mut t.key, min)
+    } else {
+      Llrb::_delete(key, &mut t.rchild)
+    };
+    Llrb::balance(t);
+    removed
+  }
+
+  pub fn len(&self) -> usize {
+    Llrb::size(&self.root)
+  }
+  pub fn is_empty(&self) -> bool {
+    self.root.is_none()
+  }
+  /// Returns an iterator over the keys in ascending order.
+  pub fn iter(&self) -> Iter<'_, K> {
+    Iter {
+      walk: InOrder::new(&self.root, self.len()),
+    }
+  }
+  /// Returns the number of black nodes on every path from `tree` down to a
+  /// missing child, or `None` if the invariants fail below `tree`.
+  fn black_height(tree: &Option<Box<Node<K>>>) -> Option<usize> {
+    match tree {
+      None => Some(0),
+      Some(t) => {
+        if Llrb::is_red(&t.rchild) || (t.color == Color::Red && Llrb::is_red(&t.lchild)) {
+          return None;
+        }
+        let lheight = Llrb::black_height(&t.lchild)?;
+        let rheight = Llrb::black_height(&t.rchild)?;
+        if lheight != rheight {
+          return None;
+        }
+        Some(lheight + (t.color == Color::Black) as usize)
+      }
+    }
+  }
+  /// Checks the coloring of the tree. Returns `true` if the root is red, a red
+  /// node is a right child or has a red child, or two paths from the root down
+  /// to a missing child pass different numbers of black nodes.
+  pub fn invalid_color(&self) -> bool {
+    Llrb::is_red(&self.root) || Llrb::black_height(&self.root).is_none()
+  }
+  #[cfg(test)]
+  pub fn print(&self) -> String {
+    print_tree(self.root.as_deref().unwrap(), &|t| {
+      let color = match t.color {
+        Color::Red => "R",
+        Color::Black => "B",
+      };
+      format!("{:?}{}", t.key, color)
+    })
+  }
+  #[cfg(test)]
+  fn _invalid_size(tree: &Option<Box<Node<K>>>) -> bool {
+    match tree {
+      None => false,
+      Some(t) => {
+        t.size != 1 + Llrb::size(&t.lchild) + Llrb::size(&t.rchild)
+          || Llrb::_invalid_size(&t.lchild)
+          || Llrb::_invalid_size(&t.rchild)
+      }
+    }
+  }
+  #[cfg(test)]
+  pub fn invalid_size(&self) -> bool {
+    Llrb::_invalid_size(&self.root)
+  }
+  #[cfg(test)]
+  pub fn invalid_key(&self) -> bool {
+    unsorted(self.iter())
+  }
+}
+
+impl<K: Ord + Debug> Default for Llrb<K> {
+  fn default() -> Llrb<K> {
+    Llrb::new()
+  }
+}
+
+impl<K: Ord + Debug> BST<K> for Llrb<K> {
+  fn insert(&mut self, key: K) -> bool {
+    let inserted = Llrb::_insert(key, &mut self.root);
+    self.root.as_mut().unwrap().color = Color::Black;
+    inserted
+  }
+  fn find<Q>(&mut self, key: &Q) -> bool
+  where
+    K: Borrow<Q>,
+    Q: Ord + ?Sized,
+  {
+    Llrb::_find(key, &self.root).is_some()
+  }
+  fn delete<Q>(&mut self, key: &Q) -> Option<K>
+  where
+    K: Borrow<Q>,
+    Q: Ord + ?Sized,
+  {
+    Llrb::_find(key, &self.root)?;
+    let root = self.root.as_mut().unwrap();
+    if !Llrb::is_red(&root.lchild) && !Llrb::is_red(&root.rchild) {
+      root.color = Color::Red;
+    }
+    let removed = Llrb::_delete(key, &mut self.root);
+    if let Some(root) = self.root.as_mut() {
+      root.color = Color::Black;
+    }
+    Some(removed)
+  }
+}
+
+impl<K: Ord> TreeNode for Node<K> {
+  type Key = K;
+  fn key(&self) -> &K {
+    &self.key
+  }
+  fn lchild(&self) -> &Link<K> {
+    &self.lchild
+  }
+  fn rchild(&self) -> &Link<K> {
+    &self.rchild
+  }
+  fn lchild_mut(&mut self) -> &mut Link<K> {
+    &mut self.lchild
+  }
+  fn rchild_mut(&mut self) -> &mut Link<K> {
+    &mut self.rchild
+  }
+}
+
+/// An in-order iterator over the keys of an `Llrb`.
+pub struct Iter<'a, K: Ord> {
+  walk: InOrder<'a, Node<K>>,
+}
+
+impl<'a, K: Ord> Iterator for Iter<'a, K> {
+  type Item = &'a K;
+
+  fn next(&mut self) -> Option<&'a K> {
+    self.walk.next().map(|t| &t.key)
+  }
+  fn size_hint(&self) -> (usize, Option<usize>) {
+    self.walk.size_hint()
+  }
+}
+
+impl<'a, K: Ord> DoubleEndedIterator for Iter<'a, K> {
+  fn next_back(&mut self) -> Option<&'a K> {
+    self.walk.next_back().map(|t| &t.key)
+  }
+}
+
+impl<'a, K: Ord> ExactSizeIterator for Iter<'a, K> {}
+
+impl<'a, K: Ord + Debug> IntoIterator for &'a Llrb<K> {
+  type Item = &'a K;
+  type IntoIter = Iter<'a, K>;
+
+  fn into_iter(self) -> Iter<'a, K> {
+    self.iter()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::Llrb;
+  use super::BST;
+  use crate::conformance;
+  use assert_str::assert_str_eq;
+
+  #[test]
+  fn test_insert() {
+    let mut tree: Llrb<u64> = Llrb::new();
+    assert!(tree.insert(10));
+    assert_str_eq!(tree.print(), "[10B()()]");
+    // a right-leaning red link is rotated left
+    assert!(tree.insert(50));
+    assert_str_eq!(tree.print(), "[50B([10R()()])()]");
+    assert!(!tree.insert(10));
+    // a temporary 4-node is split by a color flip
+    assert!(tree.insert(100));
+    assert_str_eq!(tree.print(), "[50B([10B()()])([100B()()])]");
+    assert!(tree.insert(5));
+    assert!(tree.insert(7));
+    assert_str_eq!(tree.print(), "[50B([7R([5B()()])([10B()()])])([100B()()])]");
+    assert!(!tree.invalid_color());
+    assert!(!tree.invalid_size());
+  }
+  #[test]
+  fn test_find() {
+    let mut tree: Llrb<u64> = Llrb::new();
+    for key in [10, 50, 5, 200, 400, 100].iter() {
+      tree.insert(*key);
+    }
+    assert!(tree.find(&5));
+    assert!(tree.find(&400));
+    assert!(!tree.find(&7));
+    assert!(!tree.invalid_color());
+    assert!(!tree.invalid_key());
+  }
+  #[test]
+  fn test_delete() {
+    let mut tree: Llrb<u64> = Llrb::new();
+    for key in [10, 50, 5, 200, 400, 100].iter() {
+      tree.insert(*key);
+    }
+    assert_eq!(tree.delete(&10), Some(10));
+    assert_eq!(tree.delete(&10), None);
+    assert!(!tree.invalid_color());
+    assert_eq!(tree.delete(&400), Some(400));
+    assert_eq!(tree.delete(&5), Some(5));
+    assert!(!tree.invalid_color());
+    assert!(!tree.invalid_size());
+    let keys: Vec<u64> = tree.iter().cloned().collect();
+    assert_eq!(keys, vec![50, 100, 200]);
+  }
+  #[test]
+  fn test_find_borrowed() {
+    conformance::find_borrowed(Llrb::new());
+  }
+  #[test]
+  fn test_invariants() {
+    let tree = conformance::invariants(Llrb::new(), |tree| {
+      assert!(!tree.invalid_color());
+      assert!(!tree.invalid_size());
+      assert!(!tree.invalid_key());
+    });
+    assert!(tree.is_empty());
+  }
+  #[test]
+  fn test_sorted_insert_large() {
+    let tree = conformance::sorted_insert_large(Llrb::new(), |tree| {
+      assert!(!tree.invalid_color());
+    });
+    assert!(tree.iter().cloned().eq((1..100_000).step_by(2)));
+  }
+}
@@ -0,0 +1,589 @@
+use std::borrow::Borrow;
+use std::cmp::Ordering;
+use std::fmt::Debug;
+use std::mem::{replace, swap};
+
+use super::iter::{InOrder, TreeNode};
+use super::BST;
+#[cfg(test)]
+use super::{iter::print_tree, unsorted};
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Color {
+  Red,
+  Black,
+}
+
+#[derive(Debug)]
+struct Node<K: Ord> {
+  key: K,
+  color: Color,
+  size: usize,
+  lchild: Option<Box<Node<K>>>,
+  rchild: Option<Box<Node<K>>>,
+}
+
+type Link<K> = Option<Box<Node<K>>>;
+type Path<K> = Vec<(Box<Node<K>>, Ordering)>;
+
+/// A red-black tree. No red node has a red child and every path from the root
+/// down to a missing child passes the same number of black nodes, so the tree
+/// is at most twice as high as a perfectly balanced one. Updates need at most
+/// two rotations for an insertion and three for a deletion.
+pub struct RedBlack<K: Ord + Debug> {
+  root: Option<Box<Node<K>>>,
+}
+
+impl<K: Ord + Debug> RedBlack<K> {
+  pub fn new() -> RedBlack<K> {
+    RedBlack { root: None }
+  }
+  fn size(tree: &Option<Box<Node<K>>>) -> usize {
+    tree.as_ref().map_or(0, |t| t.size)
+  }
+  fn update(tree: &mut Node<K>) {
+    tree.size = 1 + RedBlack::size(&tree.lchild) + RedBlack::size(&tree.rchild);
+  }
+  fn is_red(tree: &Option<Box<Node<K>>>) -> bool {
+    tree.as_ref().is_some_and(|t| t.color == Color::Red)
+  }
+  fn child(tree: &mut Node<K>, dir: Ordering) -> &mut Link<K> {
+    match dir {
+      Ordering::Less => &mut tree.lchild,
+      _ => &mut tree.rchild,
+    }
+  }
+
+  fn rotate_right(tree: &mut Box<Node<K>>) {
+    let lnode = tree.lchild.as_mut().unwrap();
+    let llnode = lnode.lchild.take();
+    let lrnode = lnode.rchild.take();
+    let mut rnode = replace(&mut tree.lchild, llnode);
+    swap(
+      &mut tree.as_mut().key,
+      &mut rnode.as_deref_mut().unwrap().key,
+    );
+    swap(
+      &mut tree.as_mut().color,
+      &mut rnode.as_deref_mut().unwrap().color,
+    );
+    rnode.as_deref_mut().unwrap().lchild = lrnode;
+    rnode.as_deref_mut().unwrap().rchild = tree.rchild.take();
+    RedBlack::update(rnode.as_deref_mut().unwrap());
+    tree.rchild = rnode;
+    RedBlack::update(tree);
+  }
+
+  fn rotate_left(tree: &mut Box<Node<K>>) {
+    let rnode = tree.rchild.as_mut().unwrap();
+    let rlnode = rnode.lchild.take();
+    let rrnode = rnode.rchild.take();
+    let mut lnode = replace(&mut tree.rchild, rrnode);
+    swap(
+      &mut tree.as_mut().key,
+      &mut lnode.as_deref_mut().unwrap().key,
+    );
+    swap(
+      &mut tree.as_mut().color,
+      &mut lnode.as_deref_mut().unwrap().color,
+    );
+    lnode.as_deref_mut().unwrap().lchild = tree.lchild.take();
+    lnode.as_deref_mut().unwrap().rchild = rlnode;
+    RedBlack::update(lnode.as_deref_mut().unwrap());
+    tree.lchild = lnode;
+    RedBlack::update(tree);
+  }
+  /// Rotates the child of `tree` on the side opposite to `dir` up, moving the
+  /// root of `tree` down towards `dir`.
+  fn rotate_towards(tree: &mut Box<Node<K>>, dir: Ordering) {
+    match dir {
+      Ordering::Less => RedBlack::rotate_left(tree),
+      _ => RedBlack::rotate_right(tree),
+    }
+  }
+  /// Walks down from `tree` following `cmp`, detaching every visited node from
+  /// its parent. Returns the detached ancestors, each with the direction taken
+  /// below it, and the node where `cmp` returned `Equal` if there is one.
+  fn descend<F>(tree: Link<K>, mut cmp: F) -> (Path<K>, Link<K>)
+  where
+    F: FnMut(&K) -> Ordering,
+  {
+    let mut path = Vec::new();
+    let mut tree = tree;
+    while let Some(mut t) = tree {
+      match cmp(&t.key) {
+        Ordering::Less => {
+          tree = t.lchild.take();
+          path.push((t, Ordering::Less));
+        }
+        Ordering::Greater => {
+          tree = t.rchild.take();
+          path.push((t, Ordering::Greater));
+        }
+        Ordering::Equal => return (path, Some(t)),
+      }
+    }
+    (path, None)
+  }
+  /// Reattaches `tree` below the ancestors in `path` without restructuring.
+  fn rebuild(mut path: Path<K>, tree: Link<K>) -> Link<K> {
+    let mut tree = tree;
+    while let Some((mut p, dir)) = path.pop() {
+      *RedBlack::child(&mut p, dir) = tree;
+      RedBlack::update(&mut p);
+      tree = Some(p);
+    }
+    tree
+  }
+
+  /// Inserts `key` as a red leaf and climbs up the path recoloring while the
+  /// uncle is red. A black uncle ends the climb with one or two rotations.
+  fn _insert(key: K, tree: &mut Link<K>) -> bool {
+    let (mut path, found) = RedBlack::descend(tree.take(), |k| key.cmp(k));
+    if found.is_some() {
+      *tree = RedBlack::rebuild(path, found);
+      return false;
+    }
+    let mut x = Box::new(Node {
+      key,
+      color: Color::Red,
+      size: 1,
+      lchild: None,
+      rchild: None,
+    });
+    loop {
+      let (mut p, pdir) = match path.pop() {
+        None => {
+          x.color = Color::Black;
+          *tree = Some(x);
+          return true;
+        }
+        Some(entry) => entry,
+      };
+      *RedBlack::child(&mut p, pdir) = Some(x);
+      RedBlack::update(&mut p);
+      if p.color == Color::Black {
+        *tree = RedBlack::rebuild(path, Some(p));
+        return true;
+      }
+      // A red parent is never the root, so the grandparent exists and is black.
+      let (mut g, gdir) = path.pop().unwrap();
+      *RedBlack::child(&mut g, gdir) = Some(p);
+      RedBlack::update(&mut g);
+      let uncle = match gdir {
+        Ordering::Less => &mut g.rchild,
+        _ => &mut g.lchild,
+      };
+      if let Some(u) = uncle.as_mut().filter(|u| u.color == Color::Red) {
+        u.color = Color::Black;
+        RedBlack::child(&mut g, gdir).as_mut().unwrap().color = Color::Black;
+        g.color = Color::Red;
+        x = g;
+        continue;
+      }
+      match (gdir, pdir) {
+        //      g
+        //     /
+        //    p
+        //   /
+        //  x
+        (Ordering::Less, Ordering::Less) => RedBlack::rotate_right(&mut g),
+        //      g
+        //     /
+        //    p
+        //     \
+        //      x
+        (Ordering::Less, _) => {
+          RedBlack::rotate_left(g.lchild.as_mut().unwrap());
+          RedBlack::rotate_right(&mut g);
+        }
+        //      g
+        //       \
+        //        p
+        //       /
+        //      x
+        (_, Ordering::Less) => {
+          RedBlack::rotate_right(g.rchild.as_mut().unwrap());
+          RedBlack::rotate_left(&mut g);
+        }
+        //      g
+        //       \
+        //        p
+        //         \
+        //          x
+        (_, _) => RedBlack::rotate_left(&mut g),
+      }
+      // The old grandparent went down on the side opposite to `gdir`.
+      g.color = Color::Black;
+      let down = match gdir {
+        Ordering::Less => &mut g.rchild,
+        _ => &mut g.lchild,
+      };
+      down.as_mut().unwrap().color = Color::Red;
+      *tree = RedBlack::rebuild(path, Some(g));
+      return true;
+    }
+  }
+  fn _find<'a, Q>(key: &Q, tree: &'a Link<K>) -> Option<&'a Node<K>>
+  where
+    K: Borrow<Q>,
+    Q: Ord + ?Sized,
+  {
+    let mut tree = tree;
+    while let Some(t) = tree {
+      match key.cmp(t.key.borrow()) {
+        Ordering::Less => tree = &t.lchild,
+        Ordering::Greater => tree = &t.rchild,
+        Ordering::Equal => return Some(t),
+      }
+    }
+    None
+  }
+  /// Removes the node with `key`. A node with two children first trades its key
+  /// with the minimum of its right subtree, so the node taken out of the tree
+  /// always has at most one child.
+  fn _delete<Q>(key: &Q, tree: &mut Link<K>) -> Option<K>
+  where
+    K: Borrow<Q>,
+    Q: Ord + ?Sized,
+  {
+    let (mut path, found) = RedBlack::descend(tree.take(), |k| key.cmp(k.borrow()));
+    let mut y = match found {
+      None => {
+        *tree = RedBlack::rebuild(path, None);
+        return None;
+      }
+      Some(t) => t,
+    };
+    if y.lchild.is_some() && y.rchild.is_some() {
+      let right = y.rchild.take();
+      let index = path.len();
+      path.push((y, Ordering::Greater));
+      let (rest, _) = RedBlack::descend(right, |_| Ordering::Less);
+      path.extend(rest);
+      y = path.pop().unwrap().0;
+      swap(&mut path[index].0.key, &mut y.key);
+    }
+    let child = y.lchild.take().or_else(|| y.rchild.take());
+    *tree = match (y.color, child) {
+      (Color::Red, child) => RedBlack::rebuild(path, child),
+      // The only child of a black node is a red leaf.
+      (Color::Black, Some(mut c)) => {
+        c.color = Color::Black;
+        RedBlack::rebuild(path, Some(c))
+      }
+      (Color::Black, None) => match path.pop() {
+        None => None,
+        Some((mut p, dir)) => {
+          *RedBlack::child(&mut p, dir) = None;
+          RedBlack::update(&mut p);
+          RedBlack::fix_double_black(path, p, dir)
+        }
+      },
+    };
+    if let Some(root) = tree {
+      root.color = Color::Black;
+    }
+    Some(y.key)
+  }
+  /// Restores the black heights after the subtree of `p` on side `dir` lost a
+  /// black node, climbing the ancestors in `path` as long as the deficit moves
+  /// up, and returns the reattached root.
+  fn fix_double_black(mut path: Path<K>, p: Box<Node<K>>, dir: Ordering) -> Link<K> {
+    let mut p = p;
+    let mut dir = dir;
+    loop {
+      let other = dir.reverse();
+      // A red sibling is rotated above `p`, which leaves a black sibling.
+      if RedBlack::is_red(RedBlack::child(&mut p, other)) {
+        RedBlack::rotate_towards(&mut p, dir);
+        p.color = Color::Black;
+        let mut down = RedBlack::child(&mut p, dir).take().unwrap();
+        down.color = Color::Red;
+        path.push((p, dir));
+        p = down;
+      }
+      // The sibling exists, since the side `dir` is one black node short.
+      let s = RedBlack::child(&mut p, other).as_mut().unwrap();
+      let near_red = RedBlack::is_red(RedBlack::child(s, dir));
+      let far_red = RedBlack::is_red(RedBlack::child(s, other));
+      if !near_red && !far_red {
+        s.color = Color::Red;
+        if p.color == Color::Red {
+          p.color = Color::Black;
+          return RedBlack::rebuild(path, Some(p));
+        }
+        match path.pop() {
+          None => return Some(p),
+          Some((mut g, gdir)) => {
+            *RedBlack::child(&mut g, gdir) = Some(p);
+            RedBlack::update(&mut g);
+            p = g;
+            dir = gdir;
+            continue;
+          }
+        }
+      }
+      if !far_red {
+        // Turn a red near nephew into a red far nephew.
+        RedBlack::rotate_towards(s, other);
+        s.color = Color::Black;
+        RedBlack::child(s, other).as_mut().unwrap().color = Color::Red;
+      }
+      let color = p.color;
+      RedBlack::rotate_towards(&mut p, dir);
+      p.color = color;
+      RedBlack::child(&mut p, Ordering::Less)
+        .as_mut()
+        .unwrap()
+        .color = Color::Black;
+      RedBlack::child(&mut p, Ordering::Greater)
+        .as_mut()
+        .unwrap()
+        .color = Color::Black;
+      return RedBlack::rebuild(path, Some(p));
+    }
+  }
+
+  pub fn len(&self) -> usize {
+    RedBlack::size(&self.root)
+  }
+  pub fn is_empty(&self) -> bool {
+    self.root.is_none()
+  }
+  /// Returns an iterator over the keys in ascending order.
+  pub fn iter(&self) -> Iter<'_, K> {
+    Iter {
+      walk: InOrder::new(&self.root, self.len()),
+    }
+  }
+  /// Returns the number of black nodes on every path from `tree` down to a
+  /// missing child, or `None` if the red-black invariants fail below `tree`.
+  fn black_height(tree: &Option<Box<Node<K>>>) -> Option<usize> {
+    match tree {
+      None => Some(0),
+      Some(t) => {
+        if t.color == Color::Red && (RedBlack::is_red(&t.lchild) || RedBlack::is_red(&t.rchild)) {
+          return None;
+        }
+        let lheight = RedBlack::black_height(&t.lchild)?;
+        let rheight = RedBlack::black_height(&t.rchild)?;
+        if lheight != rheight {
+          return None;
+        }
+        Some(lheight + (t.color == Color::Black) as usize)
+      }
+    }
+  }
+  /// Checks the coloring of the tree. Returns `true` if the root is red, a red
+  /// node has a red child, or two paths from the root down to a missing child
+  /// pass different numbers of black nodes.
+  pub fn invalid_color(&self) -> bool {
+    RedBlack::is_red(&self.root) || RedBlack::black_height(&self.root).is_none()
+  }
+  #[cfg(test)]
+  pub fn print(&self) -> String {
+    print_tree(self.root.as_deref().unwrap(), &|t| {
+      let color = match t.color {
+        Color::Red => "R",
+        Color::Black => "B",
+      };
+      format!("{:?}{}", t.key, color)
+    })
+  }
+  #[cfg(test)]
+  fn _invalid_size(tree: &Option<Box<Node<K>>>) -> bool {
+    match tree {
+      None => false,
+      Some(t) => {
+        t.size != 1 + RedBlack::size(&t.lchild) + RedBlack::size(&t.rchild)
+          || RedBlack::_invalid_size(&t.lchild)
+          || RedBlack::_invalid_size(&t.rchild)
+      }
+    }
+  }
+  #[cfg(test)]
+  pub fn invalid_size(&self) -> bool {
+    RedBlack::_invalid_size(&self.root)
+  }
+  #[cfg(test)]
+  pub fn invalid_key(&self) -> bool {
+    unsorted(self.iter())
+  }
+}
+
+impl<K: Ord + Debug> Default for RedBlack<K> {
+  fn default() -> RedBlack<K> {
+    RedBlack::new()
+  }
+}
+
+impl<K: Ord + Debug> BST<K> for RedBlack<K> {
+  fn insert(&mut self, key: K) -> bool {
+    RedBlack::_insert(key, &mut self.root)
+  }
+  fn find<Q>(&mut self, key: &Q) -> bool
+  where
+    K: Borrow<Q>,
+    Q: Ord + ?Sized,
+  {
+    RedBlack::_find(key, &self.root).is_some()
+  }
+  fn delete<Q>(&mut self, key: &Q) -> Option<K>
+  where
+    K: Borrow<Q>,
+    Q: Ord + ?Sized,
+  {
+    RedBlack::_delete(key, &mut self.root)
+  }
+}
+
+impl<K: Ord> TreeNode for Node<K> {
+  type Key = K;
+  fn key(&self) -> &K {
+    &self.key
+  }
+  fn lchild(&self) -> &Link<K> {
+    &self.lchild
+  }
+  fn rchild(&self) -> &Link<K> {
+    &self.rchild
+  }
+  fn lchild_mut(&mut self) -> &mut Link<K> {
+    &mut self.lchild
+  }
+  fn rchild_mut(&mut self) -> &mut Link<K> {
+    &mut self.rchild
+  }
+}
+
+/// An in-order iterator over the keys of a `RedBlack`.
+pub struct Iter<'a, K: Ord> {
+  walk: InOrder<'a, Node<K>>,
+}
+
+impl<'a, K: Ord> Iterator for Iter<'a, K> {
+  type Item = &'a K;
+
+  fn next(&mut self) -> Option<&'a K> {
+    self.walk.next().map(|t| &t.key)
+  }
+  fn size_hint(&self) -> (usize, Option<usize>) {
+    self.walk.size_hint()
+  }
+}
+
+impl<'a, K: Ord> DoubleEndedIterator for Iter<'a, K> {
+  fn next_back(&mut self) -> Option<&'a K> {
+    self.walk.next_back().map(|t| &t.key)
+  }
+}
+
+impl<'a, K: Ord> ExactSizeIterator for Iter<'a, K> {}
+
+impl<'a, K: Ord + Debug> IntoIterator for &'a RedBlack<K> {
+  type Item = &'a K;
+  type IntoIter = Iter<'a, K>;
+
+  fn into_iter(self) -> Iter<'a, K> {
+    self.iter()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::RedBlack;
+  use super::BST;
+  use crate::conformance;
+  use assert_str::assert_str_eq;
+
+  #[test]
+  fn test_insert() {
+    let mut tree: RedBlack<u64> = RedBlack::new();
+    assert!(tree.insert(10));
+    assert_str_eq!(tree.print(), "[10B()()]");
+    assert!(tree.insert(50));
+    assert!(!tree.insert(10));
+    assert_str_eq!(tree.print(), "[10B()([50R()()])]");
+    // black uncle, outer grandchild: single rotation
+    assert!(tree.insert(100));
+    assert_str_eq!(tree.print(), "[50B([10R()()])([100R()()])]");
+    // red uncle: recolor
+    assert!(tree.insert(5));
+    assert_str_eq!(tree.print(), "[50B([10B([5R()()])()])([100B()()])]");
+    // black uncle, inner grandchild: double rotation
+    assert!(tree.insert(7));
+    assert_str_eq!(tree.print(), "[50B([7B([5R()()])([10R()()])])([100B()()])]");
+    assert!(!tree.invalid_color());
+    assert!(!tree.invalid_size());
+  }
+  #[test]
+  fn test_find() {
+    let mut tree: RedBlack<u64> = RedBlack::new();
+    for key in [10, 50, 5, 200, 400, 100].iter() {
+      tree.insert(*key);
+    }
+    assert!(tree.find(&5));
+    assert!(tree.find(&400));
+    assert!(!tree.find(&7));
+    assert_str_eq!(
+      tree.print(),
+      "[10B([5B()()])([200R([50B()([100R()()])])([400B()()])])]"
+    );
+    assert!(!tree.invalid_color());
+  }
+  #[test]
+  fn test_delete() {
+    let mut tree: RedBlack<u64> = RedBlack::new();
+    for key in [10, 50, 5, 200, 400, 100].iter() {
+      tree.insert(*key);
+    }
+    // two children: replaced by the successor
+    assert_eq!(tree.delete(&10), Some(10));
+    assert_str_eq!(
+      tree.print(),
+      "[50B([5B()()])([200R([100B()()])([400B()()])])]"
+    );
+    assert_eq!(tree.delete(&10), None);
+    // black leaf with a red sibling
+    assert_eq!(tree.delete(&5), Some(5));
+    assert_str_eq!(tree.print(), "[200B([50B()([100R()()])])([400B()()])]");
+    assert!(!tree.invalid_color());
+    // black leaf with a black sibling and a red nephew
+    assert_eq!(tree.delete(&400), Some(400));
+    assert_str_eq!(tree.print(), "[100B([50B()()])([200B()()])]");
+    assert!(!tree.invalid_color());
+    assert!(!tree.invalid_size());
+    assert_eq!(tree.len(), 3);
+  }
+  #[test]
+  fn test_iter() {
+    let mut tree: RedBlack<u64> = RedBlack::new();
+    assert_eq!(tree.iter().next(), None);
+    for key in [10, 50, 5, 200, 400, 100].iter() {
+      tree.insert(*key);
+    }
+    let keys: Vec<u64> = tree.iter().cloned().collect();
+    assert_eq!(keys, vec![5, 10, 50, 100, 200, 400]);
+    assert_eq!(tree.iter().len(), 6);
+  }
+  #[test]
+  fn test_find_borrowed() {
+    conformance::find_borrowed(RedBlack::new());
+  }
+  #[test]
+  fn test_invariants() {
+    let tree = conformance::invariants(RedBlack::new(), |tree| {
+      assert!(!tree.invalid_color());
+      assert!(!tree.invalid_size());
+      assert!(!tree.invalid_key());
+    });
+    assert!(tree.is_empty());
+  }
+  #[test]
+  fn test_sorted_insert_large() {
+    let tree = conformance::sorted_insert_large(RedBlack::new(), |tree| {
+      assert!(!tree.invalid_color());
+    });
+    assert!(tree.iter().cloned().eq((1..100_000).step_by(2)));
+  }
+}
@@ -0,0 +1,435 @@
+use std::borrow::Borrow;
+use std::cmp::Ordering;
+use std::fmt::Debug;
+
+use super::iter::{InOrder, TreeNode};
+use super::BST;
+#[cfg(test)]
+use super::{iter::print_tree, unsorted};
+#[derive(Debug)]
+struct Node<K: Ord> {
+  key: K,
+  lchild: Option<Box<Node<K>>>,
+  rchild: Option<Box<Node<K>>>,
+}
+
+type Link<K> = Option<Box<Node<K>>>;
+type Path<K> = Vec<(Box<Node<K>>, Ordering)>;
+
+/// A scapegoat tree after Galperin and Rivest. Nodes carry no balance
+/// information. An insertion that ends up deeper than `log(n)` with base
+/// `1 / alpha` walks back up to the first ancestor whose subtree is out of
+/// `alpha` weight balance and rebuilds it perfectly balanced. Deletions unlink
+/// their node without rebalancing, and the whole tree is rebuilt once it has
+/// shrunk below `alpha` of the largest size it had since the last rebuild.
+pub struct Scapegoat<K: Ord + Debug> {
+  root: Option<Box<Node<K>>>,
+  alpha: f64,
+  len: usize,
+  max_len: usize,
+}
+
+impl<K: Ord + Debug> Scapegoat<K> {
+  /// Makes an empty tree with the balance factor `alpha`. Smaller values keep
+  /// the tree lower at the cost of more frequent rebuilds.
+  ///
+  /// # Panics
+  ///
+  /// Panics if `alpha` is not strictly between 0.5 and 1.
+  pub fn new(alpha: f64) -> Scapegoat<K> {
+    assert!(
+      0.5 < alpha && alpha < 1.0,
+      "new: alpha must be strictly between 0.5 and 1"
+    );
+    Scapegoat {
+      root: None,
+      alpha,
+      len: 0,
+      max_len: 0,
+    }
+  }
+  /// Counts the nodes of `tree`.
+  fn count(tree: &Link<K>) -> usize {
+    let mut count = 0;
+    let mut stack: Vec<&Box<Node<K>>> = tree.iter().collect();
+    while let Some(t) = stack.pop() {
+      count += 1;
+      stack.extend(t.lchild.iter());
+      stack.extend(t.rchild.iter());
+    }
+    count
+  }
+  /// Returns the deepest an insertion may go before a rebuild is due.
+  fn max_depth(&self) -> usize {
+    ((self.len as f64).ln() / (1.0 / self.alpha).ln()).floor() as usize
+  }
+  /// Walks down from `tree` following `cmp`, detaching every visited node from
+  /// its parent. Returns the detached ancestors, each with the direction taken
+  /// below it, and the node where `cmp` returned `Equal` if there is one.
+  fn descend<F>(tree: Link<K>, mut cmp: F) -> (Path<K>, Link<K>)
+  where
+    F: FnMut(&K) -> Ordering,
+  {
+    let mut path = Vec::new();
+    let mut tree = tree;
+    while let Some(mut t) = tree {
+      match cmp(&t.key) {
+        Ordering::Less => {
+          tree = t.lchild.take();
+          path.push((t, Ordering::Less));
+        }
+        Ordering::Greater => {
+          tree = t.rchild.take();
+          path.push((t, Ordering::Greater));
+        }
+        Ordering::Equal => return (path, Some(t)),
+      }
+    }
+    (path, None)
+  }
+  /// Reattaches `tree` below the ancestors in `path` without restructuring.
+  fn rebuild(mut path: Path<K>, tree: Link<K>) -> Link<K> {
+    let mut tree = tree;
+    while let Some((mut p, dir)) = path.pop() {
+      match dir {
+        Ordering::Less => p.lchild = tree,
+        _ => p.rchild = tree,
+      }
+      tree = Some(p);
+    }
+    tree
+  }
+  /// Flattens `tree` into its nodes in key order.
+  fn flatten(tree: Link<K>) -> Vec<Box<Node<K>>> {
+    let mut nodes = Vec::new();
+    let mut stack = Vec::new();
+    let mut tree = tree;
+    loop {
+      while let Some(mut t) = tree {
+        tree = t.lchild.take();
+        stack.push(t);
+      }
+      match stack.pop() {
+        None => return nodes,
+        Some(mut t) => {
+          tree = t.rchild.take();
+          nodes.push(t);
+        }
+      }
+    }
+  }
+  /// Builds a perfectly balanced tree from the next `len` nodes of `nodes`.
+  fn build<I>(nodes: &mut I, len: usize) -> Link<K>
+  where
+    I: Iterator<Item = Box<Node<K>>>,
+  {
+    if len == 0 {
+      return None;
+    }
+    let lchild = Scapegoat::build(nodes, len / 2);
+    let mut t = nodes.next().unwrap();
+    t.lchild = lchild;
+    t.rchild = Scapegoat::build(nodes, len - len / 2 - 1);
+    Some(t)
+  }
+  /// Rebuilds `tree` perfectly balanced.
+  fn rebuild_balanced(tree: &mut Link<K>) {
+    let nodes = Scapegoat::flatten(tree.take());
+    let len = nodes.len();
+    *tree = Scapegoat::build(&mut nodes.into_iter(), len);
+  }
+  /// Detaches the smallest node of `tree`, returning it and the rest of `tree`.
+  fn remove_min(tree: Box<Node<K>>) -> (Box<Node<K>>, Link<K>) {
+    let (mut path, _) = Scapegoat::descend(Some(tree), |_| Ordering::Less);
+    let (mut min, _) = path.pop().unwrap();
+    let rest = Scapegoat::rebuild(path, min.rchild.take());
+    (min, rest)
+  }
+
+  fn _insert(&mut self, key: K) -> bool {
+    let (mut path, found) = Scapegoat::descend(self.root.take(), |k| key.cmp(k));
+    if found.is_some() {
+      self.root = Scapegoat::rebuild(path, found);
+      return false;
+    }
+    self.len += 1;
+    self.max_len = self.max_len.max(self.len);
+    let mut tree = Box::new(Node {
+      key,
+      lchild: None,
+      rchild: None,
+    });
+    if path.len() > self.max_depth() {
+      // Climb until the child we came from holds more than `alpha` of its
+      // parent's weight. Such a scapegoat exists since the new node is too deep.
+      let mut size = 1;
+      while let Some((mut p, dir)) = path.pop() {
+        let sibling = match dir {
+          Ordering::Less => {
+            p.lchild = Some(tree);
+            Scapegoat::count(&p.rchild)
+          }
+          _ => {
+            p.rchild = Some(tree);
+            Scapegoat::count(&p.lchild)
+          }
+        };
+        let parent_size = size + 1 + sibling;
+        if size as f64 > self.alpha * parent_size as f64 {
+          let mut subtree = Some(p);
+          Scapegoat::rebuild_balanced(&mut subtree);
+          self.root = Scapegoat::rebuild(path, subtree);
+          return true;
+        }
+        size = parent_size;
+        tree = p;
+      }
+      self.root = Some(tree);
+      return true;
+    }
+    self.root = Scapegoat::rebuild(path, Some(tree));
+    true
+  }
+  fn _find<'a, Q>(key: &Q, tree: &'a Link<K>) -> Option<&'a Node<K>>
+  where
+    K: Borrow<Q>,
+    Q: Ord + ?Sized,
+  {
+    let mut tree = tree;
+    while let Some(t) = tree {
+      match key.cmp(t.key.borrow()) {
+        Ordering::Less => tree = &t.lchild,
+        Ordering::Greater => tree = &t.rchild,
+        Ordering::Equal => return Some(t),
+      }
+    }
+    None
+  }
+
+  pub fn len(&self) -> usize {
+    self.len
+  }
+  pub fn is_empty(&self) -> bool {
+    self.len == 0
+  }
+  /// Returns the height of the tree, which is zero when it is empty.
+  pub fn depth(&self) -> usize {
+    let mut depth = 0;
+    let mut stack: Vec<(&Box<Node<K>>, usize)> = self.root.iter().map(|t| (t, 1)).collect();
+    while let Some((t, d)) = stack.pop() {
+      depth = depth.max(d);
+      stack.extend(t.lchild.iter().map(|l| (l, d + 1)));
+      stack.extend(t.rchild.iter().map(|r| (r, d + 1)));
+    }
+    depth
+  }
+  /// Returns an iterator over the keys in ascending order.
+  pub fn iter(&self) -> Iter<'_, K> {
+    Iter {
+      walk: InOrder::new(&self.root, self.len),
+    }
+  }
+  #[cfg(test)]
+  pub fn print(&self) -> String {
+    print_tree(self.root.as_deref().unwrap(), &|t| format!("{:?}", t.key))
+  }
+  #[cfg(test)]
+  pub fn invalid_key(&self) -> bool {
+    unsorted(self.iter())
+  }
+  #[cfg(test)]
+  pub fn invalid_count(&self) -> bool {
+    self.len != Scapegoat::count(&self.root) || self.len > self.max_len
+  }
+}
+
+impl<K: Ord + Debug> BST<K> for Scapegoat<K> {
+  fn insert(&mut self, key: K) -> bool {
+    self._insert(key)
+  }
+  fn find<Q>(&mut self, key: &Q) -> bool
+  where
+    K: Borrow<Q>,
+    Q: Ord + ?Sized,
+  {
+    Scapegoat::_find(key, &self.root).is_some()
+  }
+  fn delete<Q>(&mut self, key: &Q) -> Option<K>
+  where
+    K: Borrow<Q>,
+    Q: Ord + ?Sized,
+  {
+    let (path, found) = Scapegoat::descend(self.root.take(), |k| key.cmp(k.borrow()));
+    let mut t = match found {
+      None => {
+        self.root = Scapegoat::rebuild(path, None);
+        return None;
+      }
+      Some(t) => t,
+    };
+    // A node with two children hands its place to its successor, which has no
+    // left child.
+    let replacement = match (t.lchild.take(), t.rchild.take()) {
+      (None, child) | (child, None) => child,
+      (Some(l), Some(r)) => {
+        let (mut min, rest) = Scapegoat::remove_min(r);
+        min.lchild = Some(l);
+        min.rchild = rest;
+        Some(min)
+      }
+    };
+    self.root = Scapegoat::rebuild(path, replacement);
+    self.len -= 1;
+    if (self.len as f64) < self.alpha * self.max_len as f64 {
+      Scapegoat::rebuild_balanced(&mut self.root);
+      self.max_len = self.len;
+    }
+    Some(t.key)
+  }
+}
+
+impl<K: Ord> TreeNode for Node<K> {
+  type Key = K;
+  fn key(&self) -> &K {
+    &self.key
+  }
+  fn lchild(&self) -> &Link<K> {
+    &self.lchild
+  }
+  fn rchild(&self) -> &Link<K> {
+    &self.rchild
+  }
+  fn lchild_mut(&mut self) -> &mut Link<K> {
+    &mut self.lchild
+  }
+  fn rchild_mut(&mut self) -> &mut Link<K> {
+    &mut self.rchild
+  }
+}
+
+/// An in-order iterator over the keys of a `Scapegoat`.
+pub struct Iter<'a, K: Ord> {
+  walk: InOrder<'a, Node<K>>,
+}
+
+impl<'a, K: Ord> Iterator for Iter<'a, K> {
+  type Item = &'a K;
+
+  fn next(&mut self) -> Option<&'a K> {
+    self.walk.next().map(|t| &t.key)
+  }
+  fn size_hint(&self) -> (usize, Option<usize>) {
+    self.walk.size_hint()
+  }
+}
+
+impl<'a, K: Ord> DoubleEndedIterator for Iter<'a, K> {
+  fn next_back(&mut self) -> Option<&'a K> {
+    self.walk.next_back().map(|t| &t.key)
+  }
+}
+
+impl<'a, K: Ord> ExactSizeIterator for Iter<'a, K> {}
+
+impl<'a, K: Ord + Debug> IntoIterator for &'a Scapegoat<K> {
+  type Item = &'a K;
+  type IntoIter = Iter<'a, K>;
+
+  fn into_iter(self) -> Iter<'a, K> {
+    self.iter()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::Scapegoat;
+  use super::BST;
+  use crate::conformance;
+  use assert_str::assert_str_eq;
+
+  #[test]
+  fn test_insert() {
+    let mut tree: Scapegoat<u64> = Scapegoat::new(0.55);
+    assert!(tree.insert(10));
+    assert!(tree.insert(50));
+    assert!(!tree.insert(10));
+    assert_str_eq!(tree.print(), "[10()([50()()])]");
+    // 100 lands at depth 2 > log(3) / log(1 / 0.55), so the root is rebuilt.
+    assert!(tree.insert(100));
+    assert_str_eq!(tree.print(), "[50([10()()])([100()()])]");
+    assert!(!tree.invalid_count());
+  }
+  #[test]
+  fn test_find() {
+    let mut tree: Scapegoat<u64> = Scapegoat::new(0.7);
+    for key in [10, 50, 5, 200, 400, 100].iter() {
+      tree.insert(*key);
+    }
+    assert!(tree.find(&5));
+    assert!(tree.find(&400));
+    assert!(!tree.find(&7));
+    assert!(!tree.invalid_key());
+  }
+  #[test]
+  fn test_delete() {
+    let mut tree: Scapegoat<u64> = Scapegoat::new(0.7);
+    for key in [50, 10, 100, 5, 200].iter() {
+      tree.insert(*key);
+    }
+    // 10 has a single child, which takes its place.
+    assert_eq!(tree.delete(&10), Some(10));
+    assert_eq!(tree.delete(&10), None);
+    assert!(!tree.find(&10));
+    assert_str_eq!(tree.print(), "[50([5()()])([100()([200()()])])]");
+    assert_eq!(tree.len(), 4);
+    assert!(tree.insert(10));
+    // 50 has two children, so its successor 100 moves up.
+    assert_eq!(tree.delete(&50), Some(50));
+    assert_str_eq!(tree.print(), "[100([5()([10()()])])([200()()])]");
+    // 3 keys out of at most 5 fall below 0.7, so the tree is rebuilt.
+    assert_eq!(tree.delete(&200), Some(200));
+    assert_str_eq!(tree.print(), "[10([5()()])([100()()])]");
+    assert!(!tree.invalid_count());
+    let keys: Vec<u64> = tree.iter().cloned().collect();
+    assert_eq!(keys, vec![5, 10, 100]);
+  }
+  #[test]
+  fn test_delete_moves_key_out() {
+    #[derive(Debug, PartialEq, Eq, PartialOrd, Ord)]
+    struct Key(u64);
+    let mut tree = Scapegoat::new(0.7);
+    for key in 0..100 {
+      tree.insert(Key(key));
+    }
+    for key in (0..100).step_by(3) {
+      assert_eq!(tree.delete(&Key(key)), Some(Key(key)));
+    }
+    assert!(tree.iter().map(|k| k.0).eq((0..100).filter(|k| k % 3 != 0)));
+    assert!(!tree.invalid_count());
+  }
+  #[test]
+  #[should_panic]
+  fn test_invalid_alpha() {
+    let _tree: Scapegoat<u64> = Scapegoat::new(0.5);
+  }
+  #[test]
+  fn test_find_borrowed() {
+    conformance::find_borrowed(Scapegoat::new(0.7));
+  }
+  #[test]
+  fn test_invariants() {
+    let tree = conformance::invariants(Scapegoat::new(0.6), |tree| {
+      assert!(!tree.invalid_count());
+      assert!(!tree.invalid_key());
+    });
+    assert!(tree.is_empty());
+  }
+  #[test]
+  fn test_sorted_insert_large() {
+    let tree = conformance::sorted_insert_large(Scapegoat::new(0.7), |tree| {
+      // log(100000) / log(1 / 0.7) is about 32.3
+      assert!(tree.depth() <= 33);
+    });
+    assert!(tree.iter().cloned().eq((1..100_000).step_by(2)));
+  }
+}
@@ -1,24 +1,175 @@
+use std::borrow::Borrow;
 use std::cmp::Ordering;
 use std::fmt::Debug;
+use std::iter::FromIterator;
+use std::iter::Peekable;
 use std::mem::{replace, swap};
+use std::ops::{Bound, RangeBounds};
 
-use super::BST;
-#[derive(Debug)]
-struct Node<K: Ord> {
+use super::iter::{drop_tree, EntryNode, InOrder, IntoInOrder, RangeInOrder, TreeNode};
+use super::{above_start, below_end, sorted_entries, Action, Monoid, BST};
+struct Node<K: Ord, V, M: Monoid<V>, A: Action<V, M>> {
   key: K,
-  lchild: Option<Box<Node<K>>>,
-  rchild: Option<Box<Node<K>>>,
+  value: V,
+  size: usize,
+  summary: M::Summary,
+  // A tag already applied to this node but not yet to its children.
+  lazy: Option<A>,
+  lchild: Option<Box<Node<K, V, M, A>>>,
+  rchild: Option<Box<Node<K, V, M, A>>>,
+}
+
+type Link<K, V, M, A> = Option<Box<Node<K, V, M, A>>>;
+// The nodes below and from a split point.
+type Halves<K, V, M, A> = (Link<K, V, M, A>, Link<K, V, M, A>);
+
+/// How much a `SplayMap` or `Splay` restructures itself on lookups.
+///
+/// Insertions, deletions and splits always splay fully, as they work on the
+/// splayed root.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SplayStrategy {
+  /// Splays every accessed key to the root.
+  #[default]
+  Full,
+  /// Semi-splays the access path, which roughly halves the depth of every node
+  /// on it without moving the accessed key all the way to the root.
+  Semi,
+  /// Splays only on every `k`-th lookup and leaves the tree alone otherwise.
+  EveryKth(usize),
+  /// Does not splay hits at depth `d` or less, counting the root as zero.
+  SkipWithinDepth(usize),
+}
+
+pub struct SplayMap<K: Ord + Debug, V, M: Monoid<V> = (), A: Action<V, M> = ()> {
+  root: Option<Box<Node<K, V, M, A>>>,
+  strategy: SplayStrategy,
+  accesses: usize,
 }
 
 pub struct Splay<K: Ord + Debug> {
-  root: Option<Box<Node<K>>>,
+  map: SplayMap<K, ()>,
 }
 
-impl<K: Ord + Debug> Splay<K> {
-  pub fn new() -> Splay<K> {
-    Splay { root: None }
+impl<K: Ord + Debug, V, M: Monoid<V>, A: Action<V, M>> SplayMap<K, V, M, A> {
+  pub fn new() -> SplayMap<K, V, M, A> {
+    SplayMap::with_strategy(SplayStrategy::Full)
+  }
+  /// Makes an empty map that restructures itself on lookups according to
+  /// `strategy`.
+  ///
+  /// # Panics
+  ///
+  /// Panics if `strategy` is `EveryKth(0)`.
+  pub fn with_strategy(strategy: SplayStrategy) -> SplayMap<K, V, M, A> {
+    assert!(
+      strategy != SplayStrategy::EveryKth(0),
+      "with_strategy: EveryKth needs a positive period"
+    );
+    SplayMap {
+      root: None,
+      strategy,
+      accesses: 0,
+    }
   }
-  fn rotate_right(tree: &mut Box<Node<K>>) {
+  /// Builds a perfectly balanced map from entries in strictly ascending key
+  /// order in linear time.
+  ///
+  /// # Panics
+  ///
+  /// Panics if the keys are not strictly ascending.
+  pub fn from_sorted_iter<I>(iter: I) -> SplayMap<K, V, M, A>
+  where
+    I: IntoIterator<Item = (K, V)>,
+  {
+    let entries: Vec<(K, V)> = iter.into_iter().collect();
+    assert!(
+      entries.windows(2).all(|w| w[0].0 < w[1].0),
+      "from_sorted_iter: keys must be strictly ascending"
+    );
+    let len = entries.len();
+    let mut map = SplayMap::new();
+    map.root = SplayMap::build(&mut entries.into_iter(), len);
+    map
+  }
+  /// Builds a balanced tree from the next `len` entries of `entries`.
+  fn build<I>(entries: &mut I, len: usize) -> Link<K, V, M, A>
+  where
+    I: Iterator<Item = (K, V)>,
+  {
+    if len == 0 {
+      return None;
+    }
+    let lchild = SplayMap::build(entries, len / 2);
+    let (key, value) = entries.next().unwrap();
+    let rchild = SplayMap::build(entries, len - len / 2 - 1);
+    let mut t = Box::new(Node {
+      key,
+      value,
+      size: len,
+      summary: M::identity(),
+      lazy: None,
+      lchild,
+      rchild,
+    });
+    SplayMap::update(&mut t);
+    Some(t)
+  }
+  fn size(tree: &Option<Box<Node<K, V, M, A>>>) -> usize {
+    tree.as_ref().map_or(0, |t| t.size)
+  }
+  fn summary(tree: &Option<Box<Node<K, V, M, A>>>) -> M::Summary {
+    tree
+      .as_ref()
+      .map_or_else(M::identity, |t| t.summary.clone())
+  }
+  /// Recomputes the size and aggregate of `tree` from its children. Everything
+  /// that restructures the tree, from the rotations to `splay`, `_insert` and
+  /// `join`, goes through here.
+  fn update(tree: &mut Node<K, V, M, A>) {
+    tree.size = 1 + SplayMap::size(&tree.lchild) + SplayMap::size(&tree.rchild);
+    tree.summary = M::combine(
+      &M::combine(&SplayMap::summary(&tree.lchild), &M::lift(&tree.value)),
+      &SplayMap::summary(&tree.rchild),
+    );
+  }
+  /// Applies `action` to the value and aggregate of `tree` and leaves it
+  /// pending for the children.
+  fn tag(tree: &mut Node<K, V, M, A>, action: &A) {
+    action.apply(&mut tree.value);
+    tree.summary = action.apply_summary(&tree.summary, tree.size);
+    tree.lazy = Some(match &tree.lazy {
+      None => action.clone(),
+      Some(lazy) => action.compose(lazy),
+    });
+  }
+  /// Hands the pending action of `tree` down to its children. Anything that
+  /// reads or relinks the children of a node pushes it first.
+  fn push(tree: &mut Node<K, V, M, A>) {
+    if let Some(action) = tree.lazy.take() {
+      for c in tree.lchild.iter_mut().chain(tree.rchild.iter_mut()) {
+        SplayMap::tag(c, &action);
+      }
+    }
+  }
+  fn min_key(tree: &Link<K, V, M, A>) -> Option<&K> {
+    let mut t = tree.as_ref()?;
+    while let Some(l) = &t.lchild {
+      t = l;
+    }
+    Some(&t.key)
+  }
+  fn max_key(tree: &Link<K, V, M, A>) -> Option<&K> {
+    let mut t = tree.as_ref()?;
+    while let Some(r) = &t.rchild {
+      t = r;
+    }
+    Some(&t.key)
+  }
+
+  fn rotate_right(tree: &mut Box<Node<K, V, M, A>>) {
+    SplayMap::push(tree);
+    SplayMap::push(tree.lchild.as_mut().unwrap());
     let lnode = tree.lchild.as_mut().unwrap();
     let llnode = lnode.lchild.take();
     let lrnode = lnode.rchild.take();
@@ -27,12 +178,20 @@ impl<K: Ord + Debug> Splay<K> {
       &mut tree.as_mut().key,
       &mut rnode.as_deref_mut().unwrap().key,
     );
+    swap(
+      &mut tree.as_mut().value,
+      &mut rnode.as_deref_mut().unwrap().value,
+    );
     rnode.as_deref_mut().unwrap().lchild = lrnode;
     rnode.as_deref_mut().unwrap().rchild = tree.rchild.take();
+    SplayMap::update(rnode.as_deref_mut().unwrap());
     tree.rchild = rnode;
+    SplayMap::update(tree);
   }
 
-  fn rotate_left(tree: &mut Box<Node<K>>) {
+  fn rotate_left(tree: &mut Box<Node<K, V, M, A>>) {
+    SplayMap::push(tree);
+    SplayMap::push(tree.rchild.as_mut().unwrap());
     let rnode = tree.rchild.as_mut().unwrap();
     let rlnode = rnode.lchild.take();
     let rrnode = rnode.rchild.take();
@@ -41,284 +200,1161 @@ impl<K: Ord + Debug> Splay<K> {
       &mut tree.as_mut().key,
       &mut lnode.as_deref_mut().unwrap().key,
     );
+    swap(
+      &mut tree.as_mut().value,
+      &mut lnode.as_deref_mut().unwrap().value,
+    );
     lnode.as_deref_mut().unwrap().lchild = tree.lchild.take();
     lnode.as_deref_mut().unwrap().rchild = rlnode;
+    SplayMap::update(lnode.as_deref_mut().unwrap());
     tree.lchild = lnode;
+    SplayMap::update(tree);
+  }
+  /// Splays the node where `cmp` returns `Equal` to the root of `tree`, or the
+  /// last node visited when there is none. Returns the new root together with
+  /// `cmp` of its key.
+  ///
+  /// This is the top-down splay of Sleator and Tarjan. Walking down from the
+  /// root, nodes smaller than the target are linked into a left tree and larger
+  /// ones into a right tree, which become the subtrees of the new root. Both
+  /// trees are kept as spines reversed through the child slot that is filled
+  /// last, so no stack is needed, and sizes are fixed while they are assembled.
+  fn splay<F>(tree: Box<Node<K, V, M, A>>, mut cmp: F) -> (Box<Node<K, V, M, A>>, Ordering)
+  where
+    F: FnMut(&K) -> Ordering,
+  {
+    let mut t = tree;
+    // Reversed through `rchild`: the most recently linked node comes first.
+    let mut left: Link<K, V, M, A> = None;
+    // Reversed through `lchild`.
+    let mut right: Link<K, V, M, A> = None;
+    let ord = loop {
+      SplayMap::push(&mut t);
+      match cmp(&t.key) {
+        Ordering::Less => {
+          match t.lchild.as_ref().map(|l| (cmp(&l.key), l.lchild.is_some())) {
+            None => break Ordering::Less,
+            // zig-zig
+            //      t         l
+            //     /           \
+            //    l     =>      t
+            //   /
+            //  x
+            Some((Ordering::Less, true)) => SplayMap::rotate_right(&mut t),
+            _ => {}
+          }
+          // link right
+          let next = t.lchild.take().unwrap();
+          t.lchild = right.take();
+          right = Some(replace(&mut t, next));
+        }
+        Ordering::Greater => {
+          match t.rchild.as_ref().map(|r| (cmp(&r.key), r.rchild.is_some())) {
+            None => break Ordering::Greater,
+            // zig-zig
+            //  t             r
+            //   \           /
+            //    r   =>    t
+            //     \
+            //      x
+            Some((Ordering::Greater, true)) => SplayMap::rotate_left(&mut t),
+            _ => {}
+          }
+          // link left
+          let next = t.rchild.take().unwrap();
+          t.rchild = left.take();
+          left = Some(replace(&mut t, next));
+        }
+        Ordering::Equal => break Ordering::Equal,
+      }
+    };
+    let mut sub = t.lchild.take();
+    while let Some(mut l) = left {
+      left = replace(&mut l.rchild, sub);
+      SplayMap::update(&mut l);
+      sub = Some(l);
+    }
+    t.lchild = sub;
+    let mut sub = t.rchild.take();
+    while let Some(mut r) = right {
+      right = replace(&mut r.lchild, sub);
+      SplayMap::update(&mut r);
+      sub = Some(r);
+    }
+    t.rchild = sub;
+    SplayMap::update(&mut t);
+    (t, ord)
+  }
+  /// Splays the node where `cmp` returns `Equal` to the root, or the last node
+  /// visited when there is none. Returns whether an `Equal` node was found.
+  fn splay_last<F>(&mut self, cmp: F) -> bool
+  where
+    F: FnMut(&K) -> Ordering,
+  {
+    match self.root.take() {
+      None => false,
+      Some(root) => {
+        let (root, ord) = SplayMap::splay(root, cmp);
+        self.root = Some(root);
+        ord == Ordering::Equal
+      }
+    }
+  }
+
+  /// Splays the position of `item` to the root and inserts it there. If the key
+  /// is already present, its node takes the new value instead.
+  fn _insert(mut item: Box<Node<K, V, M, A>>, tree: &mut Link<K, V, M, A>) -> Option<V> {
+    let root = match tree.take() {
+      None => {
+        *tree = Some(item);
+        return None;
+      }
+      Some(root) => root,
+    };
+    let (mut root, ord) = SplayMap::splay(root, |k| item.key.cmp(k));
+    match ord {
+      Ordering::Equal => {
+        let old = replace(&mut root.value, item.value);
+        SplayMap::update(&mut root);
+        *tree = Some(root);
+        return Some(old);
+      }
+      Ordering::Less => {
+        item.lchild = root.lchild.take();
+        SplayMap::update(&mut root);
+        item.rchild = Some(root);
+      }
+      Ordering::Greater => {
+        item.rchild = root.rchild.take();
+        SplayMap::update(&mut root);
+        item.lchild = Some(root);
+      }
+    }
+    SplayMap::update(&mut item);
+    *tree = Some(item);
+    None
+  }
+  /// Joins two trees where every key of `left` is less than every key of
+  /// `right` by splaying the maximum of `left` to its root.
+  fn join(left: Link<K, V, M, A>, right: Link<K, V, M, A>) -> Link<K, V, M, A> {
+    match left {
+      None => right,
+      Some(left) => {
+        let (mut t, _) = SplayMap::splay(left, |_| Ordering::Greater);
+        t.rchild = right;
+        SplayMap::update(&mut t);
+        Some(t)
+      }
+    }
+  }
+  /// Splays the node with `key` to the root and removes it by joining its subtrees.
+  fn _delete<Q>(key: &Q, tree: &mut Link<K, V, M, A>) -> Option<(K, V)>
+  where
+    K: Borrow<Q>,
+    Q: Ord + ?Sized,
+  {
+    let (mut t, ord) = SplayMap::splay(tree.take()?, |k| key.cmp(k.borrow()));
+    if ord != Ordering::Equal {
+      *tree = Some(t);
+      return None;
+    }
+    *tree = SplayMap::join(t.lchild.take(), t.rchild.take());
+    Some((t.key, t.value))
+  }
+  /// Splits `tree` into the nodes whose keys satisfy `below` and the rest, where
+  /// `below` holds for a prefix of the keys, by splaying the node next to the
+  /// boundary to the root.
+  fn split_by<F>(tree: Link<K, V, M, A>, mut below: F) -> Halves<K, V, M, A>
+  where
+    F: FnMut(&K) -> bool,
+  {
+    let root = match tree {
+      None => return (None, None),
+      Some(root) => root,
+    };
+    let (mut root, _) = SplayMap::splay(root, |k| {
+      if below(k) {
+        Ordering::Greater
+      } else {
+        Ordering::Less
+      }
+    });
+    if below(&root.key) {
+      let right = root.rchild.take();
+      SplayMap::update(&mut root);
+      (Some(root), right)
+    } else {
+      let left = root.lchild.take();
+      SplayMap::update(&mut root);
+      (left, Some(root))
+    }
+  }
+  /// Splays `key`, or the last node visited looking for it, to the root.
+  /// Returns whether `key` is present.
+  fn splay_root<Q>(&mut self, key: &Q) -> bool
+  where
+    K: Borrow<Q>,
+    Q: Ord + ?Sized,
+  {
+    self.splay_last(|k| key.cmp(k.borrow()))
+  }
+  /// Semi-splays the path to `key` top-down and returns the node holding it.
+  ///
+  /// The path is handled two edges at a time. A zig-zig rotates only at the top
+  /// of the pair and carries on below the node it lifted, and a zig-zag lifts
+  /// the lower node above both and carries on from there.
+  fn semi_splay<'a, Q>(key: &Q, tree: &'a mut Link<K, V, M, A>) -> Option<&'a mut Node<K, V, M, A>>
+  where
+    K: Borrow<Q>,
+    Q: Ord + ?Sized,
+  {
+    let mut t = tree.as_mut()?;
+    loop {
+      SplayMap::push(t);
+      let dir = key.cmp(t.key.borrow());
+      let child = match dir {
+        Ordering::Less => t.lchild.as_ref()?,
+        Ordering::Greater => t.rchild.as_ref()?,
+        Ordering::Equal => return Some(t),
+      };
+      let (child_dir, grandchild) = match key.cmp(child.key.borrow()) {
+        Ordering::Less => (Ordering::Less, child.lchild.is_some()),
+        Ordering::Greater => (Ordering::Greater, child.rchild.is_some()),
+        Ordering::Equal => (Ordering::Equal, true),
+      };
+      if !grandchild {
+        return None;
+      }
+      match (dir, child_dir) {
+        // zig
+        (Ordering::Less, Ordering::Equal) => {
+          SplayMap::rotate_right(t);
+          return Some(t);
+        }
+        (_, Ordering::Equal) => {
+          SplayMap::rotate_left(t);
+          return Some(t);
+        }
+        // zig-zig
+        (Ordering::Less, Ordering::Less) => {
+          SplayMap::rotate_right(t);
+          t = t.lchild.as_mut().unwrap();
+        }
+        (Ordering::Greater, Ordering::Greater) => {
+          SplayMap::rotate_left(t);
+          t = t.rchild.as_mut().unwrap();
+        }
+        // zig-zag
+        (Ordering::Less, _) => {
+          SplayMap::rotate_left(t.lchild.as_mut().unwrap());
+          SplayMap::rotate_right(t);
+        }
+        (_, _) => {
+          SplayMap::rotate_right(t.rchild.as_mut().unwrap());
+          SplayMap::rotate_left(t);
+        }
+      }
+    }
+  }
+  fn _find_mut<'a, Q>(key: &Q, tree: &'a mut Link<K, V, M, A>) -> Option<&'a mut Node<K, V, M, A>>
+  where
+    K: Borrow<Q>,
+    Q: Ord + ?Sized,
+  {
+    let mut tree = tree;
+    while let Some(t) = tree {
+      SplayMap::push(t);
+      match key.cmp(t.key.borrow()) {
+        Ordering::Less => tree = &mut t.lchild,
+        Ordering::Greater => tree = &mut t.rchild,
+        Ordering::Equal => return Some(t),
+      }
+    }
+    None
+  }
+  /// Returns the depth of the node holding `key`, counting the root as zero.
+  fn depth<Q>(&self, key: &Q) -> Option<usize>
+  where
+    K: Borrow<Q>,
+    Q: Ord + ?Sized,
+  {
+    let mut depth = 0;
+    let mut tree = &self.root;
+    while let Some(t) = tree {
+      match key.cmp(t.key.borrow()) {
+        Ordering::Less => tree = &t.lchild,
+        Ordering::Greater => tree = &t.rchild,
+        Ordering::Equal => return Some(depth),
+      }
+      depth += 1;
+    }
+    None
+  }
+  /// Looks up `key`, restructuring the tree according to the strategy, and
+  /// returns the node holding it.
+  fn access<Q>(&mut self, key: &Q) -> Option<&mut Node<K, V, M, A>>
+  where
+    K: Borrow<Q>,
+    Q: Ord + ?Sized,
+  {
+    let splay = match self.strategy {
+      SplayStrategy::Full => true,
+      SplayStrategy::Semi => return SplayMap::semi_splay(key, &mut self.root),
+      SplayStrategy::EveryKth(k) => {
+        self.accesses += 1;
+        if self.accesses == k {
+          self.accesses = 0;
+        }
+        self.accesses == 0
+      }
+      SplayStrategy::SkipWithinDepth(d) => match self.depth(key) {
+        Some(depth) => depth > d,
+        None => true,
+      },
+    };
+    if !splay {
+      return SplayMap::_find_mut(key, &mut self.root);
+    }
+    if !self.splay_root(key) {
+      return None;
+    }
+    self.root.as_deref_mut()
+  }
+
+  /// Inserts a key-value pair and splays it to the root, returning the previous
+  /// value of the key if it was present.
+  pub fn insert(&mut self, key: K, value: V) -> Option<V> {
+    let mut item: Box<Node<K, V, M, A>> = Box::new(Node {
+      key,
+      value,
+      size: 1,
+      summary: M::identity(),
+      lazy: None,
+      lchild: None,
+      rchild: None,
+    });
+    SplayMap::update(&mut item);
+    SplayMap::_insert(item, &mut self.root)
+  }
+  /// Looks up a key, restructuring the map according to its strategy.
+  pub fn contains_key<Q>(&mut self, key: &Q) -> bool
+  where
+    K: Borrow<Q>,
+    Q: Ord + ?Sized,
+  {
+    self.access(key).is_some()
+  }
+  pub fn get<Q>(&mut self, key: &Q) -> Option<&V>
+  where
+    K: Borrow<Q>,
+    Q: Ord + ?Sized,
+  {
+    self.access(key).map(|t| &t.value)
+  }
+  /// Removes a key after splaying it to the root, returning its value if it
+  /// was present.
+  pub fn remove<Q>(&mut self, key: &Q) -> Option<V>
+  where
+    K: Borrow<Q>,
+    Q: Ord + ?Sized,
+  {
+    self.remove_entry(key).map(|(_, value)| value)
+  }
+  /// Removes a key, returning the stored key and its value if it was present.
+  pub fn remove_entry<Q>(&mut self, key: &Q) -> Option<(K, V)>
+  where
+    K: Borrow<Q>,
+    Q: Ord + ?Sized,
+  {
+    SplayMap::_delete(key, &mut self.root)
+  }
+  pub fn len(&self) -> usize {
+    SplayMap::size(&self.root)
+  }
+  pub fn is_empty(&self) -> bool {
+    self.root.is_none()
+  }
+  /// Splits the map in two at `key`, returning the entries with keys greater than
+  /// or equal to `key` and keeping the rest.
+  ///
+  /// The node next to the boundary is splayed to the root first, so the split
+  /// itself only detaches one of its subtrees.
+  pub fn split_off<Q>(&mut self, key: &Q) -> SplayMap<K, V, M, A>
+  where
+    K: Borrow<Q>,
+    Q: Ord + ?Sized,
+  {
+    self.splay_last(|k| key.cmp(k.borrow()));
+    let mut root = match self.root.take() {
+      None => return SplayMap::new(),
+      Some(root) => root,
+    };
+    if root.key.borrow() < key {
+      let right = root.rchild.take();
+      SplayMap::update(&mut root);
+      self.root = Some(root);
+      SplayMap {
+        root: right,
+        ..SplayMap::with_strategy(self.strategy)
+      }
+    } else {
+      self.root = root.lchild.take();
+      SplayMap::update(&mut root);
+      SplayMap {
+        root: Some(root),
+        ..SplayMap::with_strategy(self.strategy)
+      }
+    }
+  }
+  /// Moves all entries of `other` into `self`, leaving `other` empty.
+  ///
+  /// When the key ranges of both maps do not overlap, the maximum of the lower
+  /// map is splayed to its root and the other map is hung off its right side.
+  /// Otherwise the entries of `other` are inserted one by one and overwrite the
+  /// values of equal keys.
+  pub fn append(&mut self, other: &mut SplayMap<K, V, M, A>) {
+    let disjoint = match (
+      SplayMap::max_key(&self.root),
+      SplayMap::min_key(&other.root),
+    ) {
+      (Some(max), Some(min)) => max < min,
+      _ => true,
+    };
+    if disjoint {
+      self.root = SplayMap::join(self.root.take(), other.root.take());
+      return;
+    }
+    let disjoint = match (
+      SplayMap::max_key(&other.root),
+      SplayMap::min_key(&self.root),
+    ) {
+      (Some(max), Some(min)) => max < min,
+      _ => true,
+    };
+    if disjoint {
+      self.root = SplayMap::join(other.root.take(), self.root.take());
+      return;
+    }
+    let entries = other.root.take();
+    let len = SplayMap::size(&entries);
+    for (key, value) in IntoInOrder::new(entries, len) {
+      self.insert(key, value);
+    }
+  }
+  /// Returns the number of keys less than `key`.
+  pub fn rank<Q>(&self, key: &Q) -> usize
+  where
+    K: Borrow<Q>,
+    Q: Ord + ?Sized,
+  {
+    let mut rank = 0;
+    let mut tree = &self.root;
+    while let Some(t) = tree {
+      match key.cmp(t.key.borrow()) {
+        Ordering::Less => tree = &t.lchild,
+        Ordering::Equal => return rank + SplayMap::size(&t.lchild),
+        Ordering::Greater => {
+          rank += SplayMap::size(&t.lchild) + 1;
+          tree = &t.rchild;
+        }
+      }
+    }
+    rank
+  }
+  /// Returns the aggregate of the values whose keys lie in `range`, combined in
+  /// ascending key order.
+  ///
+  /// The range is cut out by splaying its boundaries to the root, read off the
+  /// root of the middle part, and joined back in.
+  pub fn fold<Q, R>(&mut self, range: R) -> M::Summary
+  where
+    K: Borrow<Q>,
+    Q: Ord + ?Sized,
+    R: RangeBounds<Q>,
+  {
+    let (left, rest) = SplayMap::split_by(self.root.take(), |k| !above_start(&range, k));
+    let (middle, right) = SplayMap::split_by(rest, |k| below_end(&range, k));
+    let summary = SplayMap::summary(&middle);
+    self.root = SplayMap::join(left, SplayMap::join(middle, right));
+    summary
+  }
+  /// Applies `action` to every value whose key lies in `range`.
+  ///
+  /// The range is cut out as in `fold` and only the root of the middle part is
+  /// tagged. The action reaches the other nodes as later splays pass them.
+  pub fn apply<Q, R>(&mut self, range: R, action: A)
+  where
+    K: Borrow<Q>,
+    Q: Ord + ?Sized,
+    R: RangeBounds<Q>,
+  {
+    let (left, rest) = SplayMap::split_by(self.root.take(), |k| !above_start(&range, k));
+    let (mut middle, right) = SplayMap::split_by(rest, |k| below_end(&range, k));
+    if let Some(m) = middle.as_mut() {
+      SplayMap::tag(m, &action);
+    }
+    self.root = SplayMap::join(left, SplayMap::join(middle, right));
+  }
+  #[cfg(test)]
+  fn _print(tree: &Node<K, V, M, A>) -> String {
+    let mut message = String::from("[");
+    message = [message, format!("{:?}(", tree.key)].concat();
+    match &tree.lchild {
+      None => {}
+      Some(l) => {
+        message = [message, SplayMap::_print(l)].concat();
+      }
+    }
+    message = [message, String::from(")(")].concat();
+    match &tree.rchild {
+      None => {}
+      Some(r) => {
+        message = [message, SplayMap::_print(r)].concat();
+      }
+    }
+    [message, String::from(")]")].concat()
+  }
+  #[cfg(test)]
+  pub fn print(&self) -> String {
+    SplayMap::_print(self.root.as_ref().unwrap())
+  }
+  #[cfg(test)]
+  fn _invalid_size(tree: &Option<Box<Node<K, V, M, A>>>) -> bool {
+    match tree {
+      None => false,
+      Some(t) => {
+        t.size != 1 + SplayMap::size(&t.lchild) + SplayMap::size(&t.rchild)
+          || SplayMap::_invalid_size(&t.lchild)
+          || SplayMap::_invalid_size(&t.rchild)
+      }
+    }
+  }
+  #[cfg(test)]
+  pub fn invalid_size(&self) -> bool {
+    SplayMap::_invalid_size(&self.root)
+  }
+}
+
+impl<K: Ord + Debug, V, M: Monoid<V>, A: Action<V, M>> Default for SplayMap<K, V, M, A> {
+  fn default() -> SplayMap<K, V, M, A> {
+    SplayMap::new()
+  }
+}
+
+impl<K: Ord + Debug, V, M: Monoid<V>> SplayMap<K, V, M> {
+  // Values can only be read in place when no action is pending anywhere in
+  // the tree. Maps with an action go through `get` or `into_iter`.
+  /// Returns the entry with the `index`-th smallest key, counting from zero.
+  pub fn select(&self, index: usize) -> Option<(&K, &V)> {
+    let mut index = index;
+    let mut tree = &self.root;
+    while let Some(t) = tree {
+      let lsize = SplayMap::size(&t.lchild);
+      match index.cmp(&lsize) {
+        Ordering::Less => tree = &t.lchild,
+        Ordering::Equal => return Some((&t.key, &t.value)),
+        Ordering::Greater => {
+          index -= lsize + 1;
+          tree = &t.rchild;
+        }
+      }
+    }
+    None
+  }
+  /// Returns an iterator over the entries in ascending key order.
+  pub fn iter(&self) -> MapIter<'_, K, V, M> {
+    MapIter {
+      walk: InOrder::new(&self.root, self.len()),
+    }
+  }
+  /// Returns an iterator over the entries whose keys lie in `range`, in ascending key order.
+  pub fn range<Q, R>(&self, range: R) -> MapRange<'_, K, V, M>
+  where
+    K: Borrow<Q>,
+    Q: Ord + ?Sized,
+    R: RangeBounds<Q>,
+  {
+    MapRange {
+      walk: RangeInOrder::new(&self.root, range),
+    }
+  }
+  /// Like `range`, but first splays the end and then the start key of `range`
+  /// towards the root, so that scans over nearby ranges start close to the root.
+  pub fn splay_range<Q, R>(&mut self, range: R) -> MapRange<'_, K, V, M>
+  where
+    K: Borrow<Q>,
+    Q: Ord + ?Sized,
+    R: RangeBounds<Q>,
+  {
+    if let Bound::Included(end) | Bound::Excluded(end) = range.end_bound() {
+      self.splay_root(end);
+    }
+    if let Bound::Included(start) | Bound::Excluded(start) = range.start_bound() {
+      self.splay_root(start);
+    }
+    self.range(range)
+  }
+  #[cfg(test)]
+  pub fn invalid_key(&self) -> bool {
+    let seq: Vec<&K> = self.iter().map(|(key, _)| key).collect();
+    seq.windows(2).any(|w| w[0] >= w[1])
+  }
+}
+
+impl<K: Ord + Debug, V> SplayMap<K, V> {
+  /// Returns a mutable reference to the value of `key`, restructuring the map
+  /// according to its strategy. Only maps without an aggregate hand these out,
+  /// as changing a value in place would leave the aggregates above it stale.
+  pub fn get_mut<Q>(&mut self, key: &Q) -> Option<&mut V>
+  where
+    K: Borrow<Q>,
+    Q: Ord + ?Sized,
+  {
+    self.access(key).map(|t| &mut t.value)
+  }
+}
+
+impl<K: Ord + Debug> Splay<K> {
+  pub fn new() -> Splay<K> {
+    Splay {
+      map: SplayMap::new(),
+    }
+  }
+  /// Makes an empty set that restructures itself on lookups according to
+  /// `strategy`.
+  ///
+  /// # Panics
+  ///
+  /// Panics if `strategy` is `EveryKth(0)`.
+  pub fn with_strategy(strategy: SplayStrategy) -> Splay<K> {
+    Splay {
+      map: SplayMap::with_strategy(strategy),
+    }
+  }
+  /// Builds a perfectly balanced set from keys in strictly ascending order in
+  /// linear time.
+  ///
+  /// # Panics
+  ///
+  /// Panics if the keys are not strictly ascending.
+  pub fn from_sorted_iter<I>(iter: I) -> Splay<K>
+  where
+    I: IntoIterator<Item = K>,
+  {
+    Splay {
+      map: SplayMap::from_sorted_iter(iter.into_iter().map(|key| (key, ()))),
+    }
+  }
+  pub fn len(&self) -> usize {
+    self.map.len()
+  }
+  pub fn is_empty(&self) -> bool {
+    self.map.is_empty()
+  }
+  /// Splits the set in two at `key`, returning the keys greater than or equal to
+  /// `key` and keeping the rest.
+  pub fn split_off<Q>(&mut self, key: &Q) -> Splay<K>
+  where
+    K: Borrow<Q>,
+    Q: Ord + ?Sized,
+  {
+    Splay {
+      map: self.map.split_off(key),
+    }
+  }
+  /// Moves all keys of `other` into `self`, leaving `other` empty.
+  pub fn append(&mut self, other: &mut Splay<K>) {
+    self.map.append(&mut other.map);
+  }
+  /// Returns a lazy iterator over the keys in `self` or `other`.
+  pub fn union<'a>(&'a self, other: &'a Splay<K>) -> Union<'a, K> {
+    Union {
+      a: self.iter().peekable(),
+      b: other.iter().peekable(),
+    }
+  }
+  /// Returns a lazy iterator over the keys in both `self` and `other`.
+  pub fn intersection<'a>(&'a self, other: &'a Splay<K>) -> Intersection<'a, K> {
+    Intersection {
+      a: self.iter().peekable(),
+      b: other.iter().peekable(),
+    }
+  }
+  /// Returns a lazy iterator over the keys in `self` but not in `other`.
+  pub fn difference<'a>(&'a self, other: &'a Splay<K>) -> Difference<'a, K> {
+    Difference {
+      a: self.iter().peekable(),
+      b: other.iter().peekable(),
+    }
+  }
+  /// Returns a lazy iterator over the keys in exactly one of `self` and `other`.
+  pub fn symmetric_difference<'a>(&'a self, other: &'a Splay<K>) -> SymmetricDifference<'a, K> {
+    SymmetricDifference {
+      a: self.iter().peekable(),
+      b: other.iter().peekable(),
+    }
+  }
+  /// Returns the `index`-th smallest key, counting from zero.
+  pub fn select(&self, index: usize) -> Option<&K> {
+    self.map.select(index).map(|(key, _)| key)
+  }
+  /// Returns the number of keys less than `key`.
+  pub fn rank<Q>(&self, key: &Q) -> usize
+  where
+    K: Borrow<Q>,
+    Q: Ord + ?Sized,
+  {
+    self.map.rank(key)
+  }
+  /// Returns an iterator over the keys in ascending order.
+  pub fn iter(&self) -> Iter<'_, K> {
+    Iter {
+      iter: self.map.iter(),
+    }
+  }
+  /// Returns an iterator over the keys that lie in `range`, in ascending order.
+  pub fn range<Q, R>(&self, range: R) -> Range<'_, K>
+  where
+    K: Borrow<Q>,
+    Q: Ord + ?Sized,
+    R: RangeBounds<Q>,
+  {
+    Range {
+      iter: self.map.range(range),
+    }
+  }
+  /// Like `range`, but splays the boundaries of `range` first; see `SplayMap::splay_range`.
+  pub fn splay_range<Q, R>(&mut self, range: R) -> Range<'_, K>
+  where
+    K: Borrow<Q>,
+    Q: Ord + ?Sized,
+    R: RangeBounds<Q>,
+  {
+    Range {
+      iter: self.map.splay_range(range),
+    }
+  }
+
+  #[cfg(test)]
+  pub fn print(&self) -> String {
+    self.map.print()
+  }
+  #[cfg(test)]
+  pub fn invalid_size(&self) -> bool {
+    self.map.invalid_size()
+  }
+  #[cfg(test)]
+  pub fn invalid_key(&self) -> bool {
+    self.map.invalid_key()
+  }
+}
+
+impl<K: Ord + Debug> Default for Splay<K> {
+  fn default() -> Splay<K> {
+    Splay::new()
+  }
+}
+
+impl<K: Ord + Debug> BST<K> for Splay<K> {
+  fn insert(&mut self, key: K) -> bool {
+    self.map.insert(key, ()).is_none()
+  }
+  fn find<Q>(&mut self, key: &Q) -> bool
+  where
+    K: Borrow<Q>,
+    Q: Ord + ?Sized,
+  {
+    self.map.contains_key(key)
+  }
+  fn delete<Q>(&mut self, key: &Q) -> Option<K>
+  where
+    K: Borrow<Q>,
+    Q: Ord + ?Sized,
+  {
+    self.map.remove_entry(key).map(|(key, _)| key)
+  }
+}
+
+// Dropping the nodes recursively would overflow the stack on deep trees.
+impl<K: Ord + Debug, V, M: Monoid<V>, A: Action<V, M>> Drop for SplayMap<K, V, M, A> {
+  fn drop(&mut self) {
+    drop_tree(self.root.take());
+  }
+}
+
+impl<K: Ord, V, M: Monoid<V>, A: Action<V, M>> TreeNode for Node<K, V, M, A> {
+  type Key = K;
+  fn key(&self) -> &K {
+    &self.key
+  }
+  fn lchild(&self) -> &Link<K, V, M, A> {
+    &self.lchild
+  }
+  fn rchild(&self) -> &Link<K, V, M, A> {
+    &self.rchild
+  }
+  fn lchild_mut(&mut self) -> &mut Link<K, V, M, A> {
+    &mut self.lchild
+  }
+  fn rchild_mut(&mut self) -> &mut Link<K, V, M, A> {
+    &mut self.rchild
+  }
+}
+
+impl<K: Ord + Debug, V, M: Monoid<V>, A: Action<V, M>> EntryNode for Node<K, V, M, A> {
+  type Value = V;
+  fn push(&mut self) {
+    SplayMap::push(self);
+  }
+  fn into_entry(self) -> (K, V) {
+    (self.key, self.value)
+  }
+}
+
+/// An in-order iterator over the entries of a `SplayMap`.
+pub struct MapIter<'a, K: Ord, V, M: Monoid<V> = ()> {
+  walk: InOrder<'a, Node<K, V, M, ()>>,
+}
+
+impl<'a, K: Ord, V, M: Monoid<V>> Iterator for MapIter<'a, K, V, M> {
+  type Item = (&'a K, &'a V);
+
+  fn next(&mut self) -> Option<(&'a K, &'a V)> {
+    self.walk.next().map(|t| (&t.key, &t.value))
+  }
+  fn size_hint(&self) -> (usize, Option<usize>) {
+    self.walk.size_hint()
+  }
+}
+
+impl<'a, K: Ord, V, M: Monoid<V>> DoubleEndedIterator for MapIter<'a, K, V, M> {
+  fn next_back(&mut self) -> Option<(&'a K, &'a V)> {
+    self.walk.next_back().map(|t| (&t.key, &t.value))
+  }
+}
+
+impl<'a, K: Ord, V, M: Monoid<V>> ExactSizeIterator for MapIter<'a, K, V, M> {}
+
+/// An owning in-order iterator over the entries of a `SplayMap`.
+///
+/// Entries are moved out as the iteration reaches them, so nothing is collected
+/// up front.
+pub struct MapIntoIter<K: Ord + Debug, V, M: Monoid<V> = (), A: Action<V, M> = ()> {
+  walk: IntoInOrder<Node<K, V, M, A>>,
+}
+
+impl<K: Ord + Debug, V, M: Monoid<V>, A: Action<V, M>> Iterator for MapIntoIter<K, V, M, A> {
+  type Item = (K, V);
+
+  fn next(&mut self) -> Option<(K, V)> {
+    self.walk.next()
+  }
+  fn size_hint(&self) -> (usize, Option<usize>) {
+    self.walk.size_hint()
+  }
+}
+
+impl<K: Ord + Debug, V, M: Monoid<V>, A: Action<V, M>> DoubleEndedIterator
+  for MapIntoIter<K, V, M, A>
+{
+  fn next_back(&mut self) -> Option<(K, V)> {
+    self.walk.next_back()
+  }
+}
+
+impl<K: Ord + Debug, V, M: Monoid<V>, A: Action<V, M>> ExactSizeIterator
+  for MapIntoIter<K, V, M, A>
+{
+}
+
+/// An in-order iterator over the keys of a `Splay`.
+pub struct Iter<'a, K: Ord> {
+  iter: MapIter<'a, K, ()>,
+}
+
+impl<'a, K: Ord> Iterator for Iter<'a, K> {
+  type Item = &'a K;
+
+  fn next(&mut self) -> Option<&'a K> {
+    self.iter.next().map(|(key, _)| key)
+  }
+  fn size_hint(&self) -> (usize, Option<usize>) {
+    self.iter.size_hint()
+  }
+}
+
+impl<'a, K: Ord> DoubleEndedIterator for Iter<'a, K> {
+  fn next_back(&mut self) -> Option<&'a K> {
+    self.iter.next_back().map(|(key, _)| key)
+  }
+}
+
+impl<'a, K: Ord> ExactSizeIterator for Iter<'a, K> {}
+
+/// An owning in-order iterator over the keys of a `Splay`.
+pub struct IntoIter<K: Ord + Debug> {
+  iter: MapIntoIter<K, ()>,
+}
+
+impl<K: Ord + Debug> Iterator for IntoIter<K> {
+  type Item = K;
+
+  fn next(&mut self) -> Option<K> {
+    self.iter.next().map(|(key, _)| key)
+  }
+  fn size_hint(&self) -> (usize, Option<usize>) {
+    self.iter.size_hint()
+  }
+}
+
+impl<K: Ord + Debug> DoubleEndedIterator for IntoIter<K> {
+  fn next_back(&mut self) -> Option<K> {
+    self.iter.next_back().map(|(key, _)| key)
+  }
+}
+
+impl<K: Ord + Debug> ExactSizeIterator for IntoIter<K> {}
+
+/// An in-order iterator over a sub-range of the entries of a `SplayMap`.
+pub struct MapRange<'a, K: Ord, V, M: Monoid<V> = ()> {
+  walk: RangeInOrder<'a, Node<K, V, M, ()>>,
+}
+
+impl<'a, K: Ord, V, M: Monoid<V>> Iterator for MapRange<'a, K, V, M> {
+  type Item = (&'a K, &'a V);
+
+  fn next(&mut self) -> Option<(&'a K, &'a V)> {
+    self.walk.next().map(|t| (&t.key, &t.value))
+  }
+}
+
+impl<'a, K: Ord, V, M: Monoid<V>> DoubleEndedIterator for MapRange<'a, K, V, M> {
+  fn next_back(&mut self) -> Option<(&'a K, &'a V)> {
+    self.walk.next_back().map(|t| (&t.key, &t.value))
+  }
+}
+
+/// An in-order iterator over a sub-range of the keys of a `Splay`.
+pub struct Range<'a, K: Ord> {
+  iter: MapRange<'a, K, ()>,
+}
+
+impl<'a, K: Ord> Iterator for Range<'a, K> {
+  type Item = &'a K;
+
+  fn next(&mut self) -> Option<&'a K> {
+    self.iter.next().map(|(key, _)| key)
+  }
+}
+
+impl<'a, K: Ord> DoubleEndedIterator for Range<'a, K> {
+  fn next_back(&mut self) -> Option<&'a K> {
+    self.iter.next_back().map(|(key, _)| key)
+  }
+}
+
+/// A lazy iterator over the union of two `Splay` sets, in ascending order.
+pub struct Union<'a, K: Ord> {
+  a: Peekable<Iter<'a, K>>,
+  b: Peekable<Iter<'a, K>>,
+}
+
+impl<'a, K: Ord> Iterator for Union<'a, K> {
+  type Item = &'a K;
+
+  fn next(&mut self) -> Option<&'a K> {
+    match (self.a.peek(), self.b.peek()) {
+      (None, _) => self.b.next(),
+      (_, None) => self.a.next(),
+      (Some(a), Some(b)) => match a.cmp(b) {
+        Ordering::Less => self.a.next(),
+        Ordering::Greater => self.b.next(),
+        Ordering::Equal => {
+          self.b.next();
+          self.a.next()
+        }
+      },
+    }
   }
-  fn _insert(item: Box<Node<K>>, tree: &mut Option<Box<Node<K>>>) {
-    match tree {
-      None => {
-        *tree = Some(item);
-      }
-      Some(t) => match item.key.cmp(&t.key) {
+}
+
+/// A lazy iterator over the intersection of two `Splay` sets, in ascending order.
+pub struct Intersection<'a, K: Ord> {
+  a: Peekable<Iter<'a, K>>,
+  b: Peekable<Iter<'a, K>>,
+}
+
+impl<'a, K: Ord> Iterator for Intersection<'a, K> {
+  type Item = &'a K;
+
+  fn next(&mut self) -> Option<&'a K> {
+    loop {
+      match a_cmp_b(&mut self.a, &mut self.b)? {
         Ordering::Less => {
-          Splay::_insert(item, &mut t.lchild);
+          self.a.next();
         }
         Ordering::Greater => {
-          Splay::_insert(item, &mut t.rchild);
+          self.b.next();
         }
-        Ordering::Equal => {}
-      },
+        Ordering::Equal => {
+          self.b.next();
+          return self.a.next();
+        }
+      }
     }
   }
+}
 
-  fn splay(key: &K, tree: &mut Option<Box<Node<K>>>) -> bool {
-    match tree {
-      None => false,
-      Some(t) => match key.cmp(&t.key) {
-        Ordering::Less => match &mut t.lchild {
-          None => false,
-          Some(l) => match key.cmp(&l.key) {
-            Ordering::Less => match &mut l.lchild {
-              None => false,
-              Some(ll) => match key.cmp(&ll.key) {
-                Ordering::Less => {
-                  if !Splay::splay(key, &mut t.lchild) {
-                    return false;
-                  }
-                  Splay::splay(key, tree)
-                }
-                Ordering::Greater => {
-                  if !Splay::splay(key, &mut t.lchild) {
-                    return false;
-                  }
-                  Splay::splay(key, tree)
-                }
-                Ordering::Equal => {
-                  // zig-zig
-                  //      t
-                  //     /
-                  //    l
-                  //   /
-                  //  ll(target)
-                  Splay::rotate_right(t);
-                  Splay::rotate_right(t);
-                  true
-                }
-              },
-            },
-            Ordering::Greater => match &mut l.rchild {
-              None => false,
-              Some(lr) => match key.cmp(&lr.key) {
-                Ordering::Less => {
-                  if !Splay::splay(key, &mut t.lchild) {
-                    return false;
-                  }
-                  Splay::splay(key, tree)
-                }
-                Ordering::Greater => {
-                  if !Splay::splay(key, &mut t.lchild) {
-                    return false;
-                  }
-                  Splay::splay(key, tree)
-                }
-                Ordering::Equal => {
-                  // zig-zag
-                  //      t
-                  //     /
-                  //    l
-                  //     \
-                  //      lr(target)
-                  Splay::rotate_left(l);
-                  Splay::rotate_right(t);
-                  true
-                }
-              },
-            },
-            Ordering::Equal => true,
-          },
-        },
-        Ordering::Greater => match &mut t.rchild {
-          None => false,
-          Some(r) => match key.cmp(&r.key) {
-            Ordering::Less => match &mut r.lchild {
-              None => false,
-              Some(rl) => match key.cmp(&rl.key) {
-                Ordering::Less => {
-                  if !Splay::splay(key, &mut t.rchild) {
-                    return false;
-                  }
-                  Splay::splay(key, tree)
-                }
-                Ordering::Greater => {
-                  if !Splay::splay(key, &mut t.rchild) {
-                    return false;
-                  }
-                  Splay::splay(key, tree)
-                }
-                Ordering::Equal => {
-                  // zig-zag
-                  //      t
-                  //       \
-                  //        r
-                  //       /
-                  //      rl(target)
-                  Splay::rotate_right(r);
-                  Splay::rotate_left(t);
-                  true
-                }
-              },
-            },
-            Ordering::Greater => match &mut r.rchild {
-              None => false,
-              Some(rr) => match key.cmp(&rr.key) {
-                Ordering::Less => {
-                  if !Splay::splay(key, &mut t.rchild) {
-                    return false;
-                  }
-                  Splay::splay(key, tree)
-                }
-                Ordering::Greater => {
-                  if !Splay::splay(key, &mut t.rchild) {
-                    return false;
-                  }
-                  Splay::splay(key, tree)
-                }
-                Ordering::Equal => {
-                  // zig-zig
-                  //      t
-                  //       \
-                  //        r
-                  //         \
-                  //          rr(target)
-                  Splay::rotate_left(t);
-                  Splay::rotate_left(t);
-                  true
-                }
-              },
-            },
-            Ordering::Equal => true,
-          },
-        },
-        Ordering::Equal => true,
-      },
+/// A lazy iterator over the keys of one `Splay` set that are not in another, in
+/// ascending order.
+pub struct Difference<'a, K: Ord> {
+  a: Peekable<Iter<'a, K>>,
+  b: Peekable<Iter<'a, K>>,
+}
+
+impl<'a, K: Ord> Iterator for Difference<'a, K> {
+  type Item = &'a K;
+
+  fn next(&mut self) -> Option<&'a K> {
+    loop {
+      match a_cmp_b(&mut self.a, &mut self.b) {
+        None => return self.a.next(),
+        Some(Ordering::Less) => return self.a.next(),
+        Some(Ordering::Greater) => {
+          self.b.next();
+        }
+        Some(Ordering::Equal) => {
+          self.a.next();
+          self.b.next();
+        }
+      }
     }
   }
+}
 
-  fn root_delete(tree: &mut Option<Box<Node<K>>>) {
-    match tree {
-      None => {}
-      Some(t) => match &t.lchild {
-        None => match &t.rchild {
-          None => *tree = None,
-          Some(_) => {
-            Splay::rotate_left(t);
-            Splay::root_delete(&mut t.lchild);
-          }
-        },
-        Some(_) => match &t.rchild {
-          None => {
-            Splay::rotate_right(t);
-            Splay::root_delete(&mut t.rchild);
-          }
-          Some(_) => {
-            Splay::rotate_left(t);
-            Splay::root_delete(&mut t.lchild);
+/// A lazy iterator over the keys in exactly one of two `Splay` sets, in
+/// ascending order.
+pub struct SymmetricDifference<'a, K: Ord> {
+  a: Peekable<Iter<'a, K>>,
+  b: Peekable<Iter<'a, K>>,
+}
+
+impl<'a, K: Ord> Iterator for SymmetricDifference<'a, K> {
+  type Item = &'a K;
+
+  fn next(&mut self) -> Option<&'a K> {
+    loop {
+      match (self.a.peek(), self.b.peek()) {
+        (None, _) => return self.b.next(),
+        (_, None) => return self.a.next(),
+        (Some(a), Some(b)) => match a.cmp(b) {
+          Ordering::Less => return self.a.next(),
+          Ordering::Greater => return self.b.next(),
+          Ordering::Equal => {
+            self.a.next();
+            self.b.next();
           }
         },
-      },
-    }
-  }
-  fn _delete(key: &K, tree: &mut Option<Box<Node<K>>>) {
-    match tree {
-      None => {}
-      Some(t) => match key.cmp(&t.key) {
-        Ordering::Less => Splay::_delete(key, &mut t.lchild),
-        Ordering::Greater => Splay::_delete(key, &mut t.rchild),
-        Ordering::Equal => Splay::root_delete(tree),
-      },
-    }
-  }
-  #[cfg(test)]
-  fn _print(tree: &Box<Node<K>>) -> String {
-    let mut message = String::from("[");
-    message = [message, format!("{:?}(", tree.key)].concat();
-    match &tree.lchild {
-      None => {}
-      Some(l) => {
-        message = [message, Splay::_print(&l)].concat();
-      }
-    }
-    message = [message, String::from(")(")].concat();
-    match &tree.rchild {
-      None => {}
-      Some(r) => {
-        message = [message, Splay::_print(&r)].concat();
       }
     }
-    [message, String::from(")]")].concat()
   }
-  #[cfg(test)]
-  pub fn print(&self) -> String {
-    Splay::_print(self.root.as_ref().unwrap())
+}
+
+/// Compares the next keys of both iterators, or returns `None` if either is exhausted.
+fn a_cmp_b<'a, K: Ord>(
+  a: &mut Peekable<Iter<'a, K>>,
+  b: &mut Peekable<Iter<'a, K>>,
+) -> Option<Ordering> {
+  match (a.peek(), b.peek()) {
+    (Some(a), Some(b)) => Some(a.cmp(b)),
+    _ => None,
   }
-  #[cfg(test)]
-  fn get_seq_in_order(tree: &Box<Node<K>>, seq: &mut Vec<K>)
-  where
-    K: Copy,
-  {
-    match &tree.lchild {
-      None => {}
-      Some(l) => {
-        Splay::get_seq_in_order(l, seq);
-      }
-    }
-    seq.push(tree.key);
-    match &tree.rchild {
-      None => {}
-      Some(r) => {
-        Splay::get_seq_in_order(r, seq);
-      }
+}
+
+impl<'a, K: Ord + Debug, V, M: Monoid<V>> IntoIterator for &'a SplayMap<K, V, M> {
+  type Item = (&'a K, &'a V);
+  type IntoIter = MapIter<'a, K, V, M>;
+
+  fn into_iter(self) -> MapIter<'a, K, V, M> {
+    self.iter()
+  }
+}
+
+impl<K: Ord + Debug, V, M: Monoid<V>, A: Action<V, M>> IntoIterator for SplayMap<K, V, M, A> {
+  type Item = (K, V);
+  type IntoIter = MapIntoIter<K, V, M, A>;
+
+  fn into_iter(mut self) -> MapIntoIter<K, V, M, A> {
+    let len = self.len();
+    MapIntoIter {
+      walk: IntoInOrder::new(self.root.take(), len),
     }
   }
-  #[cfg(test)]
-  pub fn invalid_key(&self) -> bool
-  where
-    K: Copy,
-  {
-    let mut seq: Vec<K> = Vec::new();
-    Splay::get_seq_in_order(self.root.as_ref().unwrap(), &mut seq);
-    let mut ng = false;
-    for i in 1..seq.len() - 1 {
-      ng |= seq[i] >= seq[i + 1];
+}
+
+impl<'a, K: Ord + Debug> IntoIterator for &'a Splay<K> {
+  type Item = &'a K;
+  type IntoIter = Iter<'a, K>;
+
+  fn into_iter(self) -> Iter<'a, K> {
+    self.iter()
+  }
+}
+
+impl<K: Ord + Debug> IntoIterator for Splay<K> {
+  type Item = K;
+  type IntoIter = IntoIter<K>;
+
+  fn into_iter(self) -> IntoIter<K> {
+    IntoIter {
+      iter: self.map.into_iter(),
     }
-    ng
   }
 }
 
-impl<K: Ord + Debug> BST<K> for Splay<K> {
-  fn insert(&mut self, key: K) {
-    let item: Box<Node<K>> = Box::new(Node {
-      key,
-      lchild: None,
-      rchild: None,
-    });
-    Splay::_insert(item, &mut self.root);
+/// Sorts the entries and builds the map with `from_sorted_iter`.
+/// Later entries win among equal keys.
+impl<K: Ord + Debug, V, M: Monoid<V>, A: Action<V, M>> FromIterator<(K, V)>
+  for SplayMap<K, V, M, A>
+{
+  fn from_iter<I: IntoIterator<Item = (K, V)>>(iter: I) -> SplayMap<K, V, M, A> {
+    SplayMap::from_sorted_iter(sorted_entries(iter))
   }
-  fn find(&mut self, key: K) -> bool {
-    if !Splay::splay(&key, &mut self.root) {
-      return false;
+}
+
+impl<K: Ord + Debug, V, M: Monoid<V>, A: Action<V, M>> Extend<(K, V)> for SplayMap<K, V, M, A> {
+  fn extend<I: IntoIterator<Item = (K, V)>>(&mut self, iter: I) {
+    for (key, value) in iter {
+      self.insert(key, value);
     }
-    match key.cmp(&self.root.as_ref().unwrap().key) {
-      Ordering::Equal => {}
-      Ordering::Less => {
-        Splay::rotate_right(&mut self.root.as_mut().unwrap());
-      }
-      Ordering::Greater => {
-        Splay::rotate_left(&mut self.root.as_mut().unwrap());
-      }
+  }
+}
+
+impl<K: Ord + Debug> FromIterator<K> for Splay<K> {
+  fn from_iter<I: IntoIterator<Item = K>>(iter: I) -> Splay<K> {
+    Splay {
+      map: iter.into_iter().map(|key| (key, ())).collect(),
     }
-    true
   }
-  fn delete(&mut self, key: K) {
-    Splay::_delete(&key, &mut self.root);
+}
+
+impl<K: Ord + Debug> Extend<K> for Splay<K> {
+  fn extend<I: IntoIterator<Item = K>>(&mut self, iter: I) {
+    for key in iter {
+      self.insert(key);
+    }
   }
 }
 
 #[cfg(test)]
 mod tests {
+  use super::Bound;
+  use super::Monoid;
   use super::Splay;
+  use super::SplayMap;
+  use super::SplayStrategy;
   use super::BST;
+  use crate::{Max, Min, Sum, Update};
   use assert_str::assert_str_eq;
 
   #[test]
@@ -326,7 +1362,7 @@ mod tests {
     let mut tree: Splay<u64> = Splay::new();
     tree.insert(10);
     assert_str_eq!(tree.print(), "[10()()]");
-    assert_eq!(tree.invalid_key(), false);
+    assert!(!tree.invalid_key());
   }
   #[test]
   fn test_insert_2() {
@@ -334,27 +1370,28 @@ mod tests {
     tree.insert(10);
     assert_str_eq!(tree.print(), "[10()()]");
     tree.insert(50);
-    assert_str_eq!(tree.print(), "[10()([50()()])]");
-    assert_eq!(tree.invalid_key(), false);
+    assert_str_eq!(tree.print(), "[50([10()()])()]");
+    assert!(!tree.invalid_key());
     tree.insert(5);
-    assert_str_eq!(tree.print(), "[10([5()()])([50()()])]");
-    assert_eq!(tree.invalid_key(), false);
+    assert_str_eq!(tree.print(), "[5()([10()([50()()])])]");
+    assert!(!tree.invalid_key());
   }
   #[test]
   fn test_find() {
     let mut tree: Splay<u64> = Splay::new();
     tree.insert(10);
     tree.insert(50);
-    assert_eq!(tree.find(10), true);
+    assert!(tree.find(&10));
     assert_str_eq!(tree.print(), "[10()([50()()])]");
-    assert_eq!(tree.find(50), true);
+    assert!(tree.find(&50));
     assert_str_eq!(tree.print(), "[50([10()()])()]");
     tree.insert(5);
-    assert_str_eq!(tree.print(), "[50([10([5()()])()])()]");
-    assert_eq!(tree.find(5), true);
     assert_str_eq!(tree.print(), "[5()([10()([50()()])])]");
-    assert_eq!(tree.find(100), false);
+    assert!(tree.find(&5));
     assert_str_eq!(tree.print(), "[5()([10()([50()()])])]");
+    // A miss splays the last node visited.
+    assert!(!tree.find(&100));
+    assert_str_eq!(tree.print(), "[50([10([5()()])()])()]");
   }
   #[test]
   fn test_find_2() {
@@ -367,15 +1404,15 @@ mod tests {
     tree.insert(100);
     assert_str_eq!(
       tree.print(),
-      "[10([5()()])([50()([200([100()()])([400()()])])])]"
+      "[100([50([10([5()()])()])()])([200()([400()()])])]"
     );
-    assert_eq!(tree.invalid_key(), false);
-    assert_eq!(tree.find(200), true);
+    assert!(!tree.invalid_key());
+    assert!(tree.find(&200));
     assert_str_eq!(
       tree.print(),
-      "[200([50([10([5()()])()])([100()()])])([400()()])]"
+      "[200([100([50([10([5()()])()])()])()])([400()()])]"
     );
-    assert_eq!(tree.invalid_key(), false);
+    assert!(!tree.invalid_key());
   }
   #[test]
   fn test_delete() {
@@ -386,13 +1423,649 @@ mod tests {
     tree.insert(200);
     tree.insert(400);
     tree.insert(100);
-    tree.delete(200);
-    assert_str_eq!(tree.print(), "[10([5()()])([50()([400([100()()])()])])]");
-    assert_eq!(tree.find(200), false);
-    assert_eq!(tree.invalid_key(), false);
-    tree.delete(10);
-    assert_str_eq!(tree.print(), "[50([5()()])([400([100()()])()])]");
-    assert_eq!(tree.find(10), false);
-    assert_eq!(tree.invalid_key(), false);
+    tree.delete(&200);
+    assert_str_eq!(tree.print(), "[100([50([10([5()()])()])()])([400()()])]");
+    assert!(!tree.find(&200));
+    assert!(!tree.invalid_key());
+    tree.delete(&10);
+    assert_str_eq!(tree.print(), "[5()([100([50()()])([400()()])])]");
+    assert!(!tree.find(&10));
+    assert!(!tree.invalid_key());
+  }
+
+  #[test]
+  fn test_map_insert() {
+    let mut map: SplayMap<u64, &str> = SplayMap::new();
+    assert_eq!(map.insert(10, "a"), None);
+    assert_eq!(map.insert(50, "b"), None);
+    assert_eq!(map.insert(10, "c"), Some("a"));
+    assert_str_eq!(map.print(), "[10()([50()()])]");
+    assert_eq!(map.get(&50), Some(&"b"));
+    assert_str_eq!(map.print(), "[50([10()()])()]");
+    assert_eq!(map.get(&10), Some(&"c"));
+    assert_eq!(map.get(&7), None);
+  }
+  #[test]
+  fn test_map_get_mut() {
+    let mut map: SplayMap<u64, u64> = SplayMap::new();
+    map.insert(10, 1);
+    map.insert(50, 2);
+    *map.get_mut(&50).unwrap() += 40;
+    assert_eq!(map.get(&50), Some(&42));
+    assert_eq!(map.get_mut(&7), None);
+  }
+  #[test]
+  fn test_map_remove() {
+    let mut map: SplayMap<u64, u64> = SplayMap::new();
+    for key in [10, 50, 5, 200, 400, 100].iter() {
+      map.insert(*key, *key * 2);
+    }
+    assert_eq!(map.remove(&200), Some(400));
+    assert_eq!(map.remove(&200), None);
+    assert!(!map.contains_key(&200));
+    assert_eq!(map.get(&400), Some(&800));
+    assert!(!map.invalid_key());
+  }
+  #[test]
+  fn test_find_borrowed() {
+    let mut tree: Splay<String> = Splay::new();
+    tree.insert(String::from("banana"));
+    tree.insert(String::from("apple"));
+    tree.insert(String::from("cherry"));
+    assert!(tree.find("apple"));
+    assert!(!tree.find("durian"));
+    tree.delete("apple");
+    assert!(!tree.find("apple"));
+    assert!(tree.find("cherry"));
+  }
+  #[test]
+  fn test_insert_delete_result() {
+    let mut tree: Splay<u64> = Splay::new();
+    assert!(tree.insert(10));
+    assert!(tree.insert(50));
+    assert!(!tree.insert(10));
+    assert_eq!(tree.delete(&10), Some(10));
+    assert_eq!(tree.delete(&10), None);
+    assert_eq!(tree.delete(&7), None);
+    assert!(tree.insert(10));
+    assert_eq!(tree.delete(&50), Some(50));
+  }
+  #[test]
+  fn test_iter() {
+    let mut tree: Splay<u64> = Splay::new();
+    assert_eq!(tree.iter().next(), None);
+    for key in [10, 50, 5, 200, 400, 100].iter() {
+      tree.insert(*key);
+    }
+    let keys: Vec<u64> = tree.iter().cloned().collect();
+    assert_eq!(keys, vec![5, 10, 50, 100, 200, 400]);
+    let keys: Vec<u64> = tree.iter().rev().cloned().collect();
+    assert_eq!(keys, vec![400, 200, 100, 50, 10, 5]);
+    let mut iter = tree.iter();
+    assert_eq!(iter.len(), 6);
+    assert_eq!(iter.next(), Some(&5));
+    assert_eq!(iter.next_back(), Some(&400));
+    assert_eq!(iter.next_back(), Some(&200));
+    assert_eq!(iter.len(), 3);
+    assert_eq!(iter.next(), Some(&10));
+    assert_eq!(iter.next(), Some(&50));
+    assert_eq!(iter.next_back(), Some(&100));
+    assert_eq!(iter.next(), None);
+    assert_eq!(iter.next_back(), None);
+    let mut sum = 0;
+    for key in &tree {
+      sum += key;
+    }
+    assert_eq!(sum, 765);
+  }
+  #[test]
+  fn test_into_iter() {
+    let mut tree: Splay<u64> = Splay::new();
+    for key in [10, 50, 5, 200, 400, 100].iter() {
+      tree.insert(*key);
+    }
+    let mut iter = tree.into_iter();
+    assert_eq!(iter.len(), 6);
+    assert_eq!(iter.next_back(), Some(400));
+    let keys: Vec<u64> = iter.collect();
+    assert_eq!(keys, vec![5, 10, 50, 100, 200]);
+  }
+  #[test]
+  fn test_map_iter() {
+    let mut map: SplayMap<u64, &str> = SplayMap::new();
+    map.insert(10, "a");
+    map.insert(5, "b");
+    map.insert(50, "c");
+    let entries: Vec<(&u64, &&str)> = map.iter().collect();
+    assert_eq!(entries, vec![(&5, &"b"), (&10, &"a"), (&50, &"c")]);
+    let entries: Vec<(u64, &str)> = map.into_iter().rev().collect();
+    assert_eq!(entries, vec![(50, "c"), (10, "a"), (5, "b")]);
+  }
+  #[test]
+  fn test_into_iter_both_ends() {
+    let mut tree: Splay<u64> = Splay::new();
+    for i in 0..10_000 {
+      tree.insert(i * 389 % 10_000);
+    }
+    let mut iter = tree.into_iter();
+    for i in 0..2_000 {
+      assert_eq!(iter.next(), Some(i));
+      assert_eq!(iter.next_back(), Some(9_999 - i));
+      assert_eq!(iter.len(), 10_000 - 2 * (i as usize + 1));
+    }
+    let keys: Vec<u64> = iter.rev().collect();
+    assert_eq!(keys, (2_000..8_000).rev().collect::<Vec<u64>>());
+    // A partly consumed iterator drops the rest of the tree without recursing.
+    let mut tree: Splay<u64> = Splay::new();
+    for key in 0..100_000 {
+      tree.insert(key);
+    }
+    let mut iter = tree.into_iter();
+    assert_eq!(iter.next_back(), Some(99_999));
+    assert_eq!(iter.next(), Some(0));
+  }
+  #[test]
+  fn test_into_iter_degenerate() {
+    // Ascending keys leave a left path, so draining it from the back takes
+    // every entry from the bottom of the front stack.
+    let mut tree: Splay<u64> = Splay::new();
+    for key in 0..200_000 {
+      tree.insert(key);
+    }
+    assert!(tree.into_iter().rev().eq((0..200_000).rev()));
+    // Descending keys leave a right path, which the first `next_back` moves
+    // onto the back stack for `next` to take from the bottom.
+    let mut tree: Splay<u64> = Splay::new();
+    for key in (0..200_000).rev() {
+      tree.insert(key);
+    }
+    let mut iter = tree.into_iter();
+    assert_eq!(iter.next_back(), Some(199_999));
+    assert!(iter.eq(0..199_999));
+  }
+  #[test]
+  fn test_range() {
+    let mut tree: Splay<u64> = Splay::new();
+    for key in [10, 50, 5, 200, 400, 100].iter() {
+      tree.insert(*key);
+    }
+    let keys: Vec<u64> = tree.range(10..200).cloned().collect();
+    assert_eq!(keys, vec![10, 50, 100]);
+    let keys: Vec<u64> = tree.range(11..=200).cloned().collect();
+    assert_eq!(keys, vec![50, 100, 200]);
+    let keys: Vec<u64> = tree.range(..50).rev().cloned().collect();
+    assert_eq!(keys, vec![10, 5]);
+    let keys: Vec<u64> = tree.range(300..).cloned().collect();
+    assert_eq!(keys, vec![400]);
+    assert_eq!(tree.range(60..90).next(), None);
+    assert_eq!(tree.range(500..).next_back(), None);
+    let mut range = tree.range(5..=400);
+    assert_eq!(range.next(), Some(&5));
+    assert_eq!(range.next_back(), Some(&400));
+    assert_eq!(range.next_back(), Some(&200));
+    assert_eq!(range.next(), Some(&10));
+    assert_eq!(range.next(), Some(&50));
+    assert_eq!(range.next(), Some(&100));
+    assert_eq!(range.next(), None);
+    assert_eq!(range.next_back(), None);
+  }
+  #[test]
+  fn test_range_bounds() {
+    use std::collections::BTreeSet;
+    use std::ops::Bound;
+    let mut tree: Splay<u64> = Splay::new();
+    let mut expected = BTreeSet::new();
+    for key in (0..40).map(|i| i * 7 % 31) {
+      tree.insert(key);
+      expected.insert(key);
+    }
+    let bounds = |i: u64| vec![Bound::Included(i), Bound::Excluded(i), Bound::Unbounded];
+    for lo in 0..32 {
+      for hi in lo..32 {
+        for start in bounds(lo) {
+          for end in bounds(hi) {
+            if lo == hi && start == Bound::Excluded(lo) && end == Bound::Excluded(hi) {
+              continue;
+            }
+            let keys: Vec<&u64> = tree.range((start, end)).collect();
+            let want: Vec<&u64> = expected.range((start, end)).collect();
+            assert_eq!(keys, want);
+            let keys: Vec<&u64> = tree.range((start, end)).rev().collect();
+            let want: Vec<&u64> = expected.range((start, end)).rev().collect();
+            assert_eq!(keys, want);
+          }
+        }
+      }
+    }
+  }
+  #[test]
+  fn test_map_range() {
+    use std::ops::Bound;
+    let mut map: SplayMap<String, u64> = SplayMap::new();
+    map.insert(String::from("apple"), 1);
+    map.insert(String::from("banana"), 2);
+    map.insert(String::from("cherry"), 3);
+    let values: Vec<u64> = map
+      .range::<str, _>((Bound::Included("b"), Bound::Unbounded))
+      .map(|(_, v)| *v)
+      .collect();
+    assert_eq!(values, vec![2, 3]);
+  }
+  #[test]
+  fn test_splay_range() {
+    let mut tree: Splay<u64> = Splay::new();
+    for key in [10, 50, 5, 200, 400, 100].iter() {
+      tree.insert(*key);
+    }
+    let keys: Vec<u64> = tree.splay_range(50..=200).cloned().collect();
+    assert_eq!(keys, vec![50, 100, 200]);
+    assert_str_eq!(
+      tree.print(),
+      "[50([10([5()()])()])([100()([200()([400()()])])])]"
+    );
+    assert!(!tree.invalid_key());
+  }
+  #[test]
+  fn test_select_rank() {
+    let mut tree: Splay<u64> = Splay::new();
+    assert_eq!(tree.len(), 0);
+    assert_eq!(tree.select(0), None);
+    assert_eq!(tree.rank(&10), 0);
+    for key in [10, 50, 5, 200, 400, 100, 300, 35].iter() {
+      tree.insert(*key);
+      assert!(!tree.invalid_size());
+    }
+    assert_eq!(tree.len(), 8);
+    assert_eq!(tree.select(0), Some(&5));
+    assert_eq!(tree.select(3), Some(&50));
+    assert_eq!(tree.select(7), Some(&400));
+    assert_eq!(tree.select(8), None);
+    assert_eq!(tree.rank(&5), 0);
+    assert_eq!(tree.rank(&50), 3);
+    assert_eq!(tree.rank(&60), 4);
+    assert_eq!(tree.rank(&1000), 8);
+    tree.find(&300);
+    assert!(!tree.invalid_size());
+    tree.delete(&50);
+    tree.delete(&35);
+    tree.delete(&7);
+    assert!(!tree.invalid_size());
+    assert_eq!(tree.len(), 6);
+    assert_eq!(tree.select(2), Some(&100));
+    assert_eq!(tree.rank(&300), 4);
+    for (i, key) in tree.iter().enumerate() {
+      assert_eq!(tree.select(i), Some(key));
+      assert_eq!(tree.rank(key), i);
+    }
+  }
+  #[test]
+  fn test_split_off() {
+    let mut tree: Splay<u64> = Splay::new();
+    for key in [10, 50, 5, 200, 400, 100].iter() {
+      tree.insert(*key);
+    }
+    let right = tree.split_off(&100);
+    assert_str_eq!(tree.print(), "[50([10([5()()])()])()]");
+    assert_str_eq!(right.print(), "[100()([200()([400()()])])]");
+    assert!(!tree.invalid_size());
+    assert!(!right.invalid_size());
+    let right_2 = tree.split_off(&7);
+    assert_str_eq!(tree.print(), "[5()()]");
+    assert_str_eq!(right_2.print(), "[10()([50()()])]");
+    assert!(tree.split_off(&1000).is_empty());
+    assert_eq!(tree.split_off(&0).len(), 1);
+    assert!(tree.is_empty());
+  }
+  #[test]
+  fn test_append() {
+    let mut left: Splay<u64> = Splay::new();
+    let mut right: Splay<u64> = Splay::new();
+    for key in 0..50 {
+      left.insert(key * 2);
+      right.insert(key * 2 + 100);
+    }
+    right.append(&mut left);
+    assert!(left.is_empty());
+    assert_eq!(right.len(), 100);
+    assert!(!right.invalid_key());
+    assert!(!right.invalid_size());
+    let mut odd: Splay<u64> = Splay::new();
+    for key in 0..50 {
+      odd.insert(key * 2 + 1);
+    }
+    right.append(&mut odd);
+    assert!(odd.is_empty());
+    assert_eq!(right.len(), 150);
+    assert!(right.iter().cloned().take(100).eq(0..100));
+    assert!(!right.invalid_size());
+    let mut empty: Splay<u64> = Splay::new();
+    empty.append(&mut right);
+    assert_eq!(empty.len(), 150);
+    assert!(right.is_empty());
+    let mut tail = empty.split_off(&100);
+    assert_eq!(empty.len(), 100);
+    empty.append(&mut tail);
+    assert_eq!(empty.len(), 150);
+    assert!(tail.is_empty());
+    assert!(!empty.invalid_size());
+  }
+  #[test]
+  fn test_set_operations() {
+    let mut a: Splay<u64> = Splay::new();
+    let mut b: Splay<u64> = Splay::new();
+    for key in [1, 3, 5, 7, 9, 11].iter() {
+      a.insert(*key);
+    }
+    for key in [3, 4, 5, 6, 11, 12].iter() {
+      b.insert(*key);
+    }
+    let keys: Vec<u64> = a.union(&b).cloned().collect();
+    assert_eq!(keys, vec![1, 3, 4, 5, 6, 7, 9, 11, 12]);
+    let keys: Vec<u64> = a.intersection(&b).cloned().collect();
+    assert_eq!(keys, vec![3, 5, 11]);
+    let keys: Vec<u64> = a.difference(&b).cloned().collect();
+    assert_eq!(keys, vec![1, 7, 9]);
+    let keys: Vec<u64> = b.difference(&a).cloned().collect();
+    assert_eq!(keys, vec![4, 6, 12]);
+    let keys: Vec<u64> = a.symmetric_difference(&b).cloned().collect();
+    assert_eq!(keys, vec![1, 4, 6, 7, 9, 12]);
+    let empty: Splay<u64> = Splay::new();
+    assert_eq!(a.union(&empty).count(), 6);
+    assert_eq!(a.intersection(&empty).next(), None);
+    assert_eq!(empty.difference(&a).next(), None);
+    assert_eq!(empty.symmetric_difference(&b).count(), 6);
+  }
+  #[test]
+  fn test_from_sorted_iter() {
+    let tree: Splay<u64> = Splay::from_sorted_iter(0..100_000);
+    assert_eq!(tree.len(), 100_000);
+    assert!(!tree.invalid_key());
+    assert!(!tree.invalid_size());
+    assert!(tree.iter().cloned().eq(0..100_000));
+    let tree: Splay<u64> = Splay::from_sorted_iter(vec![]);
+    assert!(tree.is_empty());
+  }
+  #[test]
+  #[should_panic]
+  fn test_from_sorted_iter_unsorted() {
+    Splay::from_sorted_iter(vec![1, 3, 2]);
+  }
+  #[test]
+  fn test_from_iter_extend() {
+    let mut tree: Splay<u64> = vec![50, 10, 400, 5, 10, 200, 50].into_iter().collect();
+    assert_eq!(tree.len(), 5);
+    assert!(!tree.invalid_size());
+    tree.extend(vec![100, 5, 300]);
+    let keys: Vec<u64> = tree.iter().cloned().collect();
+    assert_eq!(keys, vec![5, 10, 50, 100, 200, 300, 400]);
+    let map: SplayMap<u64, &str> = vec![(2, "a"), (1, "b"), (2, "c")].into_iter().collect();
+    let entries: Vec<(u64, &str)> = map.into_iter().collect();
+    assert_eq!(entries, vec![(1, "b"), (2, "c")]);
+  }
+  #[test]
+  fn test_semi_splay() {
+    let mut tree: Splay<u64> = Splay::with_strategy(SplayStrategy::Semi);
+    for key in 1..=7 {
+      tree.insert(key);
+    }
+    assert_str_eq!(
+      tree.print(),
+      "[7([6([5([4([3([2([1()()])()])()])()])()])()])()]"
+    );
+    assert!(tree.find(&1));
+    assert_str_eq!(
+      tree.print(),
+      "[6([4([2([1()()])([3()()])])([5()()])])([7()()])]"
+    );
+    assert!(!tree.invalid_key());
+    assert!(!tree.invalid_size());
+    assert!(!tree.find(&8));
+    assert!(tree.find(&7));
+    assert_str_eq!(
+      tree.print(),
+      "[7([6([4([2([1()()])([3()()])])([5()()])])()])()]"
+    );
+  }
+  #[test]
+  fn test_splay_every_kth() {
+    let mut tree: Splay<u64> = Splay::with_strategy(SplayStrategy::EveryKth(3));
+    for key in 1..=4 {
+      tree.insert(key);
+    }
+    assert!(tree.find(&1));
+    assert!(tree.find(&1));
+    assert_str_eq!(tree.print(), "[4([3([2([1()()])()])()])()]");
+    assert!(tree.find(&1));
+    assert_str_eq!(tree.print(), "[1()([3([2()()])([4()()])])]");
+    assert!(!tree.invalid_size());
+  }
+  #[test]
+  fn test_skip_within_depth() {
+    let mut map: SplayMap<u64, u64> = SplayMap::with_strategy(SplayStrategy::SkipWithinDepth(1));
+    for key in 1..=4 {
+      map.insert(key, key * 10);
+    }
+    assert_eq!(map.get(&3), Some(&30));
+    assert_str_eq!(map.print(), "[4([3([2([1()()])()])()])()]");
+    *map.get_mut(&1).unwrap() += 1;
+    assert_str_eq!(map.print(), "[1()([3([2()()])([4()()])])]");
+    assert_eq!(map.get(&1), Some(&11));
+    let right = map.split_off(&3);
+    assert!(!map.invalid_size());
+    assert!(!right.invalid_size());
+  }
+  #[test]
+  #[should_panic]
+  fn test_splay_every_zeroth() {
+    let _tree: Splay<u64> = Splay::with_strategy(SplayStrategy::EveryKth(0));
+  }
+  #[test]
+  fn test_strategies() {
+    let strategies = [
+      SplayStrategy::Full,
+      SplayStrategy::Semi,
+      SplayStrategy::EveryKth(2),
+      SplayStrategy::SkipWithinDepth(3),
+    ];
+    for strategy in strategies.iter() {
+      let mut tree: Splay<u64> = Splay::with_strategy(*strategy);
+      for i in 0..101 {
+        assert!(tree.insert(i * 37 % 101));
+      }
+      for i in 0..202 {
+        assert_eq!(tree.find(&(i * 53 % 202)), i * 53 % 202 < 101);
+        assert!(!tree.invalid_key());
+        assert!(!tree.invalid_size());
+      }
+      for i in 0..50 {
+        assert_eq!(tree.delete(&(i * 2)), Some(i * 2));
+      }
+      assert_eq!(tree.len(), 51);
+      assert!(tree
+        .iter()
+        .cloned()
+        .eq((0..101).filter(|key| key % 2 == 1 || *key > 98)));
+    }
+  }
+  #[test]
+  fn test_degenerate_large() {
+    let mut tree: Splay<u64> = Splay::new();
+    // Inserting keys in ascending order leaves a path of length one million.
+    for key in 1..=1_000_000 {
+      assert!(tree.insert(key));
+    }
+    assert!(tree.find(&1));
+    assert!(tree.insert(0));
+    assert!(tree.find(&0));
+    assert_eq!(tree.delete(&1_000_000), Some(1_000_000));
+    assert_eq!(tree.delete(&1_000_001), None);
+    assert_eq!(tree.len(), 1_000_000);
+    assert!(tree.iter().cloned().eq(0..1_000_000));
+  }
+  struct Concat;
+  impl Monoid<char> for Concat {
+    type Summary = String;
+    fn identity() -> String {
+      String::new()
+    }
+    fn lift(value: &char) -> String {
+      value.to_string()
+    }
+    fn combine(left: &String, right: &String) -> String {
+      [left.as_str(), right.as_str()].concat()
+    }
+  }
+  #[test]
+  fn test_fold() {
+    let mut map: SplayMap<u64, u64, Sum> = SplayMap::new();
+    for i in 0..1000 {
+      map.insert(i * 389 % 1000, i);
+    }
+    for (lo, hi) in [
+      (0, 1000),
+      (100, 200),
+      (500, 400),
+      (999, 2000),
+      (3, 4),
+      (4, 4),
+    ]
+    .iter()
+    {
+      let expected: u64 = map.range(lo..hi).map(|(_, v)| *v).sum();
+      assert_eq!(map.fold(lo..hi), expected);
+    }
+    assert_eq!(map.fold(..), (0..1000).sum());
+    assert_eq!(
+      map.fold((Bound::Excluded(10), Bound::Included(20))),
+      map.fold(11..=20)
+    );
+    let old = map.insert(5, 1_000_000).unwrap();
+    assert_eq!(map.fold(5..6), 1_000_000);
+    assert_eq!(map.remove(&5), Some(1_000_000));
+    assert_eq!(map.fold(..), (0..1000).sum::<u64>() - old);
+    let mut right = map.split_off(&500);
+    assert_eq!(map.fold(..) + right.fold(..), (0..1000).sum::<u64>() - old);
+    assert_eq!(right.fold(..500), 0);
+  }
+  #[test]
+  fn test_fold_min_max() {
+    let mut min: SplayMap<u64, i64, Min> = SplayMap::new();
+    let mut max: SplayMap<u64, i64, Max> = SplayMap::new();
+    for key in 0..100 {
+      let value = (key as i64 * 37 % 101) - 50;
+      min.insert(key, value);
+      max.insert(key, value);
+    }
+    for (lo, hi) in [(0, 100), (10, 20), (50, 51), (60, 60)].iter() {
+      let values: Vec<i64> = min.range(lo..hi).map(|(_, v)| *v).collect();
+      assert_eq!(min.fold(lo..hi), values.iter().min().cloned());
+      assert_eq!(max.fold(lo..hi), values.iter().max().cloned());
+    }
+  }
+  #[test]
+  fn test_fold_order() {
+    let mut map: SplayMap<u64, char, Concat> = SplayMap::new();
+    for (i, c) in "the quick brown fox".char_indices().rev() {
+      map.insert(i as u64, c);
+    }
+    assert_eq!(map.fold(..), "the quick brown fox");
+    assert_eq!(map.fold(4..9), "quick");
+    assert_eq!(map.fold(16..), "fox");
+    assert_eq!(map.fold(19..), "");
+  }
+  #[test]
+  fn test_apply() {
+    let mut map: SplayMap<u64, i64, Sum, Update<i64>> = SplayMap::new();
+    let mut naive = vec![0i64; 1000];
+    for i in 0..1000 {
+      let key = i * 389 % 1000;
+      map.insert(key, key as i64);
+      naive[key as usize] = key as i64;
+    }
+    for i in 0..500u64 {
+      let lo = i * 577 % 1000;
+      let hi = lo + i * 31 % 200;
+      let action = if i % 3 == 0 {
+        Update::Assign(i as i64 - 250)
+      } else {
+        Update::Add(i as i64 % 17 - 8)
+      };
+      map.apply(lo..hi, action);
+      for v in naive
+        .iter_mut()
+        .take(hi.min(1000) as usize)
+        .skip(lo as usize)
+      {
+        *v = match action {
+          Update::Add(delta) => *v + delta,
+          Update::Assign(x) => x,
+        };
+      }
+      let (lo, hi) = (i * 241 % 1000, i * 241 % 1000 + i * 7 % 300);
+      let expected: i64 = naive
+        .iter()
+        .take(hi.min(1000) as usize)
+        .skip(lo as usize)
+        .sum();
+      assert_eq!(map.fold(lo..hi), expected);
+      if i % 50 == 0 {
+        let key = i * 13 % 1000;
+        assert_eq!(map.remove(&key), Some(naive[key as usize]));
+        map.insert(key, naive[key as usize]);
+      }
+    }
+    assert_eq!(map.fold(..), naive.iter().sum());
+    assert_eq!(map.get(&123), Some(&naive[123]));
+    let values: Vec<i64> = map.into_iter().map(|(_, v)| v).collect();
+    assert_eq!(values, naive);
+  }
+  #[test]
+  fn test_apply_min_max() {
+    let mut min: SplayMap<u64, i64, Min, Update<i64>> = SplayMap::new();
+    let mut max: SplayMap<u64, i64, Max, Update<i64>> = SplayMap::new();
+    let mut naive: Vec<i64> = (0..100).map(|key| (key * 37 % 101) - 50).collect();
+    for (key, value) in naive.iter().enumerate() {
+      min.insert(key as u64, *value);
+      max.insert(key as u64, *value);
+    }
+    for (lo, hi, action) in [
+      (20, 40, Update::Add(-100)),
+      (30, 100, Update::Assign(7)),
+      (0, 25, Update::Add(3)),
+      (45, 55, Update::Add(-1)),
+    ]
+    .iter()
+    {
+      min.apply(lo..hi, *action);
+      max.apply(lo..hi, *action);
+      for v in &mut naive[*lo as usize..*hi as usize] {
+        *v = match action {
+          Update::Add(delta) => *v + delta,
+          Update::Assign(x) => *x,
+        };
+      }
+      for (lo, hi) in [(0, 100), (10, 30), (35, 50), (24, 25), (60, 60)].iter() {
+        let values = &naive[*lo as usize..*hi as usize];
+        assert_eq!(min.fold(lo..hi), values.iter().min().cloned());
+        assert_eq!(max.fold(lo..hi), values.iter().max().cloned());
+      }
+    }
+  }
+  #[test]
+  fn test_apply_without_aggregate() {
+    let mut map: SplayMap<String, u64, (), Update<u64>> = ["a", "b", "c", "d"]
+      .iter()
+      .map(|s| (s.to_string(), 1))
+      .collect();
+    map.apply::<str, _>(
+      (Bound::Excluded("a"), Bound::Included("c")),
+      Update::Add(10),
+    );
+    map.apply::<str, _>((Bound::Included("c"), Bound::Unbounded), Update::Add(100));
+    assert_eq!(map.get("a"), Some(&1));
+    assert_eq!(map.get("b"), Some(&11));
+    assert_eq!(map.get("c"), Some(&111));
+    assert_eq!(map.get("d"), Some(&101));
+    assert_eq!(map.get("e"), None);
   }
 }
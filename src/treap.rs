@@ -1,32 +1,174 @@
+use std::borrow::Borrow;
 use std::cmp::Ordering;
 use std::fmt::Debug;
+use std::iter::FromIterator;
 use std::mem::{replace, swap};
+use std::ops::{Bound, RangeBounds};
 
 use rand::Rng;
 
-use super::BST;
-#[derive(Debug)]
-struct Node<K: Ord> {
+use super::iter::{drop_tree, EntryNode, InOrder, IntoInOrder, RangeInOrder, TreeNode};
+use super::{above_start, below_end, sorted_entries, Action, Max, Monoid, BST};
+struct Node<K: Ord, V, M: Monoid<V>, A: Action<V, M>> {
   key: K,
+  value: V,
+  size: usize,
+  summary: M::Summary,
+  // A tag already applied to this node but not yet to its children.
+  lazy: Option<A>,
   priority: u64,
-  lchild: Option<Box<Node<K>>>,
-  rchild: Option<Box<Node<K>>>,
+  lchild: Option<Box<Node<K, V, M, A>>>,
+  rchild: Option<Box<Node<K, V, M, A>>>,
 }
 
-pub struct Treap<K: Ord + Debug> {
-  root: Option<Box<Node<K>>>,
+type Link<K, V, M, A> = Option<Box<Node<K, V, M, A>>>;
+type Path<K, V, M, A> = Vec<(Box<Node<K, V, M, A>>, Ordering)>;
+// The nodes below and from a split point.
+type Halves<K, V, M, A> = (Link<K, V, M, A>, Link<K, V, M, A>);
+// The ancestors detached by `descend` and the node it stopped at.
+type Descent<K, V, M, A> = (Path<K, V, M, A>, Link<K, V, M, A>);
+// The nodes below, at and above a key.
+type Parts<K, V, M, A> = (Link<K, V, M, A>, Link<K, V, M, A>, Link<K, V, M, A>);
+
+pub struct TreapMap<K: Ord + Debug, V, M: Monoid<V> = (), A: Action<V, M> = ()> {
+  root: Option<Box<Node<K, V, M, A>>>,
   rng: rand::rngs::StdRng,
 }
 
-impl<K: Ord + Debug> Treap<K> {
-  pub fn new(seed: u8) -> Treap<K> {
-    Treap {
+pub struct Treap<K: Ord + Debug> {
+  map: TreapMap<K, ()>,
+}
+
+impl<K: Ord + Debug, V, M: Monoid<V>, A: Action<V, M>> TreapMap<K, V, M, A> {
+  pub fn new(seed: u8) -> TreapMap<K, V, M, A> {
+    TreapMap {
       root: None,
       rng: rand::SeedableRng::from_seed([seed; 32]),
     }
   }
+  /// Builds a map from entries in strictly ascending key order in linear time.
+  ///
+  /// Priorities are drawn as in `new(seed)` and the treap is assembled along
+  /// its right spine, as a Cartesian tree.
+  ///
+  /// # Panics
+  ///
+  /// Panics if the keys are not strictly ascending.
+  pub fn from_sorted_iter<I>(iter: I, seed: u8) -> TreapMap<K, V, M, A>
+  where
+    I: IntoIterator<Item = (K, V)>,
+  {
+    let mut map = TreapMap::new(seed);
+    let mut spine: Vec<Box<Node<K, V, M, A>>> = Vec::new();
+    for (key, value) in iter {
+      if let Some(last) = spine.last() {
+        assert!(
+          last.key < key,
+          "from_sorted_iter: keys must be strictly ascending"
+        );
+      }
+      let mut item = Box::new(Node {
+        key,
+        value,
+        size: 1,
+        summary: M::identity(),
+        lazy: None,
+        priority: map.rng.gen(),
+        lchild: None,
+        rchild: None,
+      });
+      let mut tree = None;
+      while spine.last().is_some_and(|t| t.priority < item.priority) {
+        let mut t = spine.pop().unwrap();
+        t.rchild = tree;
+        TreapMap::update(&mut t);
+        tree = Some(t);
+      }
+      item.lchild = tree;
+      spine.push(item);
+    }
+    let mut tree = None;
+    while let Some(mut t) = spine.pop() {
+      t.rchild = tree;
+      TreapMap::update(&mut t);
+      tree = Some(t);
+    }
+    map.root = tree;
+    map
+  }
+
+  fn size(tree: &Option<Box<Node<K, V, M, A>>>) -> usize {
+    tree.as_ref().map_or(0, |t| t.size)
+  }
+  fn summary(tree: &Option<Box<Node<K, V, M, A>>>) -> M::Summary {
+    tree
+      .as_ref()
+      .map_or_else(M::identity, |t| t.summary.clone())
+  }
+  /// Recomputes the size and aggregate of `tree` from its children. Everything
+  /// that restructures the tree, from the rotations to `_insert` and
+  /// `root_delete`, goes through here.
+  fn update(tree: &mut Node<K, V, M, A>) {
+    tree.size = 1 + TreapMap::size(&tree.lchild) + TreapMap::size(&tree.rchild);
+    tree.summary = M::combine(
+      &M::combine(&TreapMap::summary(&tree.lchild), &M::lift(&tree.value)),
+      &TreapMap::summary(&tree.rchild),
+    );
+  }
+  /// Applies `action` to the value and aggregate of `tree` and leaves it
+  /// pending for the children.
+  fn tag(tree: &mut Node<K, V, M, A>, action: &A) {
+    action.apply(&mut tree.value);
+    tree.summary = action.apply_summary(&tree.summary, tree.size);
+    tree.lazy = Some(match &tree.lazy {
+      None => action.clone(),
+      Some(lazy) => action.compose(lazy),
+    });
+  }
+  /// Hands the pending action of `tree` down to its children. Anything that
+  /// reads or relinks the children of a node pushes it first.
+  fn push(tree: &mut Node<K, V, M, A>) {
+    if let Some(action) = tree.lazy.take() {
+      for c in tree.lchild.iter_mut().chain(tree.rchild.iter_mut()) {
+        TreapMap::tag(c, &action);
+      }
+    }
+  }
+
+  /// Returns the action that applies `inner` and then `outer`.
+  fn compose(outer: &Option<A>, inner: &Option<A>) -> Option<A> {
+    match (outer, inner) {
+      (None, inner) => inner.clone(),
+      (Some(outer), None) => Some(outer.clone()),
+      (Some(outer), Some(inner)) => Some(outer.compose(inner)),
+    }
+  }
+  /// Returns the aggregate of `len` values after `action`, given their
+  /// aggregate before it.
+  fn act(action: &Option<A>, summary: M::Summary, len: usize) -> M::Summary {
+    match action {
+      None => summary,
+      Some(action) => action.apply_summary(&summary, len),
+    }
+  }
+  fn min_key(tree: &Link<K, V, M, A>) -> Option<&K> {
+    let mut t = tree.as_ref()?;
+    while let Some(l) = &t.lchild {
+      t = l;
+    }
+    Some(&t.key)
+  }
+  fn max_key(tree: &Link<K, V, M, A>) -> Option<&K> {
+    let mut t = tree.as_ref()?;
+    while let Some(r) = &t.rchild {
+      t = r;
+    }
+    Some(&t.key)
+  }
 
-  fn rotate_right(tree: &mut Box<Node<K>>) {
+  fn rotate_right(tree: &mut Box<Node<K, V, M, A>>) {
+    TreapMap::push(tree);
+    TreapMap::push(tree.lchild.as_mut().unwrap());
     let lnode = tree.lchild.as_mut().unwrap();
     let llnode = lnode.lchild.take();
     let lrnode = lnode.rchild.take();
@@ -35,16 +177,24 @@ impl<K: Ord + Debug> Treap<K> {
       &mut tree.as_mut().key,
       &mut rnode.as_deref_mut().unwrap().key,
     );
+    swap(
+      &mut tree.as_mut().value,
+      &mut rnode.as_deref_mut().unwrap().value,
+    );
     swap(
       &mut tree.as_mut().priority,
       &mut rnode.as_deref_mut().unwrap().priority,
     );
     rnode.as_deref_mut().unwrap().lchild = lrnode;
     rnode.as_deref_mut().unwrap().rchild = tree.rchild.take();
+    TreapMap::update(rnode.as_deref_mut().unwrap());
     tree.rchild = rnode;
+    TreapMap::update(tree);
   }
 
-  fn rotate_left(tree: &mut Box<Node<K>>) {
+  fn rotate_left(tree: &mut Box<Node<K, V, M, A>>) {
+    TreapMap::push(tree);
+    TreapMap::push(tree.rchild.as_mut().unwrap());
     let rnode = tree.rchild.as_mut().unwrap();
     let rlnode = rnode.lchild.take();
     let rrnode = rnode.rchild.take();
@@ -53,190 +203,1637 @@ impl<K: Ord + Debug> Treap<K> {
       &mut tree.as_mut().key,
       &mut lnode.as_deref_mut().unwrap().key,
     );
+    swap(
+      &mut tree.as_mut().value,
+      &mut lnode.as_deref_mut().unwrap().value,
+    );
     swap(
       &mut tree.as_mut().priority,
       &mut lnode.as_deref_mut().unwrap().priority,
     );
     lnode.as_deref_mut().unwrap().lchild = tree.lchild.take();
     lnode.as_deref_mut().unwrap().rchild = rlnode;
+    TreapMap::update(lnode.as_deref_mut().unwrap());
     tree.lchild = lnode;
+    TreapMap::update(tree);
+  }
+
+  /// Walks down from `tree` following `cmp`, detaching every visited node from
+  /// its parent. Returns the detached ancestors, each with the direction taken
+  /// below it, and the node where `cmp` returned `Equal` if there is one.
+  fn descend<F>(tree: Link<K, V, M, A>, mut cmp: F) -> Descent<K, V, M, A>
+  where
+    F: FnMut(&K) -> Ordering,
+  {
+    let mut path = Vec::new();
+    let mut tree = tree;
+    while let Some(mut t) = tree {
+      TreapMap::push(&mut t);
+      match cmp(&t.key) {
+        Ordering::Less => {
+          tree = t.lchild.take();
+          path.push((t, Ordering::Less));
+        }
+        Ordering::Greater => {
+          tree = t.rchild.take();
+          path.push((t, Ordering::Greater));
+        }
+        Ordering::Equal => return (path, Some(t)),
+      }
+    }
+    (path, None)
+  }
+  /// Reattaches `tree` below the ancestors in `path` without restructuring.
+  fn rebuild(mut path: Path<K, V, M, A>, tree: Link<K, V, M, A>) -> Link<K, V, M, A> {
+    let mut tree = tree;
+    while let Some((mut p, dir)) = path.pop() {
+      match dir {
+        Ordering::Less => p.lchild = tree,
+        _ => p.rchild = tree,
+      }
+      TreapMap::update(&mut p);
+      tree = Some(p);
+    }
+    tree
+  }
+
+  fn _insert(item: Box<Node<K, V, M, A>>, tree: &mut Link<K, V, M, A>) -> Option<V> {
+    let (mut path, found) = TreapMap::descend(tree.take(), |k| item.key.cmp(k));
+    if let Some(mut t) = found {
+      let old = replace(&mut t.value, item.value);
+      TreapMap::update(&mut t);
+      *tree = TreapMap::rebuild(path, Some(t));
+      return Some(old);
+    }
+    // Rotate the new node up while its priority beats its parent's.
+    let mut t = item;
+    while let Some((mut p, dir)) = path.pop() {
+      match dir {
+        Ordering::Less => {
+          let rotate = t.priority > p.priority;
+          p.lchild = Some(t);
+          TreapMap::update(&mut p);
+          if rotate {
+            TreapMap::rotate_right(&mut p);
+          }
+        }
+        _ => {
+          let rotate = t.priority > p.priority;
+          p.rchild = Some(t);
+          TreapMap::update(&mut p);
+          if rotate {
+            TreapMap::rotate_left(&mut p);
+          }
+        }
+      }
+      t = p;
+    }
+    *tree = Some(t);
+    None
+  }
+  fn _find<'a, Q>(key: &Q, tree: &'a Link<K, V, M, A>) -> Option<&'a Node<K, V, M, A>>
+  where
+    K: Borrow<Q>,
+    Q: Ord + ?Sized,
+  {
+    let mut tree = tree;
+    while let Some(t) = tree {
+      match key.cmp(t.key.borrow()) {
+        Ordering::Less => tree = &t.lchild,
+        Ordering::Greater => tree = &t.rchild,
+        Ordering::Equal => return Some(t),
+      }
+    }
+    None
+  }
+  fn _find_mut<'a, Q>(key: &Q, tree: &'a mut Link<K, V, M, A>) -> Option<&'a mut Node<K, V, M, A>>
+  where
+    K: Borrow<Q>,
+    Q: Ord + ?Sized,
+  {
+    let mut tree = tree;
+    while let Some(t) = tree {
+      TreapMap::push(t);
+      match key.cmp(t.key.borrow()) {
+        Ordering::Less => tree = &mut t.lchild,
+        Ordering::Greater => tree = &mut t.rchild,
+        Ordering::Equal => return Some(t),
+      }
+    }
+    None
+  }
+  /// Rotates the root of `tree` down until it is a leaf and removes it.
+  fn root_delete(tree: &mut Link<K, V, M, A>) -> Option<(K, V)> {
+    let mut t = tree.take()?;
+    let mut path = Vec::new();
+    loop {
+      let rotate_left = match (&t.lchild, &t.rchild) {
+        (None, None) => break,
+        (None, Some(_)) => true,
+        (Some(_), None) => false,
+        (Some(l), Some(r)) => l.priority <= r.priority,
+      };
+      if rotate_left {
+        TreapMap::rotate_left(&mut t);
+        let next = t.lchild.take().unwrap();
+        path.push((t, Ordering::Less));
+        t = next;
+      } else {
+        TreapMap::rotate_right(&mut t);
+        let next = t.rchild.take().unwrap();
+        path.push((t, Ordering::Greater));
+        t = next;
+      }
+    }
+    *tree = TreapMap::rebuild(path, None);
+    Some((t.key, t.value))
+  }
+  fn _delete<Q>(key: &Q, tree: &mut Link<K, V, M, A>) -> Option<(K, V)>
+  where
+    K: Borrow<Q>,
+    Q: Ord + ?Sized,
+  {
+    let (path, mut found) = TreapMap::descend(tree.take(), |k| key.cmp(k.borrow()));
+    let entry = TreapMap::root_delete(&mut found);
+    *tree = TreapMap::rebuild(path, found);
+    entry
+  }
+  /// Splits `tree` into the nodes with keys less than `key` and the rest.
+  fn split<Q>(tree: Link<K, V, M, A>, key: &Q) -> Halves<K, V, M, A>
+  where
+    K: Borrow<Q>,
+    Q: Ord + ?Sized,
+  {
+    TreapMap::split_by(tree, &|k: &K| k.borrow() < key)
+  }
+  /// Splits `tree` into the nodes whose keys satisfy `below` and the rest.
+  /// `below` must hold for a prefix of the keys in order.
+  fn split_by<F>(tree: Link<K, V, M, A>, below: &F) -> Halves<K, V, M, A>
+  where
+    F: Fn(&K) -> bool,
+  {
+    match tree {
+      None => (None, None),
+      Some(mut t) => {
+        TreapMap::push(&mut t);
+        if below(&t.key) {
+          let (l, r) = TreapMap::split_by(t.rchild.take(), below);
+          t.rchild = l;
+          TreapMap::update(&mut t);
+          (Some(t), r)
+        } else {
+          let (l, r) = TreapMap::split_by(t.lchild.take(), below);
+          t.lchild = r;
+          TreapMap::update(&mut t);
+          (l, Some(t))
+        }
+      }
+    }
+  }
+  /// Merges two treaps where every key of `left` is less than every key of `right`.
+  fn merge(left: Link<K, V, M, A>, right: Link<K, V, M, A>) -> Link<K, V, M, A> {
+    match (left, right) {
+      (None, r) => r,
+      (l, None) => l,
+      (Some(mut l), Some(mut r)) => {
+        TreapMap::push(&mut l);
+        TreapMap::push(&mut r);
+        if l.priority > r.priority {
+          l.rchild = TreapMap::merge(l.rchild.take(), Some(r));
+          TreapMap::update(&mut l);
+          Some(l)
+        } else {
+          r.lchild = TreapMap::merge(Some(l), r.lchild.take());
+          TreapMap::update(&mut r);
+          Some(r)
+        }
+      }
+    }
+  }
+
+  /// Splits `tree` into the nodes with keys less than, equal to and greater than `key`.
+  fn split_three(tree: Link<K, V, M, A>, key: &K) -> Parts<K, V, M, A> {
+    match tree {
+      None => (None, None, None),
+      Some(mut t) => {
+        TreapMap::push(&mut t);
+        match t.key.cmp(key) {
+          Ordering::Less => {
+            let (l, m, r) = TreapMap::split_three(t.rchild.take(), key);
+            t.rchild = l;
+            TreapMap::update(&mut t);
+            (Some(t), m, r)
+          }
+          Ordering::Greater => {
+            let (l, m, r) = TreapMap::split_three(t.lchild.take(), key);
+            t.lchild = r;
+            TreapMap::update(&mut t);
+            (l, m, Some(t))
+          }
+          Ordering::Equal => {
+            let l = t.lchild.take();
+            let r = t.rchild.take();
+            TreapMap::update(&mut t);
+            (l, Some(t), r)
+          }
+        }
+      }
+    }
+  }
+  /// Returns the union of two treaps, taking the entries of `a` for keys in both.
+  fn union(a: Link<K, V, M, A>, b: Link<K, V, M, A>) -> Link<K, V, M, A> {
+    match (a, b) {
+      (None, b) => b,
+      (a, None) => a,
+      (Some(mut a), Some(mut b)) => {
+        TreapMap::push(&mut a);
+        TreapMap::push(&mut b);
+        let (mut t, other, t_is_a) = if a.priority >= b.priority {
+          (a, b, true)
+        } else {
+          (b, a, false)
+        };
+        let (l, m, r) = TreapMap::split_three(Some(other), &t.key);
+        if let (Some(m), false) = (m, t_is_a) {
+          t.key = m.key;
+          t.value = m.value;
+        }
+        if t_is_a {
+          t.lchild = TreapMap::union(t.lchild.take(), l);
+          t.rchild = TreapMap::union(t.rchild.take(), r);
+        } else {
+          t.lchild = TreapMap::union(l, t.lchild.take());
+          t.rchild = TreapMap::union(r, t.rchild.take());
+        }
+        TreapMap::update(&mut t);
+        Some(t)
+      }
+    }
+  }
+  /// Returns the entries of `a` whose keys are also in `b`.
+  fn intersection(a: Link<K, V, M, A>, b: Link<K, V, M, A>) -> Link<K, V, M, A> {
+    match (a, b) {
+      (None, _) | (_, None) => None,
+      (Some(mut a), Some(mut b)) => {
+        TreapMap::push(&mut a);
+        TreapMap::push(&mut b);
+        if a.priority >= b.priority {
+          let mut t = a;
+          let (l, m, r) = TreapMap::split_three(Some(b), &t.key);
+          let l = TreapMap::intersection(t.lchild.take(), l);
+          let r = TreapMap::intersection(t.rchild.take(), r);
+          if m.is_none() {
+            return TreapMap::merge(l, r);
+          }
+          t.lchild = l;
+          t.rchild = r;
+          TreapMap::update(&mut t);
+          Some(t)
+        } else {
+          let mut t = b;
+          let (l, m, r) = TreapMap::split_three(Some(a), &t.key);
+          let l = TreapMap::intersection(l, t.lchild.take());
+          let r = TreapMap::intersection(r, t.rchild.take());
+          match m {
+            None => TreapMap::merge(l, r),
+            Some(mut m) => {
+              m.priority = t.priority;
+              m.lchild = l;
+              m.rchild = r;
+              TreapMap::update(&mut m);
+              Some(m)
+            }
+          }
+        }
+      }
+    }
+  }
+  /// Returns the entries of `a` whose keys are not in `b`.
+  fn difference(a: Link<K, V, M, A>, b: Link<K, V, M, A>) -> Link<K, V, M, A> {
+    match (a, b) {
+      (None, _) => None,
+      (a, None) => a,
+      (Some(a), Some(mut b)) => {
+        TreapMap::push(&mut b);
+        let (l, _, r) = TreapMap::split_three(Some(a), &b.key);
+        let l = TreapMap::difference(l, b.lchild.take());
+        let r = TreapMap::difference(r, b.rchild.take());
+        TreapMap::merge(l, r)
+      }
+    }
+  }
+  /// Returns the entries whose keys are in exactly one of `a` and `b`.
+  fn symmetric_difference(a: Link<K, V, M, A>, b: Link<K, V, M, A>) -> Link<K, V, M, A> {
+    match (a, b) {
+      (None, b) => b,
+      (a, None) => a,
+      (Some(mut a), Some(mut b)) => {
+        TreapMap::push(&mut a);
+        TreapMap::push(&mut b);
+        let (mut t, other) = if a.priority >= b.priority {
+          (a, b)
+        } else {
+          (b, a)
+        };
+        let (l, m, r) = TreapMap::split_three(Some(other), &t.key);
+        let l = TreapMap::symmetric_difference(t.lchild.take(), l);
+        let r = TreapMap::symmetric_difference(t.rchild.take(), r);
+        if m.is_some() {
+          return TreapMap::merge(l, r);
+        }
+        t.lchild = l;
+        t.rchild = r;
+        TreapMap::update(&mut t);
+        Some(t)
+      }
+    }
+  }
+
+  /// Inserts a key-value pair, returning the previous value of the key if it was present.
+  pub fn insert(&mut self, key: K, value: V) -> Option<V> {
+    let mut item: Box<Node<K, V, M, A>> = Box::new(Node {
+      key,
+      value,
+      size: 1,
+      summary: M::identity(),
+      lazy: None,
+      priority: self.rng.gen(),
+      lchild: None,
+      rchild: None,
+    });
+    TreapMap::update(&mut item);
+    TreapMap::_insert(item, &mut self.root)
+  }
+  pub fn contains_key<Q>(&self, key: &Q) -> bool
+  where
+    K: Borrow<Q>,
+    Q: Ord + ?Sized,
+  {
+    TreapMap::_find(key, &self.root).is_some()
+  }
+  /// Removes a key, returning its value if it was present.
+  pub fn remove<Q>(&mut self, key: &Q) -> Option<V>
+  where
+    K: Borrow<Q>,
+    Q: Ord + ?Sized,
+  {
+    self.remove_entry(key).map(|(_, value)| value)
+  }
+  /// Removes a key, returning the stored key and its value if it was present.
+  pub fn remove_entry<Q>(&mut self, key: &Q) -> Option<(K, V)>
+  where
+    K: Borrow<Q>,
+    Q: Ord + ?Sized,
+  {
+    TreapMap::_delete(key, &mut self.root)
+  }
+  pub fn len(&self) -> usize {
+    TreapMap::size(&self.root)
+  }
+  pub fn is_empty(&self) -> bool {
+    self.root.is_none()
+  }
+  /// Splits the map in two at `key`, returning the entries with keys greater than
+  /// or equal to `key` and keeping the rest.
+  pub fn split_off<Q>(&mut self, key: &Q) -> TreapMap<K, V, M, A>
+  where
+    K: Borrow<Q>,
+    Q: Ord + ?Sized,
+  {
+    let (l, r) = TreapMap::split(self.root.take(), key);
+    self.root = l;
+    TreapMap {
+      root: r,
+      rng: rand::SeedableRng::from_seed(self.rng.gen()),
+    }
+  }
+  /// Moves all entries of `other` into `self`, leaving `other` empty.
+  ///
+  /// When the key ranges of both maps do not overlap this is a single merge,
+  /// otherwise the maps are united and the values of `other` overwrite the
+  /// values of equal keys.
+  pub fn append(&mut self, other: &mut TreapMap<K, V, M, A>) {
+    let disjoint = match (
+      TreapMap::max_key(&self.root),
+      TreapMap::min_key(&other.root),
+    ) {
+      (Some(max), Some(min)) => max < min,
+      _ => true,
+    };
+    if disjoint {
+      self.root = TreapMap::merge(self.root.take(), other.root.take());
+      return;
+    }
+    let disjoint = match (
+      TreapMap::max_key(&other.root),
+      TreapMap::min_key(&self.root),
+    ) {
+      (Some(max), Some(min)) => max < min,
+      _ => true,
+    };
+    if disjoint {
+      self.root = TreapMap::merge(other.root.take(), self.root.take());
+      return;
+    }
+    self.root = TreapMap::union(other.root.take(), self.root.take());
+  }
+  /// Joins two maps where every key of `left` is less than every key of `right`.
+  ///
+  /// # Panics
+  ///
+  /// Panics if the key ranges of `left` and `right` overlap.
+  pub fn join(
+    mut left: TreapMap<K, V, M, A>,
+    mut right: TreapMap<K, V, M, A>,
+  ) -> TreapMap<K, V, M, A> {
+    if let (Some(max), Some(min)) = (
+      TreapMap::max_key(&left.root),
+      TreapMap::min_key(&right.root),
+    ) {
+      assert!(
+        max < min,
+        "join: keys of left must be less than keys of right"
+      );
+    }
+    left.root = TreapMap::merge(left.root.take(), right.root.take());
+    left
+  }
+  /// Returns the number of keys less than `key`.
+  pub fn rank<Q>(&self, key: &Q) -> usize
+  where
+    K: Borrow<Q>,
+    Q: Ord + ?Sized,
+  {
+    let mut rank = 0;
+    let mut tree = &self.root;
+    while let Some(t) = tree {
+      match key.cmp(t.key.borrow()) {
+        Ordering::Less => tree = &t.lchild,
+        Ordering::Equal => return rank + TreapMap::size(&t.lchild),
+        Ordering::Greater => {
+          rank += TreapMap::size(&t.lchild) + 1;
+          tree = &t.rchild;
+        }
+      }
+    }
+    rank
+  }
+  /// Returns the aggregate of the values whose keys lie in `range`, combined in
+  /// ascending key order.
+  ///
+  /// Below the topmost node inside the range, only the two paths towards the
+  /// ends of the range are walked, and the subtrees hanging off them inside the
+  /// range contribute their stored aggregates. Pending actions are composed
+  /// along the way instead of being pushed, so the map is not modified.
+  pub fn fold<Q, R>(&self, range: R) -> M::Summary
+  where
+    K: Borrow<Q>,
+    Q: Ord + ?Sized,
+    R: RangeBounds<Q>,
+  {
+    // The action pending from the ancestors of the current node.
+    let mut pending = None;
+    let mut tree = &self.root;
+    let top = loop {
+      match tree {
+        None => return M::identity(),
+        Some(t) => {
+          let above_start = above_start(&range, &t.key);
+          if above_start && below_end(&range, &t.key) {
+            break t;
+          }
+          pending = Self::compose(&pending, &t.lazy);
+          tree = if above_start { &t.lchild } else { &t.rchild };
+        }
+      }
+    };
+    let below_top = Self::compose(&pending, &top.lazy);
+    // Collected right to left.
+    let mut left = M::identity();
+    let mut pending_left = below_top.clone();
+    let mut tree = &top.lchild;
+    while let Some(t) = tree {
+      let below_t = Self::compose(&pending_left, &t.lazy);
+      if above_start(&range, &t.key) {
+        let value = Self::act(&pending_left, M::lift(&t.value), 1);
+        let rest = Self::act(
+          &below_t,
+          TreapMap::summary(&t.rchild),
+          TreapMap::size(&t.rchild),
+        );
+        left = M::combine(&M::combine(&value, &rest), &left);
+        tree = &t.lchild;
+      } else {
+        tree = &t.rchild;
+      }
+      pending_left = below_t;
+    }
+    let mut right = M::identity();
+    let mut pending_right = below_top;
+    let mut tree = &top.rchild;
+    while let Some(t) = tree {
+      let below_t = Self::compose(&pending_right, &t.lazy);
+      if below_end(&range, &t.key) {
+        let rest = Self::act(
+          &below_t,
+          TreapMap::summary(&t.lchild),
+          TreapMap::size(&t.lchild),
+        );
+        let value = Self::act(&pending_right, M::lift(&t.value), 1);
+        right = M::combine(&right, &M::combine(&rest, &value));
+        tree = &t.rchild;
+      } else {
+        tree = &t.lchild;
+      }
+      pending_right = below_t;
+    }
+    let value = Self::act(&pending, M::lift(&top.value), 1);
+    M::combine(&M::combine(&left, &value), &right)
+  }
+  /// Applies `action` to every value whose key lies in `range`.
+  ///
+  /// The range is split out, its root tagged and the parts merged back, so
+  /// the action only reaches the remaining nodes as later operations descend.
+  pub fn apply<Q, R>(&mut self, range: R, action: A)
+  where
+    K: Borrow<Q>,
+    Q: Ord + ?Sized,
+    R: RangeBounds<Q>,
+  {
+    let (left, rest) = TreapMap::split_by(self.root.take(), &|k: &K| !above_start(&range, k));
+    let (mut middle, right) = TreapMap::split_by(rest, &|k: &K| below_end(&range, k));
+    if let Some(m) = middle.as_mut() {
+      TreapMap::tag(m, &action);
+    }
+    self.root = TreapMap::merge(TreapMap::merge(left, middle), right);
+  }
+  /// Returns the value of `key` with every pending action applied, pushing the
+  /// actions down the path to it.
+  pub fn get_current<Q>(&mut self, key: &Q) -> Option<&V>
+  where
+    K: Borrow<Q>,
+    Q: Ord + ?Sized,
+  {
+    TreapMap::_find_mut(key, &mut self.root).map(|t| &t.value)
+  }
+
+  #[cfg(test)]
+  fn _print(tree: &Node<K, V, M, A>) -> String {
+    let mut message = String::from("[");
+    message = [message, format!("{:?}(", tree.key)].concat();
+    match &tree.lchild {
+      None => {}
+      Some(l) => {
+        message = [message, TreapMap::_print(l)].concat();
+      }
+    }
+    message = [message, String::from(")(")].concat();
+    match &tree.rchild {
+      None => {}
+      Some(r) => {
+        message = [message, TreapMap::_print(r)].concat();
+      }
+    }
+    [message, String::from(")]")].concat()
+  }
+  #[cfg(test)]
+  pub fn print(&self) -> String {
+    TreapMap::_print(self.root.as_ref().unwrap())
+  }
+  #[cfg(test)]
+  fn _invalid_priority(tree: &Node<K, V, M, A>) -> bool {
+    let mut ng = false;
+    match &tree.lchild {
+      None => {}
+      Some(l) => {
+        ng |= l.priority > tree.priority;
+        ng |= TreapMap::_invalid_priority(l);
+      }
+    }
+    match &tree.rchild {
+      None => {}
+      Some(r) => {
+        ng |= r.priority > tree.priority;
+        ng |= TreapMap::_invalid_priority(r);
+      }
+    }
+    ng
+  }
+  #[cfg(test)]
+  pub fn invalid_priority(&self) -> bool {
+    TreapMap::_invalid_priority(self.root.as_ref().unwrap())
+  }
+  #[cfg(test)]
+  fn _invalid_size(tree: &Option<Box<Node<K, V, M, A>>>) -> bool {
+    match tree {
+      None => false,
+      Some(t) => {
+        t.size != 1 + TreapMap::size(&t.lchild) + TreapMap::size(&t.rchild)
+          || TreapMap::_invalid_size(&t.lchild)
+          || TreapMap::_invalid_size(&t.rchild)
+      }
+    }
+  }
+  #[cfg(test)]
+  pub fn invalid_size(&self) -> bool {
+    TreapMap::_invalid_size(&self.root)
+  }
+}
+
+impl<K: Ord + Debug, V, M: Monoid<V>> TreapMap<K, V, M> {
+  // Values can only be read in place when no action is pending anywhere in
+  // the tree. Maps with an action go through `get_current` or `into_iter`.
+  pub fn get<Q>(&self, key: &Q) -> Option<&V>
+  where
+    K: Borrow<Q>,
+    Q: Ord + ?Sized,
+  {
+    TreapMap::_find(key, &self.root).map(|t| &t.value)
+  }
+  /// Returns the entry with the `index`-th smallest key, counting from zero.
+  pub fn select(&self, index: usize) -> Option<(&K, &V)> {
+    let mut index = index;
+    let mut tree = &self.root;
+    while let Some(t) = tree {
+      let lsize = TreapMap::size(&t.lchild);
+      match index.cmp(&lsize) {
+        Ordering::Less => tree = &t.lchild,
+        Ordering::Equal => return Some((&t.key, &t.value)),
+        Ordering::Greater => {
+          index -= lsize + 1;
+          tree = &t.rchild;
+        }
+      }
+    }
+    None
+  }
+  /// Returns an iterator over the entries in ascending key order.
+  pub fn iter(&self) -> MapIter<'_, K, V, M> {
+    MapIter {
+      walk: InOrder::new(&self.root, self.len()),
+    }
+  }
+  /// Returns an iterator over the entries whose keys lie in `range`, in ascending key order.
+  pub fn range<Q, R>(&self, range: R) -> MapRange<'_, K, V, M>
+  where
+    K: Borrow<Q>,
+    Q: Ord + ?Sized,
+    R: RangeBounds<Q>,
+  {
+    MapRange {
+      walk: RangeInOrder::new(&self.root, range),
+    }
+  }
+  #[cfg(test)]
+  pub fn invalid_key(&self) -> bool {
+    let seq: Vec<&K> = self.iter().map(|(key, _)| key).collect();
+    seq.windows(2).any(|w| w[0] >= w[1])
+  }
+}
+
+impl<K: Ord + Debug, V> TreapMap<K, V> {
+  /// Returns a mutable reference to the value of `key`. Only maps without an
+  /// aggregate hand these out, as changing a value in place would leave the
+  /// aggregates above it stale.
+  pub fn get_mut<Q>(&mut self, key: &Q) -> Option<&mut V>
+  where
+    K: Borrow<Q>,
+    Q: Ord + ?Sized,
+  {
+    TreapMap::_find_mut(key, &mut self.root).map(|t| &mut t.value)
+  }
+}
+
+impl<K: Ord + Debug> Treap<K> {
+  pub fn new(seed: u8) -> Treap<K> {
+    Treap {
+      map: TreapMap::new(seed),
+    }
+  }
+  /// Builds a set from keys in strictly ascending order in linear time.
+  ///
+  /// # Panics
+  ///
+  /// Panics if the keys are not strictly ascending.
+  pub fn from_sorted_iter<I>(iter: I, seed: u8) -> Treap<K>
+  where
+    I: IntoIterator<Item = K>,
+  {
+    Treap {
+      map: TreapMap::from_sorted_iter(iter.into_iter().map(|key| (key, ())), seed),
+    }
+  }
+  pub fn len(&self) -> usize {
+    self.map.len()
+  }
+  pub fn is_empty(&self) -> bool {
+    self.map.is_empty()
+  }
+  /// Splits the set in two at `key`, returning the keys greater than or equal to
+  /// `key` and keeping the rest.
+  pub fn split_off<Q>(&mut self, key: &Q) -> Treap<K>
+  where
+    K: Borrow<Q>,
+    Q: Ord + ?Sized,
+  {
+    Treap {
+      map: self.map.split_off(key),
+    }
+  }
+  /// Moves all keys of `other` into `self`, leaving `other` empty.
+  pub fn append(&mut self, other: &mut Treap<K>) {
+    self.map.append(&mut other.map);
+  }
+  /// Joins two sets where every key of `left` is less than every key of `right`.
+  ///
+  /// # Panics
+  ///
+  /// Panics if the key ranges of `left` and `right` overlap.
+  pub fn join(left: Treap<K>, right: Treap<K>) -> Treap<K> {
+    Treap {
+      map: TreapMap::join(left.map, right.map),
+    }
+  }
+  /// Returns the keys that are in `self` or `other`.
+  pub fn union(mut self, mut other: Treap<K>) -> Treap<K> {
+    self.map.root = TreapMap::union(self.map.root.take(), other.map.root.take());
+    self
+  }
+  /// Returns the keys that are in both `self` and `other`.
+  pub fn intersection(mut self, mut other: Treap<K>) -> Treap<K> {
+    self.map.root = TreapMap::intersection(self.map.root.take(), other.map.root.take());
+    self
+  }
+  /// Returns the keys that are in `self` but not in `other`.
+  pub fn difference(mut self, mut other: Treap<K>) -> Treap<K> {
+    self.map.root = TreapMap::difference(self.map.root.take(), other.map.root.take());
+    self
+  }
+  /// Returns the keys that are in exactly one of `self` and `other`.
+  pub fn symmetric_difference(mut self, mut other: Treap<K>) -> Treap<K> {
+    self.map.root = TreapMap::symmetric_difference(self.map.root.take(), other.map.root.take());
+    self
+  }
+  /// Returns the `index`-th smallest key, counting from zero.
+  pub fn select(&self, index: usize) -> Option<&K> {
+    self.map.select(index).map(|(key, _)| key)
+  }
+  /// Returns the number of keys less than `key`.
+  pub fn rank<Q>(&self, key: &Q) -> usize
+  where
+    K: Borrow<Q>,
+    Q: Ord + ?Sized,
+  {
+    self.map.rank(key)
+  }
+  /// Returns an iterator over the keys in ascending order.
+  pub fn iter(&self) -> Iter<'_, K> {
+    Iter {
+      iter: self.map.iter(),
+    }
+  }
+  /// Returns an iterator over the keys that lie in `range`, in ascending order.
+  pub fn range<Q, R>(&self, range: R) -> Range<'_, K>
+  where
+    K: Borrow<Q>,
+    Q: Ord + ?Sized,
+    R: RangeBounds<Q>,
+  {
+    Range {
+      iter: self.map.range(range),
+    }
+  }
+
+  #[cfg(test)]
+  pub fn print(&self) -> String {
+    self.map.print()
+  }
+  #[cfg(test)]
+  pub fn invalid_priority(&self) -> bool {
+    self.map.invalid_priority()
+  }
+  #[cfg(test)]
+  pub fn invalid_size(&self) -> bool {
+    self.map.invalid_size()
+  }
+  #[cfg(test)]
+  pub fn invalid_key(&self) -> bool {
+    self.map.invalid_key()
+  }
+}
+
+impl<K: Ord + Debug> BST<K> for Treap<K> {
+  fn insert(&mut self, key: K) -> bool {
+    self.map.insert(key, ()).is_none()
+  }
+  fn find<Q>(&mut self, key: &Q) -> bool
+  where
+    K: Borrow<Q>,
+    Q: Ord + ?Sized,
+  {
+    self.map.contains_key(key)
+  }
+  fn delete<Q>(&mut self, key: &Q) -> Option<K>
+  where
+    K: Borrow<Q>,
+    Q: Ord + ?Sized,
+  {
+    self.map.remove_entry(key).map(|(key, _)| key)
+  }
+}
+
+// Dropping the nodes recursively would overflow the stack on deep trees.
+impl<K: Ord + Debug, V, M: Monoid<V>, A: Action<V, M>> Drop for TreapMap<K, V, M, A> {
+  fn drop(&mut self) {
+    drop_tree(self.root.take());
+  }
+}
+
+impl<K: Ord, V, M: Monoid<V>, A: Action<V, M>> TreeNode for Node<K, V, M, A> {
+  type Key = K;
+  fn key(&self) -> &K {
+    &self.key
+  }
+  fn lchild(&self) -> &Link<K, V, M, A> {
+    &self.lchild
+  }
+  fn rchild(&self) -> &Link<K, V, M, A> {
+    &self.rchild
+  }
+  fn lchild_mut(&mut self) -> &mut Link<K, V, M, A> {
+    &mut self.lchild
+  }
+  fn rchild_mut(&mut self) -> &mut Link<K, V, M, A> {
+    &mut self.rchild
+  }
+}
+
+impl<K: Ord + Debug, V, M: Monoid<V>, A: Action<V, M>> EntryNode for Node<K, V, M, A> {
+  type Value = V;
+  fn push(&mut self) {
+    TreapMap::push(self);
+  }
+  fn into_entry(self) -> (K, V) {
+    (self.key, self.value)
+  }
+}
+
+/// An in-order iterator over the entries of a `TreapMap`.
+pub struct MapIter<'a, K: Ord, V, M: Monoid<V> = ()> {
+  walk: InOrder<'a, Node<K, V, M, ()>>,
+}
+
+impl<'a, K: Ord, V, M: Monoid<V>> Iterator for MapIter<'a, K, V, M> {
+  type Item = (&'a K, &'a V);
+
+  fn next(&mut self) -> Option<(&'a K, &'a V)> {
+    self.walk.next().map(|t| (&t.key, &t.value))
+  }
+  fn size_hint(&self) -> (usize, Option<usize>) {
+    self.walk.size_hint()
+  }
+}
+
+impl<'a, K: Ord, V, M: Monoid<V>> DoubleEndedIterator for MapIter<'a, K, V, M> {
+  fn next_back(&mut self) -> Option<(&'a K, &'a V)> {
+    self.walk.next_back().map(|t| (&t.key, &t.value))
+  }
+}
+
+impl<'a, K: Ord, V, M: Monoid<V>> ExactSizeIterator for MapIter<'a, K, V, M> {}
+
+/// An owning in-order iterator over the entries of a `TreapMap`.
+///
+/// Entries are moved out as the iteration reaches them, so nothing is collected
+/// up front.
+pub struct MapIntoIter<K: Ord + Debug, V, M: Monoid<V> = (), A: Action<V, M> = ()> {
+  walk: IntoInOrder<Node<K, V, M, A>>,
+}
+
+impl<K: Ord + Debug, V, M: Monoid<V>, A: Action<V, M>> Iterator for MapIntoIter<K, V, M, A> {
+  type Item = (K, V);
+
+  fn next(&mut self) -> Option<(K, V)> {
+    self.walk.next()
+  }
+  fn size_hint(&self) -> (usize, Option<usize>) {
+    self.walk.size_hint()
+  }
+}
+
+impl<K: Ord + Debug, V, M: Monoid<V>, A: Action<V, M>> DoubleEndedIterator
+  for MapIntoIter<K, V, M, A>
+{
+  fn next_back(&mut self) -> Option<(K, V)> {
+    self.walk.next_back()
+  }
+}
+
+impl<K: Ord + Debug, V, M: Monoid<V>, A: Action<V, M>> ExactSizeIterator
+  for MapIntoIter<K, V, M, A>
+{
+}
+
+/// An in-order iterator over the keys of a `Treap`.
+pub struct Iter<'a, K: Ord> {
+  iter: MapIter<'a, K, ()>,
+}
+
+impl<'a, K: Ord> Iterator for Iter<'a, K> {
+  type Item = &'a K;
+
+  fn next(&mut self) -> Option<&'a K> {
+    self.iter.next().map(|(key, _)| key)
+  }
+  fn size_hint(&self) -> (usize, Option<usize>) {
+    self.iter.size_hint()
+  }
+}
+
+impl<'a, K: Ord> DoubleEndedIterator for Iter<'a, K> {
+  fn next_back(&mut self) -> Option<&'a K> {
+    self.iter.next_back().map(|(key, _)| key)
+  }
+}
+
+impl<'a, K: Ord> ExactSizeIterator for Iter<'a, K> {}
+
+/// An owning in-order iterator over the keys of a `Treap`.
+pub struct IntoIter<K: Ord + Debug> {
+  iter: MapIntoIter<K, ()>,
+}
+
+impl<K: Ord + Debug> Iterator for IntoIter<K> {
+  type Item = K;
+
+  fn next(&mut self) -> Option<K> {
+    self.iter.next().map(|(key, _)| key)
+  }
+  fn size_hint(&self) -> (usize, Option<usize>) {
+    self.iter.size_hint()
+  }
+}
+
+impl<K: Ord + Debug> DoubleEndedIterator for IntoIter<K> {
+  fn next_back(&mut self) -> Option<K> {
+    self.iter.next_back().map(|(key, _)| key)
+  }
+}
+
+impl<K: Ord + Debug> ExactSizeIterator for IntoIter<K> {}
+
+/// An in-order iterator over a sub-range of the entries of a `TreapMap`.
+pub struct MapRange<'a, K: Ord, V, M: Monoid<V> = ()> {
+  walk: RangeInOrder<'a, Node<K, V, M, ()>>,
+}
+
+impl<'a, K: Ord, V, M: Monoid<V>> Iterator for MapRange<'a, K, V, M> {
+  type Item = (&'a K, &'a V);
+
+  fn next(&mut self) -> Option<(&'a K, &'a V)> {
+    self.walk.next().map(|t| (&t.key, &t.value))
+  }
+}
+
+impl<'a, K: Ord, V, M: Monoid<V>> DoubleEndedIterator for MapRange<'a, K, V, M> {
+  fn next_back(&mut self) -> Option<(&'a K, &'a V)> {
+    self.walk.next_back().map(|t| (&t.key, &t.value))
+  }
+}
+
+/// An in-order iterator over a sub-range of the keys of a `Treap`.
+pub struct Range<'a, K: Ord> {
+  iter: MapRange<'a, K, ()>,
+}
+
+impl<'a, K: Ord> Iterator for Range<'a, K> {
+  type Item = &'a K;
+
+  fn next(&mut self) -> Option<&'a K> {
+    self.iter.next().map(|(key, _)| key)
+  }
+}
+
+impl<'a, K: Ord> DoubleEndedIterator for Range<'a, K> {
+  fn next_back(&mut self) -> Option<&'a K> {
+    self.iter.next_back().map(|(key, _)| key)
+  }
+}
+
+impl<'a, K: Ord + Debug, V, M: Monoid<V>> IntoIterator for &'a TreapMap<K, V, M> {
+  type Item = (&'a K, &'a V);
+  type IntoIter = MapIter<'a, K, V, M>;
+
+  fn into_iter(self) -> MapIter<'a, K, V, M> {
+    self.iter()
+  }
+}
+
+impl<K: Ord + Debug, V, M: Monoid<V>, A: Action<V, M>> IntoIterator for TreapMap<K, V, M, A> {
+  type Item = (K, V);
+  type IntoIter = MapIntoIter<K, V, M, A>;
+
+  fn into_iter(mut self) -> MapIntoIter<K, V, M, A> {
+    let len = self.len();
+    MapIntoIter {
+      walk: IntoInOrder::new(self.root.take(), len),
+    }
+  }
+}
+
+impl<'a, K: Ord + Debug> IntoIterator for &'a Treap<K> {
+  type Item = &'a K;
+  type IntoIter = Iter<'a, K>;
+
+  fn into_iter(self) -> Iter<'a, K> {
+    self.iter()
+  }
+}
+
+impl<K: Ord + Debug> IntoIterator for Treap<K> {
+  type Item = K;
+  type IntoIter = IntoIter<K>;
+
+  fn into_iter(self) -> IntoIter<K> {
+    IntoIter {
+      iter: self.map.into_iter(),
+    }
+  }
+}
+
+/// Sorts the entries and builds the map with `from_sorted_iter` and seed 0.
+/// Later entries win among equal keys.
+impl<K: Ord + Debug, V, M: Monoid<V>, A: Action<V, M>> FromIterator<(K, V)>
+  for TreapMap<K, V, M, A>
+{
+  fn from_iter<I: IntoIterator<Item = (K, V)>>(iter: I) -> TreapMap<K, V, M, A> {
+    TreapMap::from_sorted_iter(sorted_entries(iter), 0)
+  }
+}
+
+impl<K: Ord + Debug, V, M: Monoid<V>, A: Action<V, M>> Extend<(K, V)> for TreapMap<K, V, M, A> {
+  fn extend<I: IntoIterator<Item = (K, V)>>(&mut self, iter: I) {
+    for (key, value) in iter {
+      self.insert(key, value);
+    }
+  }
+}
+
+impl<K: Ord + Debug> FromIterator<K> for Treap<K> {
+  fn from_iter<I: IntoIterator<Item = K>>(iter: I) -> Treap<K> {
+    Treap {
+      map: iter.into_iter().map(|key| (key, ())).collect(),
+    }
+  }
+}
+
+impl<K: Ord + Debug> Extend<K> for Treap<K> {
+  fn extend<I: IntoIterator<Item = K>>(&mut self, iter: I) {
+    for key in iter {
+      self.insert(key);
+    }
+  }
+}
+
+#[derive(Debug)]
+struct ImplicitNode<T> {
+  value: T,
+  size: usize,
+  priority: u64,
+  // The children are still to be swapped and the flag passed on to them.
+  reversed: bool,
+  lchild: Option<Box<ImplicitNode<T>>>,
+  rchild: Option<Box<ImplicitNode<T>>>,
+}
+
+type ImplicitLink<T> = Option<Box<ImplicitNode<T>>>;
+
+/// A treap ordered by position instead of by key, for use as a sequence: each
+/// node's index is the size of everything to its left. Positional access,
+/// insertion, removal, splitting, concatenation and reversal of a subrange take
+/// O(log n) expected time. Reversals are recorded as lazy flags and pushed down
+/// whenever a node is descended through.
+pub struct ImplicitTreap<T> {
+  root: Option<Box<ImplicitNode<T>>>,
+  rng: rand::rngs::StdRng,
+}
+
+impl<T> ImplicitTreap<T> {
+  pub fn new(seed: u8) -> ImplicitTreap<T> {
+    ImplicitTreap {
+      root: None,
+      rng: rand::SeedableRng::from_seed([seed; 32]),
+    }
+  }
+  /// Builds a sequence from `iter` in linear time, as `TreapMap::from_sorted_iter`
+  /// does.
+  fn from_seq<I>(iter: I, seed: u8) -> ImplicitTreap<T>
+  where
+    I: IntoIterator<Item = T>,
+  {
+    let mut seq = ImplicitTreap::new(seed);
+    let mut spine: Vec<Box<ImplicitNode<T>>> = Vec::new();
+    for value in iter {
+      let mut item = seq.node(value);
+      let mut tree = None;
+      while spine.last().is_some_and(|t| t.priority < item.priority) {
+        let mut t = spine.pop().unwrap();
+        t.rchild = tree;
+        ImplicitTreap::update(&mut t);
+        tree = Some(t);
+      }
+      item.lchild = tree;
+      spine.push(item);
+    }
+    let mut tree = None;
+    while let Some(mut t) = spine.pop() {
+      t.rchild = tree;
+      ImplicitTreap::update(&mut t);
+      tree = Some(t);
+    }
+    seq.root = tree;
+    seq
+  }
+  fn node(&mut self, value: T) -> Box<ImplicitNode<T>> {
+    Box::new(ImplicitNode {
+      value,
+      size: 1,
+      priority: self.rng.gen(),
+      reversed: false,
+      lchild: None,
+      rchild: None,
+    })
+  }
+
+  fn size(tree: &Option<Box<ImplicitNode<T>>>) -> usize {
+    tree.as_ref().map_or(0, |t| t.size)
+  }
+  fn update(tree: &mut ImplicitNode<T>) {
+    tree.size = 1 + ImplicitTreap::size(&tree.lchild) + ImplicitTreap::size(&tree.rchild);
+  }
+  /// Applies the pending reversal of `tree` to its children.
+  fn push(tree: &mut ImplicitNode<T>) {
+    if tree.reversed {
+      swap(&mut tree.lchild, &mut tree.rchild);
+      for c in tree.lchild.iter_mut().chain(tree.rchild.iter_mut()) {
+        c.reversed = !c.reversed;
+      }
+      tree.reversed = false;
+    }
+  }
+  /// Splits `tree` into its first `index` elements and the rest.
+  fn split(tree: ImplicitLink<T>, index: usize) -> (ImplicitLink<T>, ImplicitLink<T>) {
+    match tree {
+      None => (None, None),
+      Some(mut t) => {
+        ImplicitTreap::push(&mut t);
+        let lsize = ImplicitTreap::size(&t.lchild);
+        if index <= lsize {
+          let (l, r) = ImplicitTreap::split(t.lchild.take(), index);
+          t.lchild = r;
+          ImplicitTreap::update(&mut t);
+          (l, Some(t))
+        } else {
+          let (l, r) = ImplicitTreap::split(t.rchild.take(), index - lsize - 1);
+          t.rchild = l;
+          ImplicitTreap::update(&mut t);
+          (Some(t), r)
+        }
+      }
+    }
+  }
+  /// Concatenates two sequences.
+  fn merge(left: ImplicitLink<T>, right: ImplicitLink<T>) -> ImplicitLink<T> {
+    match (left, right) {
+      (None, r) => r,
+      (l, None) => l,
+      (Some(mut l), Some(mut r)) => {
+        if l.priority > r.priority {
+          ImplicitTreap::push(&mut l);
+          l.rchild = ImplicitTreap::merge(l.rchild.take(), Some(r));
+          ImplicitTreap::update(&mut l);
+          Some(l)
+        } else {
+          ImplicitTreap::push(&mut r);
+          r.lchild = ImplicitTreap::merge(Some(l), r.lchild.take());
+          ImplicitTreap::update(&mut r);
+          Some(r)
+        }
+      }
+    }
+  }
+
+  pub fn len(&self) -> usize {
+    ImplicitTreap::size(&self.root)
+  }
+  pub fn is_empty(&self) -> bool {
+    self.root.is_none()
+  }
+  /// Inserts `value` at position `index`, shifting the elements after it.
+  ///
+  /// # Panics
+  ///
+  /// Panics if `index` is greater than the length.
+  pub fn insert(&mut self, index: usize, value: T) {
+    assert!(index <= self.len(), "insert: index out of bounds");
+    let item = self.node(value);
+    let (l, r) = ImplicitTreap::split(self.root.take(), index);
+    self.root = ImplicitTreap::merge(ImplicitTreap::merge(l, Some(item)), r);
+  }
+  /// Appends `value` to the end of the sequence.
+  pub fn push_back(&mut self, value: T) {
+    let item = self.node(value);
+    self.root = ImplicitTreap::merge(self.root.take(), Some(item));
+  }
+  /// Removes the element at position `index`, returning it if there was one.
+  pub fn remove(&mut self, index: usize) -> Option<T> {
+    if index >= self.len() {
+      return None;
+    }
+    let (l, r) = ImplicitTreap::split(self.root.take(), index);
+    let (m, r) = ImplicitTreap::split(r, 1);
+    self.root = ImplicitTreap::merge(l, r);
+    m.map(|t| t.value)
+  }
+  /// Returns the element at position `index`.
+  pub fn get(&self, index: usize) -> Option<&T> {
+    let mut index = index;
+    let mut reversed = false;
+    let mut tree = &self.root;
+    while let Some(t) = tree {
+      // Reversals pending above are folded in rather than pushed down.
+      reversed ^= t.reversed;
+      let (l, r) = if reversed {
+        (&t.rchild, &t.lchild)
+      } else {
+        (&t.lchild, &t.rchild)
+      };
+      let lsize = ImplicitTreap::size(l);
+      match index.cmp(&lsize) {
+        Ordering::Less => tree = l,
+        Ordering::Equal => return Some(&t.value),
+        Ordering::Greater => {
+          index -= lsize + 1;
+          tree = r;
+        }
+      }
+    }
+    None
+  }
+  /// Returns the element at position `index` mutably.
+  pub fn get_mut(&mut self, index: usize) -> Option<&mut T> {
+    let mut index = index;
+    let mut tree = &mut self.root;
+    while let Some(t) = tree {
+      ImplicitTreap::push(t);
+      let lsize = ImplicitTreap::size(&t.lchild);
+      match index.cmp(&lsize) {
+        Ordering::Less => tree = &mut t.lchild,
+        Ordering::Equal => return Some(&mut t.value),
+        Ordering::Greater => {
+          index -= lsize + 1;
+          tree = &mut t.rchild;
+        }
+      }
+    }
+    None
+  }
+  /// Splits the sequence in two at `at`, returning the elements from `at` on
+  /// and keeping the rest.
+  ///
+  /// # Panics
+  ///
+  /// Panics if `at` is greater than the length.
+  pub fn split_off(&mut self, at: usize) -> ImplicitTreap<T> {
+    assert!(at <= self.len(), "split_off: index out of bounds");
+    let (l, r) = ImplicitTreap::split(self.root.take(), at);
+    self.root = l;
+    ImplicitTreap {
+      root: r,
+      rng: rand::SeedableRng::from_seed(self.rng.gen()),
+    }
+  }
+  /// Moves all elements of `other` to the end of `self`, leaving `other` empty.
+  pub fn append(&mut self, other: &mut ImplicitTreap<T>) {
+    self.root = ImplicitTreap::merge(self.root.take(), other.root.take());
+  }
+  /// Concatenates two sequences.
+  pub fn join(mut left: ImplicitTreap<T>, mut right: ImplicitTreap<T>) -> ImplicitTreap<T> {
+    left.append(&mut right);
+    left
+  }
+  /// Reverses the elements within `range`.
+  ///
+  /// # Panics
+  ///
+  /// Panics if `range` starts after it ends or ends after the sequence.
+  pub fn reverse<R>(&mut self, range: R)
+  where
+    R: RangeBounds<usize>,
+  {
+    let start = match range.start_bound() {
+      Bound::Included(&start) => start,
+      Bound::Excluded(&start) => start + 1,
+      Bound::Unbounded => 0,
+    };
+    let end = match range.end_bound() {
+      Bound::Included(&end) => end + 1,
+      Bound::Excluded(&end) => end,
+      Bound::Unbounded => self.len(),
+    };
+    assert!(
+      start <= end && end <= self.len(),
+      "reverse: range out of bounds"
+    );
+    let (l, r) = ImplicitTreap::split(self.root.take(), end);
+    let (l, mut m) = ImplicitTreap::split(l, start);
+    if let Some(m) = m.as_mut() {
+      m.reversed = !m.reversed;
+    }
+    self.root = ImplicitTreap::merge(ImplicitTreap::merge(l, m), r);
+  }
+  /// Returns an iterator over the elements in order.
+  pub fn iter(&self) -> ImplicitIter<'_, T> {
+    let mut iter = ImplicitIter {
+      stack: Vec::new(),
+      remaining: self.len(),
+    };
+    iter.push_spine(&self.root, false);
+    iter
+  }
+  #[cfg(test)]
+  fn _invalid_priority(tree: &Option<Box<ImplicitNode<T>>>) -> bool {
+    match tree {
+      None => false,
+      Some(t) => [&t.lchild, &t.rchild].iter().any(|child| match child {
+        None => false,
+        Some(c) => c.priority > t.priority || ImplicitTreap::_invalid_priority(child),
+      }),
+    }
+  }
+  #[cfg(test)]
+  pub fn invalid_priority(&self) -> bool {
+    ImplicitTreap::_invalid_priority(&self.root)
+  }
+  #[cfg(test)]
+  fn _invalid_size(tree: &Option<Box<ImplicitNode<T>>>) -> bool {
+    match tree {
+      None => false,
+      Some(t) => {
+        t.size != 1 + ImplicitTreap::size(&t.lchild) + ImplicitTreap::size(&t.rchild)
+          || ImplicitTreap::_invalid_size(&t.lchild)
+          || ImplicitTreap::_invalid_size(&t.rchild)
+      }
+    }
+  }
+  #[cfg(test)]
+  pub fn invalid_size(&self) -> bool {
+    ImplicitTreap::_invalid_size(&self.root)
+  }
+}
+
+// Dropping the nodes recursively would overflow the stack on deep trees.
+impl<T> Drop for ImplicitTreap<T> {
+  fn drop(&mut self) {
+    let mut stack: Vec<Box<ImplicitNode<T>>> = self.root.take().into_iter().collect();
+    while let Some(mut t) = stack.pop() {
+      stack.extend(t.lchild.take());
+      stack.extend(t.rchild.take());
+    }
+  }
+}
+
+/// An iterator over the elements of an `ImplicitTreap` in order.
+pub struct ImplicitIter<'a, T> {
+  // Each node comes with whether its subtree is reversed once the pending
+  // flags above it are applied.
+  stack: Vec<(&'a ImplicitNode<T>, bool)>,
+  remaining: usize,
+}
+
+impl<'a, T> ImplicitIter<'a, T> {
+  fn push_spine(&mut self, mut tree: &'a Option<Box<ImplicitNode<T>>>, mut reversed: bool) {
+    while let Some(t) = tree {
+      reversed ^= t.reversed;
+      self.stack.push((t, reversed));
+      tree = if reversed { &t.rchild } else { &t.lchild };
+    }
+  }
+}
+
+impl<'a, T> Iterator for ImplicitIter<'a, T> {
+  type Item = &'a T;
+
+  fn next(&mut self) -> Option<&'a T> {
+    let (t, reversed) = self.stack.pop()?;
+    self.push_spine(if reversed { &t.lchild } else { &t.rchild }, reversed);
+    self.remaining -= 1;
+    Some(&t.value)
   }
+  fn size_hint(&self) -> (usize, Option<usize>) {
+    (self.remaining, Some(self.remaining))
+  }
+}
 
-  fn _insert(item: Box<Node<K>>, tree: &mut Option<Box<Node<K>>>) {
-    match tree {
-      None => {
-        *tree = Some(item);
-      }
-      Some(t) => match item.key.cmp(&t.key) {
-        Ordering::Less => {
-          Treap::_insert(item, &mut t.lchild);
-          if t.lchild.as_deref().unwrap().priority > t.priority {
-            Treap::rotate_right(t);
-          }
-        }
-        Ordering::Greater => {
-          Treap::_insert(item, &mut t.rchild);
-          if t.rchild.as_deref().unwrap().priority > t.priority {
-            Treap::rotate_left(t);
-          }
-        }
-        Ordering::Equal => {}
-      },
-    }
+impl<'a, T> ExactSizeIterator for ImplicitIter<'a, T> {}
+
+impl<'a, T> IntoIterator for &'a ImplicitTreap<T> {
+  type Item = &'a T;
+  type IntoIter = ImplicitIter<'a, T>;
+
+  fn into_iter(self) -> ImplicitIter<'a, T> {
+    self.iter()
   }
-  fn _find(key: &K, tree: &Option<Box<Node<K>>>) -> bool {
-    match tree {
-      None => false,
-      Some(t) => match key.cmp(&t.key) {
-        Ordering::Less => Treap::_find(key, &t.lchild),
-        Ordering::Greater => Treap::_find(key, &t.rchild),
-        Ordering::Equal => true,
-      },
-    }
+}
+
+/// Builds the sequence in linear time with seed 0.
+impl<T> FromIterator<T> for ImplicitTreap<T> {
+  fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> ImplicitTreap<T> {
+    ImplicitTreap::from_seq(iter, 0)
   }
-  fn root_delete(tree: &mut Option<Box<Node<K>>>) {
-    match tree {
-      None => {}
-      Some(t) => match &t.lchild {
-        None => match &t.rchild {
-          None => *tree = None,
-          Some(_) => {
-            Treap::rotate_left(t);
-            Treap::root_delete(&mut t.lchild);
-          }
-        },
-        Some(l) => match &t.rchild {
-          None => {
-            Treap::rotate_right(t);
-            Treap::root_delete(&mut t.rchild);
-          }
-          Some(r) => match l.priority.cmp(&r.priority) {
-            Ordering::Equal => {}
-            Ordering::Less => {
-              Treap::rotate_left(t);
-              Treap::root_delete(&mut t.lchild);
-            }
-            Ordering::Greater => {
-              Treap::rotate_right(t);
-              Treap::root_delete(&mut t.rchild);
-            }
-          },
-        },
-      },
+}
+
+impl<T> Extend<T> for ImplicitTreap<T> {
+  fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+    for value in iter {
+      self.push_back(value);
     }
   }
-  fn _delete(key: &K, tree: &mut Option<Box<Node<K>>>) {
-    match tree {
-      None => {}
-      Some(t) => match key.cmp(&t.key) {
-        Ordering::Less => Treap::_delete(key, &mut t.lchild),
-        Ordering::Greater => Treap::_delete(key, &mut t.rchild),
-        Ordering::Equal => Treap::root_delete(tree),
-      },
+}
+
+type IntervalNode<T> = Node<(T, T), T, Max, ()>;
+
+/// A set of half-open intervals `start..end` answering overlap and stabbing
+/// queries. The intervals are the keys of a `TreapMap` ordered by start and
+/// then end, with each end also stored as the value so that the `Max`
+/// aggregate of a subtree is its largest endpoint. The rotations, splits and
+/// merges of the map keep that aggregate up to date, and queries skip every
+/// subtree that ends too early or starts too late.
+pub struct IntervalTreap<T: Ord + Clone + Debug> {
+  map: TreapMap<(T, T), T, Max>,
+}
+
+impl<T: Ord + Clone + Debug> IntervalTreap<T> {
+  pub fn new(seed: u8) -> IntervalTreap<T> {
+    IntervalTreap {
+      map: TreapMap::new(seed),
     }
   }
-  #[cfg(test)]
-  fn _print(tree: &Box<Node<K>>) -> String {
-    let mut message = String::from("[");
-    message = [message, format!("{:?}(", tree.key)].concat();
-    match &tree.lchild {
-      None => {}
-      Some(l) => {
-        message = [message, Treap::_print(&l)].concat();
-      }
-    }
-    message = [message, String::from(")(")].concat();
-    match &tree.rchild {
-      None => {}
-      Some(r) => {
-        message = [message, Treap::_print(&r)].concat();
-      }
+  pub fn len(&self) -> usize {
+    self.map.len()
+  }
+  pub fn is_empty(&self) -> bool {
+    self.map.is_empty()
+  }
+  /// Inserts an interval, returning `true` if it was not already present.
+  ///
+  /// # Panics
+  ///
+  /// Panics if the interval is empty.
+  pub fn insert(&mut self, interval: std::ops::Range<T>) -> bool {
+    assert!(
+      interval.start < interval.end,
+      "insert: interval must not be empty"
+    );
+    let end = interval.end.clone();
+    self
+      .map
+      .insert((interval.start, interval.end), end)
+      .is_none()
+  }
+  /// Removes an interval, returning `true` if it was present.
+  pub fn remove(&mut self, interval: &std::ops::Range<T>) -> bool {
+    let key = (interval.start.clone(), interval.end.clone());
+    self.map.remove(&key).is_some()
+  }
+  pub fn contains(&self, interval: &std::ops::Range<T>) -> bool {
+    let key = (interval.start.clone(), interval.end.clone());
+    self.map.contains_key(&key)
+  }
+  /// Returns an iterator over the intervals sharing at least one point with
+  /// `range`, in ascending order of start and then end.
+  pub fn overlapping(&self, range: std::ops::Range<T>) -> Overlapping<'_, T> {
+    if range.start >= range.end {
+      return Overlapping {
+        stack: Vec::new(),
+        low: range.start,
+        high: Bound::Unbounded,
+      };
     }
-    [message, String::from(")]")].concat()
+    Overlapping::new(&self.map.root, range.start, Bound::Excluded(range.end))
   }
-  #[cfg(test)]
-  pub fn print(&self) -> String {
-    Treap::_print(self.root.as_ref().unwrap())
+  /// Returns an iterator over the intervals containing `point`, in ascending
+  /// order of start and then end.
+  pub fn containing(&self, point: T) -> Overlapping<'_, T> {
+    Overlapping::new(&self.map.root, point.clone(), Bound::Included(point))
   }
+
   #[cfg(test)]
-  fn _invalid_priority(tree: &Box<Node<K>>) -> bool {
-    let mut ng = false;
-    match &tree.lchild {
-      None => {}
-      Some(l) => {
-        ng |= l.priority > tree.priority;
-        ng |= Treap::_invalid_priority(l);
-      }
-    }
-    match &tree.rchild {
-      None => {}
-      Some(r) => {
-        ng |= r.priority > tree.priority;
-        ng |= Treap::_invalid_priority(r);
+  fn _invalid_max_end(tree: &Option<Box<IntervalNode<T>>>) -> bool {
+    match tree {
+      None => false,
+      Some(t) => {
+        let max = [&t.lchild, &t.rchild]
+          .iter()
+          .filter_map(|c| c.as_ref().and_then(|c| c.summary.as_ref()))
+          .fold(&t.key.1, |max, end| max.max(end));
+        t.summary.as_ref() != Some(max)
+          || IntervalTreap::_invalid_max_end(&t.lchild)
+          || IntervalTreap::_invalid_max_end(&t.rchild)
       }
     }
-    ng
   }
   #[cfg(test)]
-  pub fn invalid_priority(&self) -> bool {
-    Treap::_invalid_priority(self.root.as_ref().unwrap())
+  pub fn invalid_max_end(&self) -> bool {
+    IntervalTreap::_invalid_max_end(&self.map.root)
   }
-  #[cfg(test)]
-  fn get_seq_in_order(tree: &Box<Node<K>>, seq: &mut Vec<K>)
-  where
-    K: Copy,
-  {
-    match &tree.lchild {
-      None => {}
-      Some(l) => {
-        Treap::get_seq_in_order(l, seq);
-      }
+}
+
+/// Builds the set with seed 0.
+impl<T: Ord + Clone + Debug> FromIterator<std::ops::Range<T>> for IntervalTreap<T> {
+  fn from_iter<I: IntoIterator<Item = std::ops::Range<T>>>(iter: I) -> IntervalTreap<T> {
+    let mut set = IntervalTreap::new(0);
+    set.extend(iter);
+    set
+  }
+}
+
+impl<T: Ord + Clone + Debug> Extend<std::ops::Range<T>> for IntervalTreap<T> {
+  fn extend<I: IntoIterator<Item = std::ops::Range<T>>>(&mut self, iter: I) {
+    for interval in iter {
+      self.insert(interval);
     }
-    seq.push(tree.key);
-    match &tree.rchild {
-      None => {}
-      Some(r) => {
-        Treap::get_seq_in_order(r, seq);
+  }
+}
+
+/// An iterator over the intervals of an `IntervalTreap` that end after `low`
+/// and start before `high`, as `(start, end)` pairs in ascending order.
+pub struct Overlapping<'a, T: Ord + Clone + Debug> {
+  stack: Vec<&'a IntervalNode<T>>,
+  low: T,
+  high: Bound<T>,
+}
+
+impl<'a, T: Ord + Clone + Debug> Overlapping<'a, T> {
+  fn new(root: &'a Option<Box<IntervalNode<T>>>, low: T, high: Bound<T>) -> Overlapping<'a, T> {
+    let mut iter = Overlapping {
+      stack: Vec::new(),
+      low,
+      high,
+    };
+    iter.push_spine(root);
+    iter
+  }
+  /// Pushes the left spine of `tree`, stopping at the first subtree whose
+  /// intervals all end by `low`.
+  fn push_spine(&mut self, mut tree: &'a Option<Box<IntervalNode<T>>>) {
+    while let Some(t) = tree {
+      match &t.summary {
+        Some(max) if *max > self.low => {}
+        _ => return,
       }
+      self.stack.push(t);
+      tree = &t.lchild;
     }
   }
-  #[cfg(test)]
-  pub fn invalid_key(&self) -> bool
-  where
-    K: Copy,
-  {
-    let mut seq: Vec<K> = Vec::new();
-    Treap::get_seq_in_order(self.root.as_ref().unwrap(), &mut seq);
-    let mut ng = false;
-    for i in 1..seq.len() - 1 {
-      ng |= seq[i] >= seq[i + 1];
+  fn starts_before_high(&self, start: &T) -> bool {
+    match &self.high {
+      Bound::Included(high) => start <= high,
+      Bound::Excluded(high) => start < high,
+      Bound::Unbounded => true,
     }
-    ng
   }
 }
 
-impl<K: Ord + Debug> BST<K> for Treap<K> {
-  fn insert(&mut self, key: K) {
-    let item: Box<Node<K>> = Box::new(Node {
-      key,
-      priority: self.rng.gen(),
-      lchild: None,
-      rchild: None,
-    });
-    Treap::_insert(item, &mut self.root);
-  }
-  fn find(&mut self, key: K) -> bool {
-    Treap::_find(&key, &self.root)
-  }
-  fn delete(&mut self, key: K) {
-    Treap::_delete(&key, &mut self.root);
+impl<'a, T: Ord + Clone + Debug> Iterator for Overlapping<'a, T> {
+  type Item = (&'a T, &'a T);
+
+  fn next(&mut self) -> Option<(&'a T, &'a T)> {
+    while let Some(t) = self.stack.pop() {
+      if !self.starts_before_high(&t.key.0) {
+        // Every interval after this one starts later still.
+        self.stack.clear();
+        return None;
+      }
+      self.push_spine(&t.rchild);
+      if t.key.1 > self.low {
+        return Some((&t.key.0, &t.key.1));
+      }
+    }
+    None
   }
 }
 
 #[cfg(test)]
 mod tests {
+  use super::Bound;
+  use super::ImplicitTreap;
+  use super::IntervalTreap;
+  use super::Monoid;
   use super::Treap;
+  use super::TreapMap;
   use super::BST;
+  use crate::{Max, Min, Sum, Update};
   use assert_str::assert_str_eq;
 
   #[test]
@@ -244,8 +1841,8 @@ mod tests {
     let mut tree: Treap<u64> = Treap::new(77);
     tree.insert(10);
     assert_str_eq!(tree.print(), "[10()()]");
-    assert_eq!(tree.invalid_priority(), false);
-    assert_eq!(tree.invalid_key(), false);
+    assert!(!tree.invalid_priority());
+    assert!(!tree.invalid_key());
   }
   #[test]
   fn test_insert_2() {
@@ -254,8 +1851,8 @@ mod tests {
     assert_str_eq!(tree.print(), "[10()()]");
     tree.insert(50);
     assert_str_eq!(tree.print(), "[10()([50()()])]");
-    assert_eq!(tree.invalid_priority(), false);
-    assert_eq!(tree.invalid_key(), false);
+    assert!(!tree.invalid_priority());
+    assert!(!tree.invalid_key());
   }
   #[test]
   fn test_insert_3() {
@@ -266,16 +1863,16 @@ mod tests {
     assert_str_eq!(tree.print(), "[10()([50()()])]");
     tree.insert(5);
     assert_str_eq!(tree.print(), "[10([5()()])([50()()])]");
-    assert_eq!(tree.invalid_priority(), false);
-    assert_eq!(tree.invalid_key(), false);
+    assert!(!tree.invalid_priority());
+    assert!(!tree.invalid_key());
     tree.insert(5);
     assert_str_eq!(tree.print(), "[10([5()()])([50()()])]");
-    assert_eq!(tree.invalid_priority(), false);
-    assert_eq!(tree.invalid_key(), false);
+    assert!(!tree.invalid_priority());
+    assert!(!tree.invalid_key());
     tree.insert(10);
     assert_str_eq!(tree.print(), "[10([5()()])([50()()])]");
-    assert_eq!(tree.invalid_priority(), false);
-    assert_eq!(tree.invalid_key(), false);
+    assert!(!tree.invalid_priority());
+    assert!(!tree.invalid_key());
   }
 
   #[test]
@@ -283,73 +1880,73 @@ mod tests {
     let mut tree: Treap<u64> = Treap::new(77);
     tree.insert(10);
     assert_str_eq!(tree.print(), "[10()()]");
-    assert_eq!(tree.invalid_priority(), false);
-    assert_eq!(tree.invalid_key(), false);
+    assert!(!tree.invalid_priority());
+    assert!(!tree.invalid_key());
     tree.insert(50);
     assert_str_eq!(tree.print(), "[10()([50()()])]");
-    assert_eq!(tree.invalid_priority(), false);
-    assert_eq!(tree.invalid_key(), false);
+    assert!(!tree.invalid_priority());
+    assert!(!tree.invalid_key());
     tree.insert(5);
     assert_str_eq!(tree.print(), "[10([5()()])([50()()])]");
-    assert_eq!(tree.invalid_priority(), false);
-    assert_eq!(tree.invalid_key(), false);
+    assert!(!tree.invalid_priority());
+    assert!(!tree.invalid_key());
     tree.insert(100);
     assert_str_eq!(tree.print(), "[10([5()()])([50()([100()()])])]");
-    assert_eq!(tree.invalid_priority(), false);
-    assert_eq!(tree.invalid_key(), false);
+    assert!(!tree.invalid_priority());
+    assert!(!tree.invalid_key());
     tree.insert(200);
     assert_str_eq!(tree.print(), "[10([5()()])([200([50()([100()()])])()])]");
-    assert_eq!(tree.invalid_priority(), false);
-    assert_eq!(tree.invalid_key(), false);
+    assert!(!tree.invalid_priority());
+    assert!(!tree.invalid_key());
     tree.insert(400);
     assert_str_eq!(
       tree.print(),
       "[400([10([5()()])([200([50()([100()()])])()])])()]"
     );
-    assert_eq!(tree.invalid_priority(), false);
-    assert_eq!(tree.invalid_key(), false);
+    assert!(!tree.invalid_priority());
+    assert!(!tree.invalid_key());
     tree.insert(300);
     assert_str_eq!(
       tree.print(),
       "[400([10([5()()])([200([50()([100()()])])([300()()])])])()]"
     );
-    assert_eq!(tree.invalid_priority(), false);
-    assert_eq!(tree.invalid_key(), false);
+    assert!(!tree.invalid_priority());
+    assert!(!tree.invalid_key());
     tree.insert(35);
     assert_str_eq!(
       tree.print(),
       "[400([10([5()()])([35()([200([50()([100()()])])([300()()])])])])()]"
     );
-    assert_eq!(tree.invalid_priority(), false);
-    assert_eq!(tree.invalid_key(), false);
+    assert!(!tree.invalid_priority());
+    assert!(!tree.invalid_key());
   }
 
   #[test]
   fn test_find() {
     let mut tree: Treap<u64> = Treap::new(77);
-    assert_eq!(tree.find(0), false);
+    assert!(!tree.find(&0));
     tree.insert(10);
-    assert_eq!(tree.find(0), false);
-    assert_eq!(tree.find(10), true);
+    assert!(!tree.find(&0));
+    assert!(tree.find(&10));
     tree.insert(50);
-    assert_eq!(tree.find(0), false);
-    assert_eq!(tree.find(10), true);
-    assert_eq!(tree.find(50), true);
+    assert!(!tree.find(&0));
+    assert!(tree.find(&10));
+    assert!(tree.find(&50));
   }
   #[test]
   fn test_delete() {
     let mut tree: Treap<u64> = Treap::new(77);
-    assert_eq!(tree.find(0), false);
+    assert!(!tree.find(&0));
     tree.insert(10);
-    assert_eq!(tree.find(0), false);
-    assert_eq!(tree.find(10), true);
-    tree.delete(10);
-    assert_eq!(tree.find(0), false);
-    assert_eq!(tree.find(10), false);
+    assert!(!tree.find(&0));
+    assert!(tree.find(&10));
+    tree.delete(&10);
+    assert!(!tree.find(&0));
+    assert!(!tree.find(&10));
     tree.insert(50);
-    assert_eq!(tree.find(0), false);
-    assert_eq!(tree.find(10), false);
-    assert_eq!(tree.find(50), true);
+    assert!(!tree.find(&0));
+    assert!(!tree.find(&10));
+    assert!(tree.find(&50));
   }
   #[test]
   fn test_delete_2() {
@@ -366,31 +1963,790 @@ mod tests {
       tree.print(),
       "[400([10([5()()])([35()([200([50()([100()()])])([300()()])])])])()]"
     );
-    tree.delete(50);
+    tree.delete(&50);
     assert_str_eq!(
       tree.print(),
       "[400([10([5()()])([35()([200([100()()])([300()()])])])])()]"
     );
-    assert_eq!(tree.invalid_priority(), false);
-    assert_eq!(tree.invalid_key(), false);
-    tree.delete(35);
+    assert!(!tree.invalid_priority());
+    assert!(!tree.invalid_key());
+    tree.delete(&35);
     assert_str_eq!(
       tree.print(),
       "[400([10([5()()])([200([100()()])([300()()])])])()]"
     );
-    assert_eq!(tree.invalid_priority(), false);
-    assert_eq!(tree.invalid_key(), false);
-    tree.delete(400);
+    assert!(!tree.invalid_priority());
+    assert!(!tree.invalid_key());
+    tree.delete(&400);
     assert_str_eq!(tree.print(), "[10([5()()])([200([100()()])([300()()])])]");
-    assert_eq!(tree.invalid_priority(), false);
-    assert_eq!(tree.invalid_key(), false);
-    tree.delete(200);
+    assert!(!tree.invalid_priority());
+    assert!(!tree.invalid_key());
+    tree.delete(&200);
     assert_str_eq!(tree.print(), "[10([5()()])([300([100()()])()])]");
-    assert_eq!(tree.invalid_priority(), false);
-    assert_eq!(tree.invalid_key(), false);
-    tree.delete(10);
+    assert!(!tree.invalid_priority());
+    assert!(!tree.invalid_key());
+    tree.delete(&10);
     assert_str_eq!(tree.print(), "[5()([300([100()()])()])]");
-    assert_eq!(tree.invalid_priority(), false);
-    assert_eq!(tree.invalid_key(), false);
+    assert!(!tree.invalid_priority());
+    assert!(!tree.invalid_key());
+  }
+
+  #[test]
+  fn test_map_insert() {
+    let mut map: TreapMap<u64, &str> = TreapMap::new(77);
+    assert_eq!(map.insert(10, "a"), None);
+    assert_eq!(map.insert(50, "b"), None);
+    assert_eq!(map.insert(5, "c"), None);
+    assert_str_eq!(map.print(), "[10([5()()])([50()()])]");
+    assert_eq!(map.insert(10, "d"), Some("a"));
+    assert_str_eq!(map.print(), "[10([5()()])([50()()])]");
+    // Maps without an action are read through a shared borrow.
+    let shared = &map;
+    assert_eq!(shared.get(&10), Some(&"d"));
+    assert_eq!(shared.get(&5), Some(&"c"));
+    assert_eq!(shared.get(&7), None);
+    assert!(!map.invalid_priority());
+    assert!(!map.invalid_key());
+  }
+  #[test]
+  fn test_map_get_mut() {
+    let mut map: TreapMap<u64, u64> = TreapMap::new(77);
+    map.insert(10, 1);
+    map.insert(50, 2);
+    *map.get_mut(&50).unwrap() += 40;
+    assert_eq!(map.get(&50), Some(&42));
+    assert_eq!(map.get_mut(&7), None);
+  }
+  #[test]
+  fn test_map_remove() {
+    let mut map: TreapMap<u64, u64> = TreapMap::new(77);
+    for key in [10, 50, 5, 100, 200, 400, 300, 35].iter() {
+      map.insert(*key, *key * 2);
+    }
+    assert_eq!(map.remove(&200), Some(400));
+    assert_eq!(map.remove(&200), None);
+    assert!(!map.contains_key(&200));
+    assert_eq!(map.get(&300), Some(&600));
+    assert!(!map.invalid_priority());
+    assert!(!map.invalid_key());
+  }
+  #[test]
+  fn test_find_borrowed() {
+    let mut tree: Treap<String> = Treap::new(77);
+    tree.insert(String::from("banana"));
+    tree.insert(String::from("apple"));
+    tree.insert(String::from("cherry"));
+    assert!(tree.find("apple"));
+    assert!(!tree.find("durian"));
+    tree.delete("apple");
+    assert!(!tree.find("apple"));
+    assert!(tree.find("cherry"));
+  }
+  #[test]
+  fn test_insert_delete_result() {
+    let mut tree: Treap<u64> = Treap::new(77);
+    assert!(tree.insert(10));
+    assert!(tree.insert(50));
+    assert!(!tree.insert(10));
+    assert_eq!(tree.delete(&10), Some(10));
+    assert_eq!(tree.delete(&10), None);
+    assert_eq!(tree.delete(&7), None);
+    assert!(tree.insert(10));
+    assert_eq!(tree.delete(&50), Some(50));
+  }
+  #[test]
+  fn test_iter() {
+    let mut tree: Treap<u64> = Treap::new(77);
+    assert_eq!(tree.iter().next(), None);
+    for key in [10, 50, 5, 200, 400, 100].iter() {
+      tree.insert(*key);
+    }
+    let keys: Vec<u64> = tree.iter().cloned().collect();
+    assert_eq!(keys, vec![5, 10, 50, 100, 200, 400]);
+    let keys: Vec<u64> = tree.iter().rev().cloned().collect();
+    assert_eq!(keys, vec![400, 200, 100, 50, 10, 5]);
+    let mut iter = tree.iter();
+    assert_eq!(iter.len(), 6);
+    assert_eq!(iter.next(), Some(&5));
+    assert_eq!(iter.next_back(), Some(&400));
+    assert_eq!(iter.next_back(), Some(&200));
+    assert_eq!(iter.len(), 3);
+    assert_eq!(iter.next(), Some(&10));
+    assert_eq!(iter.next(), Some(&50));
+    assert_eq!(iter.next_back(), Some(&100));
+    assert_eq!(iter.next(), None);
+    assert_eq!(iter.next_back(), None);
+    let mut sum = 0;
+    for key in &tree {
+      sum += key;
+    }
+    assert_eq!(sum, 765);
+  }
+  #[test]
+  fn test_into_iter() {
+    let mut tree: Treap<u64> = Treap::new(77);
+    for key in [10, 50, 5, 200, 400, 100].iter() {
+      tree.insert(*key);
+    }
+    let mut iter = tree.into_iter();
+    assert_eq!(iter.len(), 6);
+    assert_eq!(iter.next_back(), Some(400));
+    let keys: Vec<u64> = iter.collect();
+    assert_eq!(keys, vec![5, 10, 50, 100, 200]);
+  }
+  #[test]
+  fn test_map_iter() {
+    let mut map: TreapMap<u64, &str> = TreapMap::new(77);
+    map.insert(10, "a");
+    map.insert(5, "b");
+    map.insert(50, "c");
+    let entries: Vec<(&u64, &&str)> = map.iter().collect();
+    assert_eq!(entries, vec![(&5, &"b"), (&10, &"a"), (&50, &"c")]);
+    let entries: Vec<(u64, &str)> = map.into_iter().rev().collect();
+    assert_eq!(entries, vec![(50, "c"), (10, "a"), (5, "b")]);
+  }
+  #[test]
+  fn test_into_iter_both_ends() {
+    let mut tree: Treap<u64> = Treap::new(77);
+    for i in 0..10_000 {
+      tree.insert(i * 389 % 10_000);
+    }
+    let mut iter = tree.into_iter();
+    for i in 0..2_000 {
+      assert_eq!(iter.next(), Some(i));
+      assert_eq!(iter.next_back(), Some(9_999 - i));
+      assert_eq!(iter.len(), 10_000 - 2 * (i as usize + 1));
+    }
+    let keys: Vec<u64> = iter.rev().collect();
+    assert_eq!(keys, (2_000..8_000).rev().collect::<Vec<u64>>());
+    // A partly consumed iterator drops the rest of the tree without recursing.
+    let mut tree: Treap<u64> = Treap::new(77);
+    for key in 0..100_000 {
+      tree.insert(key);
+    }
+    let mut iter = tree.into_iter();
+    assert_eq!(iter.next_back(), Some(99_999));
+    assert_eq!(iter.next(), Some(0));
+  }
+  #[test]
+  fn test_range() {
+    let mut tree: Treap<u64> = Treap::new(77);
+    for key in [10, 50, 5, 200, 400, 100].iter() {
+      tree.insert(*key);
+    }
+    let keys: Vec<u64> = tree.range(10..200).cloned().collect();
+    assert_eq!(keys, vec![10, 50, 100]);
+    let keys: Vec<u64> = tree.range(11..=200).cloned().collect();
+    assert_eq!(keys, vec![50, 100, 200]);
+    let keys: Vec<u64> = tree.range(..50).rev().cloned().collect();
+    assert_eq!(keys, vec![10, 5]);
+    let keys: Vec<u64> = tree.range(300..).cloned().collect();
+    assert_eq!(keys, vec![400]);
+    assert_eq!(tree.range(60..90).next(), None);
+    assert_eq!(tree.range(500..).next_back(), None);
+    let mut range = tree.range(5..=400);
+    assert_eq!(range.next(), Some(&5));
+    assert_eq!(range.next_back(), Some(&400));
+    assert_eq!(range.next_back(), Some(&200));
+    assert_eq!(range.next(), Some(&10));
+    assert_eq!(range.next(), Some(&50));
+    assert_eq!(range.next(), Some(&100));
+    assert_eq!(range.next(), None);
+    assert_eq!(range.next_back(), None);
+  }
+  #[test]
+  fn test_range_bounds() {
+    use std::collections::BTreeSet;
+    use std::ops::Bound;
+    let mut tree: Treap<u64> = Treap::new(77);
+    let mut expected = BTreeSet::new();
+    for key in (0..40).map(|i| i * 7 % 31) {
+      tree.insert(key);
+      expected.insert(key);
+    }
+    let bounds = |i: u64| vec![Bound::Included(i), Bound::Excluded(i), Bound::Unbounded];
+    for lo in 0..32 {
+      for hi in lo..32 {
+        for start in bounds(lo) {
+          for end in bounds(hi) {
+            if lo == hi && start == Bound::Excluded(lo) && end == Bound::Excluded(hi) {
+              continue;
+            }
+            let keys: Vec<&u64> = tree.range((start, end)).collect();
+            let want: Vec<&u64> = expected.range((start, end)).collect();
+            assert_eq!(keys, want);
+            let keys: Vec<&u64> = tree.range((start, end)).rev().collect();
+            let want: Vec<&u64> = expected.range((start, end)).rev().collect();
+            assert_eq!(keys, want);
+          }
+        }
+      }
+    }
+  }
+  #[test]
+  fn test_map_range() {
+    use std::ops::Bound;
+    let mut map: TreapMap<String, u64> = TreapMap::new(77);
+    map.insert(String::from("apple"), 1);
+    map.insert(String::from("banana"), 2);
+    map.insert(String::from("cherry"), 3);
+    let values: Vec<u64> = map
+      .range::<str, _>((Bound::Included("b"), Bound::Unbounded))
+      .map(|(_, v)| *v)
+      .collect();
+    assert_eq!(values, vec![2, 3]);
+  }
+  #[test]
+  fn test_select_rank() {
+    let mut tree: Treap<u64> = Treap::new(77);
+    assert_eq!(tree.len(), 0);
+    assert_eq!(tree.select(0), None);
+    assert_eq!(tree.rank(&10), 0);
+    for key in [10, 50, 5, 200, 400, 100, 300, 35].iter() {
+      tree.insert(*key);
+      assert!(!tree.invalid_size());
+    }
+    assert_eq!(tree.len(), 8);
+    assert_eq!(tree.select(0), Some(&5));
+    assert_eq!(tree.select(3), Some(&50));
+    assert_eq!(tree.select(7), Some(&400));
+    assert_eq!(tree.select(8), None);
+    assert_eq!(tree.rank(&5), 0);
+    assert_eq!(tree.rank(&50), 3);
+    assert_eq!(tree.rank(&60), 4);
+    assert_eq!(tree.rank(&1000), 8);
+    tree.find(&300);
+    assert!(!tree.invalid_size());
+    tree.delete(&50);
+    tree.delete(&35);
+    tree.delete(&7);
+    assert!(!tree.invalid_size());
+    assert_eq!(tree.len(), 6);
+    assert_eq!(tree.select(2), Some(&100));
+    assert_eq!(tree.rank(&300), 4);
+    for (i, key) in tree.iter().enumerate() {
+      assert_eq!(tree.select(i), Some(key));
+      assert_eq!(tree.rank(key), i);
+    }
+  }
+  #[test]
+  fn test_split_off() {
+    let mut tree: Treap<u64> = Treap::new(77);
+    for key in [10, 50, 5, 100, 200, 400, 300, 35].iter() {
+      tree.insert(*key);
+    }
+    let right = tree.split_off(&100);
+    assert_eq!(
+      tree.iter().cloned().collect::<Vec<u64>>(),
+      vec![5, 10, 35, 50]
+    );
+    assert_eq!(
+      right.iter().cloned().collect::<Vec<u64>>(),
+      vec![100, 200, 300, 400]
+    );
+    assert!(!tree.invalid_priority());
+    assert!(!tree.invalid_size());
+    assert!(!right.invalid_priority());
+    assert!(!right.invalid_size());
+    let mut empty = tree.split_off(&1000);
+    assert!(empty.is_empty());
+    assert_eq!(tree.len(), 4);
+    empty.insert(1);
+    assert_eq!(empty.len(), 1);
+  }
+  #[test]
+  fn test_join_append() {
+    let mut tree: Treap<u64> = Treap::new(77);
+    for key in 0..100 {
+      tree.insert(key);
+    }
+    let right = tree.split_off(&60);
+    let tree = Treap::join(tree, right);
+    assert_eq!(tree.len(), 100);
+    assert!(tree.iter().cloned().eq(0..100));
+    assert!(!tree.invalid_priority());
+    assert!(!tree.invalid_size());
+
+    let mut left: Treap<u64> = Treap::new(1);
+    let mut right: Treap<u64> = Treap::new(2);
+    for key in 0..50 {
+      left.insert(key * 2);
+      right.insert(key * 2 + 100);
+    }
+    right.append(&mut left);
+    assert!(left.is_empty());
+    assert_eq!(right.len(), 100);
+    assert!(!right.invalid_priority());
+    assert!(!right.invalid_size());
+    let mut odd: Treap<u64> = Treap::new(3);
+    for key in 0..50 {
+      odd.insert(key * 2 + 1);
+    }
+    right.append(&mut odd);
+    assert!(odd.is_empty());
+    assert_eq!(right.len(), 150);
+    assert!(right.iter().cloned().take(100).eq(0..100));
+    assert!(!right.invalid_priority());
+    assert!(!right.invalid_size());
+  }
+  #[test]
+  #[should_panic]
+  fn test_join_overlap() {
+    let mut left: Treap<u64> = Treap::new(1);
+    let mut right: Treap<u64> = Treap::new(2);
+    left.insert(10);
+    right.insert(5);
+    Treap::join(left, right);
+  }
+  #[test]
+  fn test_set_operations() {
+    use std::collections::BTreeSet;
+    let build = |seed: u8, keys: &Vec<u64>| {
+      let mut tree: Treap<u64> = Treap::new(seed);
+      for key in keys.iter() {
+        tree.insert(*key);
+      }
+      tree
+    };
+    let a: Vec<u64> = (0..300).map(|i| i * 7 % 401).collect();
+    let b: Vec<u64> = (0..200).map(|i| i * 13 % 307).collect();
+    let sa: BTreeSet<u64> = a.iter().cloned().collect();
+    let sb: BTreeSet<u64> = b.iter().cloned().collect();
+    let check = |tree: Treap<u64>, want: Vec<u64>| {
+      assert!(!tree.invalid_priority());
+      assert!(!tree.invalid_key());
+      assert!(!tree.invalid_size());
+      assert_eq!(tree.iter().cloned().collect::<Vec<u64>>(), want);
+    };
+    check(
+      build(1, &a).union(build(2, &b)),
+      sa.union(&sb).cloned().collect(),
+    );
+    check(
+      build(1, &a).intersection(build(2, &b)),
+      sa.intersection(&sb).cloned().collect(),
+    );
+    check(
+      build(1, &a).difference(build(2, &b)),
+      sa.difference(&sb).cloned().collect(),
+    );
+    check(
+      build(2, &b).difference(build(1, &a)),
+      sb.difference(&sa).cloned().collect(),
+    );
+    check(
+      build(1, &a).symmetric_difference(build(2, &b)),
+      sa.symmetric_difference(&sb).cloned().collect(),
+    );
+    check(
+      build(1, &a).union(Treap::new(3)),
+      sa.iter().cloned().collect(),
+    );
+    assert!(build(1, &a).intersection(Treap::new(3)).is_empty());
+  }
+  #[test]
+  fn test_map_append_overlap() {
+    let mut a: TreapMap<u64, &str> = TreapMap::new(1);
+    let mut b: TreapMap<u64, &str> = TreapMap::new(2);
+    for key in 0..20 {
+      a.insert(key * 2, "a");
+      b.insert(key * 3, "b");
+    }
+    a.append(&mut b);
+    assert!(b.is_empty());
+    assert_eq!(a.len(), 20 + 20 - 7);
+    assert_eq!(a.get(&6), Some(&"b"));
+    assert_eq!(a.get(&4), Some(&"a"));
+    assert_eq!(a.get(&9), Some(&"b"));
+    assert!(!a.invalid_priority());
+    assert!(!a.invalid_size());
+  }
+  #[test]
+  fn test_from_sorted_iter() {
+    let tree: Treap<u64> = Treap::from_sorted_iter(0..100_000, 77);
+    assert_eq!(tree.len(), 100_000);
+    assert!(!tree.invalid_priority());
+    assert!(!tree.invalid_key());
+    assert!(!tree.invalid_size());
+    assert!(tree.iter().cloned().eq(0..100_000));
+    let tree: Treap<u64> = Treap::from_sorted_iter(vec![], 77);
+    assert!(tree.is_empty());
+  }
+  #[test]
+  #[should_panic]
+  fn test_from_sorted_iter_unsorted() {
+    Treap::from_sorted_iter(vec![1, 3, 2], 77);
+  }
+  #[test]
+  fn test_from_iter_extend() {
+    let mut tree: Treap<u64> = vec![50, 10, 400, 5, 10, 200, 50].into_iter().collect();
+    assert_eq!(tree.len(), 5);
+    assert!(!tree.invalid_priority());
+    assert!(!tree.invalid_size());
+    tree.extend(vec![100, 5, 300]);
+    let keys: Vec<u64> = tree.iter().cloned().collect();
+    assert_eq!(keys, vec![5, 10, 50, 100, 200, 300, 400]);
+    let map: TreapMap<u64, &str> = vec![(2, "a"), (1, "b"), (2, "c")].into_iter().collect();
+    let entries: Vec<(u64, &str)> = map.into_iter().collect();
+    assert_eq!(entries, vec![(1, "b"), (2, "c")]);
+  }
+  #[test]
+  fn test_sorted_insert_large() {
+    let mut tree: Treap<u64> = Treap::new(77);
+    for key in 0..1_000_000 {
+      assert!(tree.insert(key));
+    }
+    assert_eq!(tree.len(), 1_000_000);
+    assert!(tree.find(&0));
+    assert!(tree.find(&999_999));
+    for key in (0..1_000_000).step_by(2) {
+      assert_eq!(tree.delete(&key), Some(key));
+    }
+    assert_eq!(tree.len(), 500_000);
+    assert!(tree.iter().cloned().eq((1..1_000_000).step_by(2)));
+    assert!(!tree.invalid_priority());
+    assert!(!tree.invalid_size());
+  }
+  #[test]
+  fn test_implicit_insert_remove() {
+    let mut seq: ImplicitTreap<char> = ImplicitTreap::new(77);
+    assert!(seq.is_empty());
+    assert_eq!(seq.get(0), None);
+    seq.insert(0, 'b');
+    seq.insert(0, 'a');
+    seq.insert(2, 'd');
+    seq.insert(2, 'c');
+    seq.push_back('e');
+    assert_eq!(seq.iter().collect::<String>(), "abcde");
+    assert_eq!(seq.get(2), Some(&'c'));
+    assert_eq!(seq.get(5), None);
+    *seq.get_mut(4).unwrap() = 'f';
+    assert_eq!(seq.remove(1), Some('b'));
+    assert_eq!(seq.remove(4), None);
+    assert_eq!(seq.iter().collect::<String>(), "acdf");
+    assert_eq!(seq.len(), 4);
+    assert!(!seq.invalid_priority());
+    assert!(!seq.invalid_size());
+  }
+  #[test]
+  #[should_panic]
+  fn test_implicit_insert_out_of_bounds() {
+    let mut seq: ImplicitTreap<u64> = ImplicitTreap::new(77);
+    seq.insert(1, 0);
+  }
+  #[test]
+  fn test_implicit_split_off_join() {
+    let mut seq: ImplicitTreap<u64> = (0..100).collect();
+    assert!(!seq.invalid_priority());
+    let mut right = seq.split_off(30);
+    assert!(seq.iter().cloned().eq(0..30));
+    assert!(right.iter().cloned().eq(30..100));
+    let empty = right.split_off(70);
+    assert!(empty.is_empty());
+    right.extend(100..110);
+    let mut seq = ImplicitTreap::join(right, seq);
+    assert!(seq.iter().cloned().eq((30..110).chain(0..30)));
+    let mut other: ImplicitTreap<u64> = ImplicitTreap::new(3);
+    other.push_back(7);
+    seq.append(&mut other);
+    assert!(other.is_empty());
+    assert_eq!(seq.get(110), Some(&7));
+    assert!(!seq.invalid_priority());
+    assert!(!seq.invalid_size());
+  }
+  #[test]
+  fn test_implicit_reverse() {
+    let mut seq: ImplicitTreap<u64> = ImplicitTreap::new(77);
+    let mut expected: Vec<u64> = (0..200).collect();
+    seq.extend(0..200);
+    for (i, (start, end)) in [(10, 50), (0, 200), (30, 31), (5, 5), (100, 190), (40, 120)]
+      .iter()
+      .enumerate()
+    {
+      seq.reverse(*start..*end);
+      expected[*start..*end].reverse();
+      // Reads through pending flags must agree with reads that push them.
+      for (index, value) in expected.iter().enumerate().skip(i) {
+        assert_eq!(seq.get(index), Some(value));
+      }
+      assert!(seq.iter().eq(expected.iter()));
+      assert_eq!(seq.get_mut(i).copied(), Some(expected[i]));
+    }
+    seq.reverse(..);
+    seq.reverse(..=3);
+    expected.reverse();
+    expected[..=3].reverse();
+    assert!(seq.iter().eq(expected.iter()));
+    seq.insert(150, 1000);
+    expected.insert(150, 1000);
+    assert_eq!(seq.remove(20), Some(expected.remove(20)));
+    assert!(seq.iter().eq(expected.iter()));
+    assert!(!seq.invalid_priority());
+    assert!(!seq.invalid_size());
+  }
+  #[test]
+  #[should_panic]
+  fn test_implicit_reverse_out_of_bounds() {
+    let mut seq: ImplicitTreap<u64> = (0..10).collect();
+    seq.reverse(5..11);
+  }
+  #[test]
+  fn test_implicit_large() {
+    let mut seq: ImplicitTreap<u64> = ImplicitTreap::new(77);
+    for value in 0..100_000 {
+      seq.push_back(value);
+    }
+    for value in 0..1_000 {
+      seq.insert(value as usize * 100, value);
+    }
+    assert_eq!(seq.len(), 101_000);
+    assert_eq!(seq.get(100), Some(&1));
+    seq.reverse(..);
+    assert_eq!(seq.get(100_999), Some(&0));
+    assert!(!seq.invalid_priority());
+    assert!(!seq.invalid_size());
+  }
+  struct Concat;
+  impl Monoid<char> for Concat {
+    type Summary = String;
+    fn identity() -> String {
+      String::new()
+    }
+    fn lift(value: &char) -> String {
+      value.to_string()
+    }
+    fn combine(left: &String, right: &String) -> String {
+      [left.as_str(), right.as_str()].concat()
+    }
+  }
+  #[test]
+  fn test_fold() {
+    let mut map: TreapMap<u64, u64, Sum> = TreapMap::new(77);
+    for i in 0..1000 {
+      map.insert(i * 389 % 1000, i);
+    }
+    for (lo, hi) in [
+      (0, 1000),
+      (100, 200),
+      (500, 400),
+      (999, 2000),
+      (3, 4),
+      (4, 4),
+    ]
+    .iter()
+    {
+      let expected: u64 = map.range(lo..hi).map(|(_, v)| *v).sum();
+      assert_eq!(map.fold(lo..hi), expected);
+    }
+    assert_eq!(map.fold(..), (0..1000).sum());
+    assert_eq!(
+      map.fold((Bound::Excluded(10), Bound::Included(20))),
+      map.fold(11..=20)
+    );
+    let old = map.insert(5, 1_000_000).unwrap();
+    assert_eq!(map.fold(5..6), 1_000_000);
+    assert_eq!(map.remove(&5), Some(1_000_000));
+    assert_eq!(map.fold(..), (0..1000).sum::<u64>() - old);
+    let right = map.split_off(&500);
+    assert_eq!(map.fold(..) + right.fold(..), (0..1000).sum::<u64>() - old);
+    assert_eq!(right.fold(..500), 0);
+  }
+  #[test]
+  fn test_fold_min_max() {
+    let mut min: TreapMap<u64, i64, Min> = TreapMap::new(77);
+    let mut max: TreapMap<u64, i64, Max> = TreapMap::new(77);
+    for key in 0..100 {
+      let value = (key as i64 * 37 % 101) - 50;
+      min.insert(key, value);
+      max.insert(key, value);
+    }
+    for (lo, hi) in [(0, 100), (10, 20), (50, 51), (60, 60)].iter() {
+      let values: Vec<i64> = min.range(lo..hi).map(|(_, v)| *v).collect();
+      assert_eq!(min.fold(lo..hi), values.iter().min().cloned());
+      assert_eq!(max.fold(lo..hi), values.iter().max().cloned());
+    }
+  }
+  #[test]
+  fn test_fold_order() {
+    let mut map: TreapMap<u64, char, Concat> = TreapMap::new(77);
+    for (i, c) in "the quick brown fox".char_indices().rev() {
+      map.insert(i as u64, c);
+    }
+    assert_eq!(map.fold(..), "the quick brown fox");
+    assert_eq!(map.fold(4..9), "quick");
+    assert_eq!(map.fold(16..), "fox");
+    assert_eq!(map.fold(19..), "");
+  }
+  #[test]
+  fn test_apply() {
+    let mut map: TreapMap<u64, i64, Sum, Update<i64>> = TreapMap::new(77);
+    let mut naive = vec![0i64; 1000];
+    for i in 0..1000 {
+      let key = i * 389 % 1000;
+      map.insert(key, key as i64);
+      naive[key as usize] = key as i64;
+    }
+    for i in 0..500u64 {
+      let lo = i * 577 % 1000;
+      let hi = lo + i * 31 % 200;
+      let action = if i % 3 == 0 {
+        Update::Assign(i as i64 - 250)
+      } else {
+        Update::Add(i as i64 % 17 - 8)
+      };
+      map.apply(lo..hi, action);
+      for v in naive
+        .iter_mut()
+        .take(hi.min(1000) as usize)
+        .skip(lo as usize)
+      {
+        *v = match action {
+          Update::Add(delta) => *v + delta,
+          Update::Assign(x) => x,
+        };
+      }
+      let (lo, hi) = (i * 241 % 1000, i * 241 % 1000 + i * 7 % 300);
+      let expected: i64 = naive
+        .iter()
+        .take(hi.min(1000) as usize)
+        .skip(lo as usize)
+        .sum();
+      assert_eq!(map.fold(lo..hi), expected);
+      if i % 50 == 0 {
+        let key = i * 13 % 1000;
+        assert_eq!(map.remove(&key), Some(naive[key as usize]));
+        map.insert(key, naive[key as usize]);
+      }
+    }
+    assert_eq!(map.fold(..), naive.iter().sum());
+    assert_eq!(map.get_current(&123), Some(&naive[123]));
+    let values: Vec<i64> = map.into_iter().map(|(_, v)| v).collect();
+    assert_eq!(values, naive);
+  }
+  #[test]
+  fn test_apply_min_max() {
+    let mut min: TreapMap<u64, i64, Min, Update<i64>> = TreapMap::new(77);
+    let mut max: TreapMap<u64, i64, Max, Update<i64>> = TreapMap::new(77);
+    let mut naive: Vec<i64> = (0..100).map(|key| (key * 37 % 101) - 50).collect();
+    for (key, value) in naive.iter().enumerate() {
+      min.insert(key as u64, *value);
+      max.insert(key as u64, *value);
+    }
+    for (lo, hi, action) in [
+      (20, 40, Update::Add(-100)),
+      (30, 100, Update::Assign(7)),
+      (0, 25, Update::Add(3)),
+      (45, 55, Update::Add(-1)),
+    ]
+    .iter()
+    {
+      min.apply(lo..hi, *action);
+      max.apply(lo..hi, *action);
+      for v in &mut naive[*lo as usize..*hi as usize] {
+        *v = match action {
+          Update::Add(delta) => *v + delta,
+          Update::Assign(x) => *x,
+        };
+      }
+      for (lo, hi) in [(0, 100), (10, 30), (35, 50), (24, 25), (60, 60)].iter() {
+        let values = &naive[*lo as usize..*hi as usize];
+        assert_eq!(min.fold(lo..hi), values.iter().min().cloned());
+        assert_eq!(max.fold(lo..hi), values.iter().max().cloned());
+      }
+    }
+  }
+  #[test]
+  fn test_apply_without_aggregate() {
+    let mut map: TreapMap<String, u64, (), Update<u64>> = ["a", "b", "c", "d"]
+      .iter()
+      .map(|s| (s.to_string(), 1))
+      .collect();
+    map.apply::<str, _>(
+      (Bound::Excluded("a"), Bound::Included("c")),
+      Update::Add(10),
+    );
+    map.apply::<str, _>((Bound::Included("c"), Bound::Unbounded), Update::Add(100));
+    assert_eq!(map.get_current("a"), Some(&1));
+    assert_eq!(map.get_current("b"), Some(&11));
+    assert_eq!(map.get_current("c"), Some(&111));
+    assert_eq!(map.get_current("d"), Some(&101));
+    assert_eq!(map.get_current("e"), None);
+  }
+  #[test]
+  fn test_interval_insert_remove() {
+    let mut set = IntervalTreap::new(3);
+    assert!(set.insert(5..10));
+    assert!(set.insert(5..8));
+    assert!(!set.insert(5..10));
+    assert!(set.insert(1..3));
+    assert_eq!(set.len(), 3);
+    assert!(set.contains(&(5..8)));
+    assert!(!set.contains(&(5..9)));
+    assert!(set.remove(&(5..10)));
+    assert!(!set.remove(&(5..10)));
+    assert_eq!(set.len(), 2);
+    assert!(!set.invalid_max_end());
+  }
+  #[test]
+  #[should_panic(expected = "insert: interval must not be empty")]
+  fn test_interval_insert_empty() {
+    let mut set = IntervalTreap::new(3);
+    set.insert(4..4);
+  }
+  #[test]
+  fn test_interval_queries() {
+    let set: IntervalTreap<u32> = vec![0..5, 3..4, 3..9, 6..7, 8..20, 12..13, 15..16]
+      .into_iter()
+      .collect();
+    let hits: Vec<(&u32, &u32)> = set.overlapping(4..8).collect();
+    assert_eq!(hits, vec![(&0, &5), (&3, &9), (&6, &7)]);
+    let hits: Vec<(&u32, &u32)> = set.overlapping(13..15).collect();
+    assert_eq!(hits, vec![(&8, &20)]);
+    assert_eq!(set.overlapping(5..5).count(), 0);
+    assert_eq!(set.overlapping(20..30).count(), 0);
+    let hits: Vec<(&u32, &u32)> = set.containing(3).collect();
+    assert_eq!(hits, vec![(&0, &5), (&3, &4), (&3, &9)]);
+    let hits: Vec<(&u32, &u32)> = set.containing(12).collect();
+    assert_eq!(hits, vec![(&8, &20), (&12, &13)]);
+    assert_eq!(set.containing(5).count(), 1);
+    assert_eq!(set.containing(20).count(), 0);
+  }
+  #[test]
+  fn test_interval_large() {
+    let mut set = IntervalTreap::new(11);
+    let mut naive = Vec::new();
+    for i in 0..2000u64 {
+      let start = i * 389 % 1000;
+      let end = start + 1 + i * 577 % 50;
+      set.insert(start..end);
+      naive.push((start, end));
+    }
+    let inserted = naive.clone();
+    for i in 0..1000u64 {
+      let interval = inserted[(i * 7 % 2000) as usize];
+      set.remove(&(interval.0..interval.1));
+      naive.retain(|x| *x != interval);
+    }
+    naive.sort();
+    naive.dedup();
+    assert_eq!(set.len(), naive.len());
+    assert!(!set.invalid_max_end());
+    for i in 0..200u64 {
+      let (lo, hi) = (i * 241 % 1000, i * 241 % 1000 + i % 30);
+      let hits: Vec<(u64, u64)> = set.overlapping(lo..hi).map(|(s, e)| (*s, *e)).collect();
+      let expected: Vec<(u64, u64)> = naive
+        .iter()
+        .filter(|(s, e)| lo < hi && *s < hi && lo < *e)
+        .cloned()
+        .collect();
+      assert_eq!(hits, expected);
+      let hits: Vec<(u64, u64)> = set.containing(lo).map(|(s, e)| (*s, *e)).collect();
+      let expected: Vec<(u64, u64)> = naive
+        .iter()
+        .filter(|(s, e)| *s <= lo && lo < *e)
+        .cloned()
+        .collect();
+      assert_eq!(hits, expected);
+    }
   }
 }
@@ -0,0 +1,618 @@
+use std::borrow::Borrow;
+use std::cmp::Ordering;
+use std::fmt::Debug;
+use std::mem::{replace, swap};
+
+use super::iter::{InOrder, TreeNode};
+use super::BST;
+#[cfg(test)]
+use super::{iter::print_tree, unsorted};
+#[derive(Debug)]
+struct Node<K: Ord> {
+  key: K,
+  size: usize,
+  lchild: Option<Box<Node<K>>>,
+  rchild: Option<Box<Node<K>>>,
+}
+
+type Link<K> = Option<Box<Node<K>>>;
+
+/// A subtree may weigh at most `DELTA` times as much as its sibling, where the
+/// weight of a subtree is its size plus one.
+const DELTA: usize = 3;
+/// Restoring the balance takes a single rotation if the inner grandchild on the
+/// heavy side weighs less than `GAMMA` times the outer one, and a double
+/// rotation otherwise. Together with `DELTA` this is the parameter pair Hirai
+/// and Yamamoto proved correct.
+const GAMMA: usize = 2;
+
+/// A weight-balanced tree, also known as a BB[alpha] tree. The subtree sizes
+/// double as the balance information, so `rank` and `select` take O(log n) time
+/// and trees can be split and joined in O(log n) time as well.
+pub struct WeightBalanced<K: Ord + Debug> {
+  root: Option<Box<Node<K>>>,
+}
+
+impl<K: Ord + Debug> WeightBalanced<K> {
+  pub fn new() -> WeightBalanced<K> {
+    WeightBalanced { root: None }
+  }
+  fn size(tree: &Option<Box<Node<K>>>) -> usize {
+    tree.as_ref().map_or(0, |t| t.size)
+  }
+  fn weight(tree: &Option<Box<Node<K>>>) -> usize {
+    WeightBalanced::size(tree) + 1
+  }
+  fn update(tree: &mut Node<K>) {
+    tree.size = 1 + WeightBalanced::size(&tree.lchild) + WeightBalanced::size(&tree.rchild);
+  }
+  fn node(key: K, lchild: Link<K>, rchild: Link<K>) -> Box<Node<K>> {
+    let mut t = Box::new(Node {
+      key,
+      size: 1,
+      lchild,
+      rchild,
+    });
+    WeightBalanced::update(&mut t);
+    t
+  }
+
+  fn rotate_right(tree: &mut Box<Node<K>>) {
+    let lnode = tree.lchild.as_mut().unwrap();
+    let llnode = lnode.lchild.take();
+    let lrnode = lnode.rchild.take();
+    let mut rnode = replace(&mut tree.lchild, llnode);
+    swap(
+      &mut tree.as_mut().key,
+      &mut rnode.as_deref_mut().unwrap().key,
+    );
+    rnode.as_deref_mut().unwrap().lchild = lrnode;
+    rnode.as_deref_mut().unwrap().rchild = tree.rchild.take();
+    WeightBalanced::update(rnode.as_deref_mut().unwrap());
+    tree.rchild = rnode;
+    WeightBalanced::update(tree);
+  }
+
+  fn rotate_left(tree: &mut Box<Node<K>>) {
+    let rnode = tree.rchild.as_mut().unwrap();
+    let rlnode = rnode.lchild.take();
+    let rrnode = rnode.rchild.take();
+    let mut lnode = replace(&mut tree.rchild, rrnode);
+    swap(
+      &mut tree.as_mut().key,
+      &mut lnode.as_deref_mut().unwrap().key,
+    );
+    lnode.as_deref_mut().unwrap().lchild = tree.lchild.take();
+    lnode.as_deref_mut().unwrap().rchild = rlnode;
+    WeightBalanced::update(lnode.as_deref_mut().unwrap());
+    tree.lchild = lnode;
+    WeightBalanced::update(tree);
+  }
+  /// Recomputes the size of `tree` and restores its weight balance with a
+  /// single or double rotation, assuming that a single insertion or deletion
+  /// below it upset the balance.
+  fn balance(tree: &mut Box<Node<K>>) {
+    WeightBalanced::update(tree);
+    let lweight = WeightBalanced::weight(&tree.lchild);
+    let rweight = WeightBalanced::weight(&tree.rchild);
+    if rweight > DELTA * lweight {
+      let rnode = tree.rchild.as_mut().unwrap();
+      if WeightBalanced::weight(&rnode.lchild) >= GAMMA * WeightBalanced::weight(&rnode.rchild) {
+        WeightBalanced::rotate_right(rnode);
+      }
+      WeightBalanced::rotate_left(tree);
+    } else if lweight > DELTA * rweight {
+      let lnode = tree.lchild.as_mut().unwrap();
+      if WeightBalanced::weight(&lnode.rchild) >= GAMMA * WeightBalanced::weight(&lnode.lchild) {
+        WeightBalanced::rotate_left(lnode);
+      }
+      WeightBalanced::rotate_right(tree);
+    }
+  }
+
+  fn _insert(key: K, tree: &mut Link<K>) -> bool {
+    let t = match tree {
+      None => {
+        *tree = Some(WeightBalanced::node(key, None, None));
+        return true;
+      }
+      Some(t) => t,
+    };
+    let inserted = match key.cmp(&t.key) {
+      Ordering::Less => WeightBalanced::_insert(key, &mut t.lchild),
+      Ordering::Greater => WeightBalanced::_insert(key, &mut t.rchild),
+      Ordering::Equal => return false,
+    };
+    WeightBalanced::balance(t);
+    inserted
+  }
+  /// Inserts `key`, which is smaller than every key of `tree`.
+  fn insert_min(key: K, tree: Link<K>) -> Box<Node<K>> {
+    match tree {
+      None => WeightBalanced::node(key, None, None),
+      Some(mut t) => {
+        t.lchild = Some(WeightBalanced::insert_min(key, t.lchild.take()));
+        WeightBalanced::balance(&mut t);
+        t
+      }
+    }
+  }
+  /// Inserts `key`, which is greater than every key of `tree`.
+  fn insert_max(key: K, tree: Link<K>) -> Box<Node<K>> {
+    match tree {
+      None => WeightBalanced::node(key, None, None),
+      Some(mut t) => {
+        t.rchild = Some(WeightBalanced::insert_max(key, t.rchild.take()));
+        WeightBalanced::balance(&mut t);
+        t
+      }
+    }
+  }
+  /// Removes the smallest key from `tree`, returning it and the rest.
+  fn remove_min(mut tree: Box<Node<K>>) -> (K, Link<K>) {
+    match tree.lchild.take() {
+      None => (tree.key, tree.rchild),
+      Some(l) => {
+        let (min, rest) = WeightBalanced::remove_min(l);
+        tree.lchild = rest;
+        WeightBalanced::balance(&mut tree);
+        (min, Some(tree))
+      }
+    }
+  }
+  /// Removes the largest key from `tree`, returning it and the rest.
+  fn remove_max(mut tree: Box<Node<K>>) -> (K, Link<K>) {
+    match tree.rchild.take() {
+      None => (tree.key, tree.lchild),
+      Some(r) => {
+        let (max, rest) = WeightBalanced::remove_max(r);
+        tree.rchild = rest;
+        WeightBalanced::balance(&mut tree);
+        (max, Some(tree))
+      }
+    }
+  }
+  /// Joins the two subtrees of a removed node, taking the new root from the
+  /// heavier side.
+  fn glue(left: Link<K>, right: Link<K>) -> Link<K> {
+    match (left, right) {
+      (None, right) => right,
+      (left, None) => left,
+      (Some(l), Some(r)) => {
+        let mut t = if l.size > r.size {
+          let (max, rest) = WeightBalanced::remove_max(l);
+          WeightBalanced::node(max, rest, Some(r))
+        } else {
+          let (min, rest) = WeightBalanced::remove_min(r);
+          WeightBalanced::node(min, Some(l), rest)
+        };
+        WeightBalanced::balance(&mut t);
+        Some(t)
+      }
+    }
+  }
+  fn _delete<Q>(key: &Q, tree: &mut Link<K>) -> Option<K>
+  where
+    K: Borrow<Q>,
+    Q: Ord + ?Sized,
+  {
+    let t = tree.as_mut()?;
+    let removed = match key.cmp(t.key.borrow()) {
+      Ordering::Less => WeightBalanced::_delete(key, &mut t.lchild)?,
+      Ordering::Greater => WeightBalanced::_delete(key, &mut t.rchild)?,
+      Ordering::Equal => {
+        let t = tree.take().unwrap();
+        *tree = WeightBalanced::glue(t.lchild, t.rchild);
+        return Some(t.key);
+      }
+    };
+    WeightBalanced::balance(t);
+    Some(removed)
+  }
+  /// Joins `left`, `key` and `right`, where every key of `left` is less than
+  /// `key` and every key of `right` is greater. The smaller tree is hung off the
+  /// spine of the heavier one where the weights match, so this takes time
+  /// proportional to the difference of their heights.
+  fn link(left: Link<K>, key: K, right: Link<K>) -> Box<Node<K>> {
+    match (left, right) {
+      (None, right) => WeightBalanced::insert_min(key, right),
+      (left, None) => WeightBalanced::insert_max(key, left),
+      (Some(l), Some(mut r)) if DELTA * (l.size + 1) < r.size + 1 => {
+        r.lchild = Some(WeightBalanced::link(Some(l), key, r.lchild.take()));
+        WeightBalanced::balance(&mut r);
+        r
+      }
+      (Some(mut l), Some(r)) if DELTA * (r.size + 1) < l.size + 1 => {
+        l.rchild = Some(WeightBalanced::link(l.rchild.take(), key, Some(r)));
+        WeightBalanced::balance(&mut l);
+        l
+      }
+      (left, right) => WeightBalanced::node(key, left, right),
+    }
+  }
+  /// Joins `left` and `right`, where every key of `left` is less than every key
+  /// of `right`.
+  fn merge(left: Link<K>, right: Link<K>) -> Link<K> {
+    match (left, right) {
+      (None, right) => right,
+      (left, None) => left,
+      (left, Some(r)) => {
+        let (min, rest) = WeightBalanced::remove_min(r);
+        Some(WeightBalanced::link(left, min, rest))
+      }
+    }
+  }
+  /// Splits `tree` into the keys less than `key`, the key equal to it if there
+  /// is one, and the keys greater than it.
+  fn split<Q>(tree: Link<K>, key: &Q) -> (Link<K>, Option<K>, Link<K>)
+  where
+    K: Borrow<Q>,
+    Q: Ord + ?Sized,
+  {
+    let t = match tree {
+      None => return (None, None, None),
+      Some(t) => *t,
+    };
+    match key.cmp(t.key.borrow()) {
+      Ordering::Less => {
+        let (left, found, right) = WeightBalanced::split(t.lchild, key);
+        let right = WeightBalanced::link(right, t.key, t.rchild);
+        (left, found, Some(right))
+      }
+      Ordering::Greater => {
+        let (left, found, right) = WeightBalanced::split(t.rchild, key);
+        let left = WeightBalanced::link(t.lchild, t.key, left);
+        (Some(left), found, right)
+      }
+      Ordering::Equal => (t.lchild, Some(t.key), t.rchild),
+    }
+  }
+  fn _find<'a, Q>(key: &Q, tree: &'a Link<K>) -> Option<&'a Node<K>>
+  where
+    K: Borrow<Q>,
+    Q: Ord + ?Sized,
+  {
+    let mut tree = tree;
+    while let Some(t) = tree {
+      match key.cmp(t.key.borrow()) {
+        Ordering::Less => tree = &t.lchild,
+        Ordering::Greater => tree = &t.rchild,
+        Ordering::Equal => return Some(t),
+      }
+    }
+    None
+  }
+
+  pub fn len(&self) -> usize {
+    WeightBalanced::size(&self.root)
+  }
+  pub fn is_empty(&self) -> bool {
+    self.root.is_none()
+  }
+  /// Splits the tree in two at `key`, returning the keys greater than or equal
+  /// to `key` and keeping the rest.
+  pub fn split_off<Q>(&mut self, key: &Q) -> WeightBalanced<K>
+  where
+    K: Borrow<Q>,
+    Q: Ord + ?Sized,
+  {
+    let (left, found, right) = WeightBalanced::split(self.root.take(), key);
+    self.root = left;
+    let right = match found {
+      None => right,
+      Some(found) => Some(WeightBalanced::insert_min(found, right)),
+    };
+    WeightBalanced { root: right }
+  }
+  /// Joins two trees where every key of `left` is less than every key of
+  /// `right`.
+  ///
+  /// # Panics
+  ///
+  /// Panics if the key ranges of `left` and `right` overlap.
+  pub fn join(mut left: WeightBalanced<K>, mut right: WeightBalanced<K>) -> WeightBalanced<K> {
+    if let (Some(max), Some(min)) = (left.max(), right.select(0)) {
+      assert!(max < min, "join: key ranges must not overlap");
+    }
+    WeightBalanced {
+      root: WeightBalanced::merge(left.root.take(), right.root.take()),
+    }
+  }
+  /// Returns the `index`-th smallest key, counting from zero.
+  pub fn select(&self, index: usize) -> Option<&K> {
+    let mut index = index;
+    let mut tree = &self.root;
+    while let Some(t) = tree {
+      let lsize = WeightBalanced::size(&t.lchild);
+      match index.cmp(&lsize) {
+        Ordering::Less => tree = &t.lchild,
+        Ordering::Equal => return Some(&t.key),
+        Ordering::Greater => {
+          index -= lsize + 1;
+          tree = &t.rchild;
+        }
+      }
+    }
+    None
+  }
+  /// Returns the largest key, the counterpart of `select(0)`.
+  pub fn max(&self) -> Option<&K> {
+    let mut tree = self.root.as_ref()?;
+    while let Some(r) = &tree.rchild {
+      tree = r;
+    }
+    Some(&tree.key)
+  }
+  /// Returns the number of keys less than `key`.
+  pub fn rank<Q>(&self, key: &Q) -> usize
+  where
+    K: Borrow<Q>,
+    Q: Ord + ?Sized,
+  {
+    let mut rank = 0;
+    let mut tree = &self.root;
+    while let Some(t) = tree {
+      match key.cmp(t.key.borrow()) {
+        Ordering::Less => tree = &t.lchild,
+        Ordering::Equal => return rank + WeightBalanced::size(&t.lchild),
+        Ordering::Greater => {
+          rank += WeightBalanced::size(&t.lchild) + 1;
+          tree = &t.rchild;
+        }
+      }
+    }
+    rank
+  }
+  /// Returns an iterator over the keys in ascending order.
+  pub fn iter(&self) -> Iter<'_, K> {
+    Iter {
+      walk: InOrder::new(&self.root, self.len()),
+    }
+  }
+  #[cfg(test)]
+  pub fn print(&self) -> String {
+    print_tree(self.root.as_deref().unwrap(), &|t| format!("{:?}", t.key))
+  }
+  #[cfg(test)]
+  fn _invalid_balance(tree: &Option<Box<Node<K>>>) -> bool {
+    match tree {
+      None => false,
+      Some(t) => {
+        let lweight = WeightBalanced::weight(&t.lchild);
+        let rweight = WeightBalanced::weight(&t.rchild);
+        t.size != lweight + rweight - 1
+          || lweight > DELTA * rweight
+          || rweight > DELTA * lweight
+          || WeightBalanced::_invalid_balance(&t.lchild)
+          || WeightBalanced::_invalid_balance(&t.rchild)
+      }
+    }
+  }
+  #[cfg(test)]
+  pub fn invalid_balance(&self) -> bool {
+    WeightBalanced::_invalid_balance(&self.root)
+  }
+  #[cfg(test)]
+  pub fn invalid_key(&self) -> bool {
+    unsorted(self.iter())
+  }
+}
+
+impl<K: Ord + Debug> Default for WeightBalanced<K> {
+  fn default() -> WeightBalanced<K> {
+    WeightBalanced::new()
+  }
+}
+
+impl<K: Ord + Debug> BST<K> for WeightBalanced<K> {
+  fn insert(&mut self, key: K) -> bool {
+    WeightBalanced::_insert(key, &mut self.root)
+  }
+  fn find<Q>(&mut self, key: &Q) -> bool
+  where
+    K: Borrow<Q>,
+    Q: Ord + ?Sized,
+  {
+    WeightBalanced::_find(key, &self.root).is_some()
+  }
+  fn delete<Q>(&mut self, key: &Q) -> Option<K>
+  where
+    K: Borrow<Q>,
+    Q: Ord + ?Sized,
+  {
+    WeightBalanced::_delete(key, &mut self.root)
+  }
+}
+
+impl<K: Ord> TreeNode for Node<K> {
+  type Key = K;
+  fn key(&self) -> &K {
+    &self.key
+  }
+  fn lchild(&self) -> &Link<K> {
+    &self.lchild
+  }
+  fn rchild(&self) -> &Link<K> {
+    &self.rchild
+  }
+  fn lchild_mut(&mut self) -> &mut Link<K> {
+    &mut self.lchild
+  }
+  fn rchild_mut(&mut self) -> &mut Link<K> {
+    &mut self.rchild
+  }
+}
+
+/// An in-order iterator over the keys of a `WeightBalanced`.
+pub struct Iter<'a, K: Ord> {
+  walk: InOrder<'a, Node<K>>,
+}
+
+impl<'a, K: Ord> Iterator for Iter<'a, K> {
+  type Item = &'a K;
+
+  fn next(&mut self) -> Option<&'a K> {
+    self.walk.next().map(|t| &t.key)
+  }
+  fn size_hint(&self) -> (usize, Option<usize>) {
+    self.walk.size_hint()
+  }
+}
+
+impl<'a, K: Ord> DoubleEndedIterator for Iter<'a, K> {
+  fn next_back(&mut self) -> Option<&'a K> {
+    self.walk.next_back().map(|t| &t.key)
+  }
+}
+
+impl<'a, K: Ord> ExactSizeIterator for Iter<'a, K> {}
+
+impl<'a, K: Ord + Debug> IntoIterator for &'a WeightBalanced<K> {
+  type Item = &'a K;
+  type IntoIter = Iter<'a, K>;
+
+  fn into_iter(self) -> Iter<'a, K> {
+    self.iter()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::WeightBalanced;
+  use super::BST;
+  use crate::conformance;
+  use assert_str::assert_str_eq;
+
+  #[test]
+  fn test_insert() {
+    let mut tree: WeightBalanced<u64> = WeightBalanced::new();
+    assert!(tree.insert(10));
+    assert!(tree.insert(50));
+    assert!(!tree.insert(10));
+    assert_str_eq!(tree.print(), "[10()([50()()])]");
+    assert!(tree.insert(100));
+    assert_str_eq!(tree.print(), "[10()([50()([100()()])])]");
+    // single rotation
+    assert!(tree.insert(200));
+    assert_str_eq!(tree.print(), "[50([10()()])([100()([200()()])])]");
+    // double rotation
+    assert!(tree.insert(5));
+    assert!(tree.insert(7));
+    assert!(tree.insert(8));
+    assert_str_eq!(
+      tree.print(),
+      "[50([7([5()()])([10([8()()])()])])([100()([200()()])])]"
+    );
+    assert!(!tree.invalid_balance());
+  }
+  #[test]
+  fn test_find_delete() {
+    let mut tree: WeightBalanced<u64> = WeightBalanced::new();
+    for key in [10, 50, 5, 200, 400, 100].iter() {
+      tree.insert(*key);
+    }
+    assert!(tree.find(&5));
+    assert!(!tree.find(&7));
+    assert_eq!(tree.delete(&50), Some(50));
+    assert_eq!(tree.delete(&50), None);
+    assert_eq!(tree.delete(&5), Some(5));
+    assert!(!tree.invalid_balance());
+    assert!(!tree.invalid_key());
+    let keys: Vec<u64> = tree.iter().cloned().collect();
+    assert_eq!(keys, vec![10, 100, 200, 400]);
+  }
+  #[test]
+  fn test_find_borrowed() {
+    conformance::find_borrowed(WeightBalanced::new());
+  }
+  #[test]
+  fn test_select_rank() {
+    let mut tree: WeightBalanced<u64> = WeightBalanced::new();
+    assert_eq!(tree.select(0), None);
+    assert_eq!(tree.rank(&10), 0);
+    for key in [10, 50, 5, 200, 400, 100, 7, 300].iter() {
+      tree.insert(*key);
+    }
+    assert_eq!(tree.len(), 8);
+    assert_eq!(tree.select(0), Some(&5));
+    assert_eq!(tree.select(3), Some(&50));
+    assert_eq!(tree.select(8), None);
+    assert_eq!(tree.rank(&50), 3);
+    assert_eq!(tree.rank(&60), 4);
+    assert_eq!(tree.rank(&1000), 8);
+    for (i, key) in tree.iter().enumerate() {
+      assert_eq!(tree.select(i), Some(key));
+      assert_eq!(tree.rank(key), i);
+    }
+  }
+  #[test]
+  fn test_split_off_join() {
+    let mut tree: WeightBalanced<u64> = WeightBalanced::new();
+    for key in 0..1000 {
+      tree.insert(key);
+    }
+    let mut right = tree.split_off(&300);
+    assert!(tree.iter().cloned().eq(0..300));
+    assert!(right.iter().cloned().eq(300..1000));
+    assert!(!tree.invalid_balance());
+    assert!(!right.invalid_balance());
+    let right_2 = right.split_off(&999);
+    assert_eq!(right_2.len(), 1);
+    assert!(!right.invalid_balance());
+    let small: WeightBalanced<u64> = (1000..1003).fold(WeightBalanced::new(), |mut tree, key| {
+      tree.insert(key);
+      tree
+    });
+    let joined = WeightBalanced::join(WeightBalanced::join(tree, right), small);
+    assert!(joined.iter().cloned().eq((0..999).chain(1000..1003)));
+    assert!(!joined.invalid_balance());
+  }
+  #[test]
+  fn test_join_large_small() {
+    let mut large: WeightBalanced<u64> = WeightBalanced::new();
+    for key in 0..100_000 {
+      large.insert(key);
+    }
+    assert_eq!(large.max(), Some(&99_999));
+    let mut small: WeightBalanced<u64> = WeightBalanced::new();
+    small.insert(100_000);
+    small.insert(100_001);
+    let mut joined = WeightBalanced::join(large, small);
+    assert_eq!(joined.len(), 100_002);
+    assert_eq!(joined.max(), Some(&100_001));
+    assert!(!joined.invalid_balance());
+    let mut tiny: WeightBalanced<u64> = WeightBalanced::new();
+    tiny.insert(0);
+    joined.delete(&0);
+    let joined = WeightBalanced::join(tiny, joined);
+    assert!(joined.iter().cloned().eq(0..100_002));
+    assert!(!joined.invalid_balance());
+    assert_eq!(WeightBalanced::<u64>::new().max(), None);
+  }
+  #[test]
+  #[should_panic]
+  fn test_join_overlap() {
+    let mut left: WeightBalanced<u64> = WeightBalanced::new();
+    let mut right: WeightBalanced<u64> = WeightBalanced::new();
+    left.insert(10);
+    right.insert(5);
+    WeightBalanced::join(left, right);
+  }
+  #[test]
+  fn test_invariants() {
+    let tree = conformance::invariants(WeightBalanced::new(), |tree| {
+      assert!(!tree.invalid_balance());
+      assert!(!tree.invalid_key());
+    });
+    assert!(tree.is_empty());
+  }
+  #[test]
+  fn test_sorted_insert_large() {
+    let tree = conformance::sorted_insert_large(WeightBalanced::new(), |tree| {
+      assert!(!tree.invalid_balance());
+    });
+    assert_eq!(tree.len(), 50_000);
+    assert_eq!(tree.select(27_160), Some(&54_321));
+    assert_eq!(tree.rank(&99_999), 49_999);
+  }
+}
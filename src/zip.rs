@@ -0,0 +1,481 @@
+use std::borrow::Borrow;
+use std::cmp::Ordering;
+use std::fmt::Debug;
+
+use rand::Rng;
+
+use super::iter::{InOrder, TreeNode};
+use super::BST;
+#[cfg(test)]
+use super::{iter::print_tree, unsorted};
+#[derive(Debug)]
+struct Node<K: Ord> {
+  key: K,
+  rank: (u32, u32),
+  size: usize,
+  lchild: Option<Box<Node<K>>>,
+  rchild: Option<Box<Node<K>>>,
+}
+
+type Link<K> = Option<Box<Node<K>>>;
+type Path<K> = Vec<(Box<Node<K>>, Ordering)>;
+type Halves<K> = (Link<K>, Link<K>);
+
+/// A zip tree after Tarjan, Levy and Timmel: a treap-like tree whose nodes get
+/// geometrically distributed ranks, kept in heap order with ties broken in
+/// favor of the smaller key. Insertions and deletions unzip and zip paths
+/// instead of rotating.
+///
+/// The zip-zip variant of Gila, Goodrich and Tarjan pairs the geometric rank
+/// with a uniform secondary rank, which makes ties rare and brings the expected
+/// depth down to that of a treap.
+pub struct ZipTree<K: Ord + Debug> {
+  root: Option<Box<Node<K>>>,
+  rng: rand::rngs::StdRng,
+  zip_zip: bool,
+}
+
+impl<K: Ord + Debug> ZipTree<K> {
+  /// Creates a zip tree whose ranks are drawn from an RNG seeded as in
+  /// `Treap::new(seed)`.
+  pub fn new(seed: u8) -> ZipTree<K> {
+    ZipTree {
+      root: None,
+      rng: rand::SeedableRng::from_seed([seed; 32]),
+      zip_zip: false,
+    }
+  }
+  /// Creates a zip-zip tree, seeded as in `new(seed)`.
+  pub fn new_zip_zip(seed: u8) -> ZipTree<K> {
+    ZipTree {
+      zip_zip: true,
+      ..ZipTree::new(seed)
+    }
+  }
+  fn random_rank(&mut self) -> (u32, u32) {
+    // The number of heads before the first tail of a fair coin.
+    let geometric = self.rng.gen::<u64>().trailing_ones();
+    let uniform = if self.zip_zip { self.rng.gen() } else { 0 };
+    (geometric, uniform)
+  }
+  fn size(tree: &Option<Box<Node<K>>>) -> usize {
+    tree.as_ref().map_or(0, |t| t.size)
+  }
+  fn update(tree: &mut Node<K>) {
+    tree.size = 1 + ZipTree::size(&tree.lchild) + ZipTree::size(&tree.rchild);
+  }
+  /// Whether `a` belongs above `b`.
+  fn above(a: &Node<K>, b: &Node<K>) -> bool {
+    a.rank > b.rank || (a.rank == b.rank && a.key < b.key)
+  }
+
+  /// Unzips `tree` into the nodes with keys less than `key` and the rest. If
+  /// `tree` already holds `key`, it is handed back unchanged as `Err`.
+  fn unzip(tree: Link<K>, key: &K) -> Result<Halves<K>, Link<K>> {
+    let mut t = match tree {
+      None => return Ok((None, None)),
+      Some(t) => t,
+    };
+    match t.key.cmp(key) {
+      Ordering::Equal => Err(Some(t)),
+      Ordering::Less => match ZipTree::unzip(t.rchild.take(), key) {
+        Ok((l, r)) => {
+          t.rchild = l;
+          ZipTree::update(&mut t);
+          Ok((Some(t), r))
+        }
+        Err(rchild) => {
+          t.rchild = rchild;
+          Err(Some(t))
+        }
+      },
+      Ordering::Greater => match ZipTree::unzip(t.lchild.take(), key) {
+        Ok((l, r)) => {
+          t.lchild = r;
+          ZipTree::update(&mut t);
+          Ok((l, Some(t)))
+        }
+        Err(lchild) => {
+          t.lchild = lchild;
+          Err(Some(t))
+        }
+      },
+    }
+  }
+  /// Zips two trees where every key of `left` is less than every key of `right`.
+  fn zip(left: Link<K>, right: Link<K>) -> Link<K> {
+    match (left, right) {
+      (None, r) => r,
+      (l, None) => l,
+      (Some(mut l), Some(mut r)) => {
+        if ZipTree::above(&l, &r) {
+          l.rchild = ZipTree::zip(l.rchild.take(), Some(r));
+          ZipTree::update(&mut l);
+          Some(l)
+        } else {
+          r.lchild = ZipTree::zip(Some(l), r.lchild.take());
+          ZipTree::update(&mut r);
+          Some(r)
+        }
+      }
+    }
+  }
+
+  /// Reattaches `tree` below the ancestors in `path`, updating their sizes.
+  fn rebuild(mut path: Path<K>, tree: Link<K>) -> Link<K> {
+    let mut tree = tree;
+    while let Some((mut p, dir)) = path.pop() {
+      match dir {
+        Ordering::Less => p.lchild = tree,
+        _ => p.rchild = tree,
+      }
+      ZipTree::update(&mut p);
+      tree = Some(p);
+    }
+    tree
+  }
+  /// Inserts `node` unless its key is already in `root`, returning whether it
+  /// did.
+  fn _insert(mut node: Box<Node<K>>, root: &mut Link<K>) -> bool {
+    let mut path = Vec::new();
+    let mut tree = root.take();
+    // Descend past the nodes that stay above the new one.
+    while let Some(mut t) = tree.take() {
+      let order = node.key.cmp(&t.key);
+      if order == Ordering::Equal {
+        *root = ZipTree::rebuild(path, Some(t));
+        return false;
+      }
+      if !ZipTree::above(&t, &node) {
+        tree = Some(t);
+        break;
+      }
+      tree = match order {
+        Ordering::Less => t.lchild.take(),
+        _ => t.rchild.take(),
+      };
+      path.push((t, order));
+    }
+    // The rest of the search path for the key is the path `unzip` follows.
+    let (l, r) = match ZipTree::unzip(tree, &node.key) {
+      Ok(halves) => halves,
+      Err(tree) => {
+        *root = ZipTree::rebuild(path, tree);
+        return false;
+      }
+    };
+    node.lchild = l;
+    node.rchild = r;
+    ZipTree::update(&mut node);
+    *root = ZipTree::rebuild(path, Some(node));
+    true
+  }
+  fn _delete<Q>(key: &Q, root: &mut Link<K>) -> Option<K>
+  where
+    K: Borrow<Q>,
+    Q: Ord + ?Sized,
+  {
+    let mut path = Vec::new();
+    let mut tree = root.take();
+    while let Some(mut t) = tree {
+      let order = key.cmp(t.key.borrow());
+      if order == Ordering::Equal {
+        let rest = ZipTree::zip(t.lchild.take(), t.rchild.take());
+        *root = ZipTree::rebuild(path, rest);
+        return Some(t.key);
+      }
+      tree = match order {
+        Ordering::Less => t.lchild.take(),
+        _ => t.rchild.take(),
+      };
+      path.push((t, order));
+    }
+    *root = ZipTree::rebuild(path, None);
+    None
+  }
+  fn _find<'a, Q>(key: &Q, tree: &'a Link<K>) -> Option<&'a Node<K>>
+  where
+    K: Borrow<Q>,
+    Q: Ord + ?Sized,
+  {
+    let mut tree = tree;
+    while let Some(t) = tree {
+      match key.cmp(t.key.borrow()) {
+        Ordering::Less => tree = &t.lchild,
+        Ordering::Greater => tree = &t.rchild,
+        Ordering::Equal => return Some(t),
+      }
+    }
+    None
+  }
+  fn _depth(tree: &Option<Box<Node<K>>>) -> usize {
+    match tree {
+      None => 0,
+      Some(t) => 1 + ZipTree::_depth(&t.lchild).max(ZipTree::_depth(&t.rchild)),
+    }
+  }
+
+  pub fn len(&self) -> usize {
+    ZipTree::size(&self.root)
+  }
+  pub fn is_empty(&self) -> bool {
+    self.root.is_none()
+  }
+  /// Returns the number of nodes on the longest root-to-leaf path.
+  pub fn depth(&self) -> usize {
+    ZipTree::_depth(&self.root)
+  }
+  /// Returns an iterator over the keys in ascending order.
+  pub fn iter(&self) -> Iter<'_, K> {
+    Iter {
+      walk: InOrder::new(&self.root, self.len()),
+    }
+  }
+  /// Inserts `key` with a chosen geometric rank, so tests can build fixed shapes.
+  #[cfg(test)]
+  fn insert_ranked(&mut self, key: K, rank: u32) -> bool {
+    let node = Box::new(Node {
+      key,
+      rank: (rank, 0),
+      size: 1,
+      lchild: None,
+      rchild: None,
+    });
+    ZipTree::_insert(node, &mut self.root)
+  }
+  #[cfg(test)]
+  pub fn print(&self) -> String {
+    print_tree(self.root.as_deref().unwrap(), &|t| {
+      format!("{:?}:{}", t.key, t.rank.0)
+    })
+  }
+  #[cfg(test)]
+  fn _invalid_rank(tree: &Node<K>) -> bool {
+    [&tree.lchild, &tree.rchild]
+      .iter()
+      .any(|child| match child {
+        None => false,
+        Some(c) => ZipTree::above(c, tree) || ZipTree::_invalid_rank(c),
+      })
+  }
+  #[cfg(test)]
+  pub fn invalid_rank(&self) -> bool {
+    self
+      .root
+      .as_ref()
+      .is_some_and(|t| ZipTree::_invalid_rank(t))
+  }
+  #[cfg(test)]
+  fn _invalid_size(tree: &Option<Box<Node<K>>>) -> bool {
+    match tree {
+      None => false,
+      Some(t) => {
+        t.size != 1 + ZipTree::size(&t.lchild) + ZipTree::size(&t.rchild)
+          || ZipTree::_invalid_size(&t.lchild)
+          || ZipTree::_invalid_size(&t.rchild)
+      }
+    }
+  }
+  #[cfg(test)]
+  pub fn invalid_size(&self) -> bool {
+    ZipTree::_invalid_size(&self.root)
+  }
+  #[cfg(test)]
+  pub fn invalid_key(&self) -> bool {
+    unsorted(self.iter())
+  }
+}
+
+impl<K: Ord + Debug> BST<K> for ZipTree<K> {
+  fn insert(&mut self, key: K) -> bool {
+    let node = Box::new(Node {
+      key,
+      rank: self.random_rank(),
+      size: 1,
+      lchild: None,
+      rchild: None,
+    });
+    ZipTree::_insert(node, &mut self.root)
+  }
+  fn find<Q>(&mut self, key: &Q) -> bool
+  where
+    K: Borrow<Q>,
+    Q: Ord + ?Sized,
+  {
+    ZipTree::_find(key, &self.root).is_some()
+  }
+  fn delete<Q>(&mut self, key: &Q) -> Option<K>
+  where
+    K: Borrow<Q>,
+    Q: Ord + ?Sized,
+  {
+    ZipTree::_delete(key, &mut self.root)
+  }
+}
+
+impl<K: Ord> TreeNode for Node<K> {
+  type Key = K;
+  fn key(&self) -> &K {
+    &self.key
+  }
+  fn lchild(&self) -> &Link<K> {
+    &self.lchild
+  }
+  fn rchild(&self) -> &Link<K> {
+    &self.rchild
+  }
+  fn lchild_mut(&mut self) -> &mut Link<K> {
+    &mut self.lchild
+  }
+  fn rchild_mut(&mut self) -> &mut Link<K> {
+    &mut self.rchild
+  }
+}
+
+/// An in-order iterator over the keys of a `ZipTree`.
+pub struct Iter<'a, K: Ord> {
+  walk: InOrder<'a, Node<K>>,
+}
+
+impl<'a, K: Ord> Iterator for Iter<'a, K> {
+  type Item = &'a K;
+
+  fn next(&mut self) -> Option<&'a K> {
+    self.walk.next().map(|t| &t.key)
+  }
+  fn size_hint(&self) -> (usize, Option<usize>) {
+    self.walk.size_hint()
+  }
+}
+
+impl<'a, K: Ord> DoubleEndedIterator for Iter<'a, K> {
+  fn next_back(&mut self) -> Option<&'a K> {
+    self.walk.next_back().map(|t| &t.key)
+  }
+}
+
+impl<'a, K: Ord> ExactSizeIterator for Iter<'a, K> {}
+
+impl<'a, K: Ord + Debug> IntoIterator for &'a ZipTree<K> {
+  type Item = &'a K;
+  type IntoIter = Iter<'a, K>;
+
+  fn into_iter(self) -> Iter<'a, K> {
+    self.iter()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::ZipTree;
+  use super::BST;
+  use crate::conformance;
+  use assert_str::assert_str_eq;
+
+  #[test]
+  fn test_insert() {
+    let mut tree: ZipTree<u64> = ZipTree::new(77);
+    assert!(tree.insert(10));
+    assert!(!tree.insert(10));
+    assert_str_eq!(tree.print(), "[10:0()()]");
+    assert!(!tree.invalid_rank());
+  }
+  #[test]
+  fn test_unzip() {
+    let mut tree: ZipTree<u64> = ZipTree::new(77);
+    tree.insert_ranked(10, 1);
+    tree.insert_ranked(20, 0);
+    tree.insert_ranked(30, 2);
+    assert_str_eq!(tree.print(), "[30:2([10:1()([20:0()()])])()]");
+    // The path from 10 is unzipped around the new key.
+    tree.insert_ranked(15, 1);
+    assert_str_eq!(tree.print(), "[30:2([10:1()([15:1()([20:0()()])])])()]");
+    tree.insert_ranked(5, 1);
+    assert_str_eq!(
+      tree.print(),
+      "[30:2([5:1()([10:1()([15:1()([20:0()()])])])])()]"
+    );
+    tree.insert_ranked(12, 3);
+    assert_str_eq!(
+      tree.print(),
+      "[12:3([5:1()([10:1()()])])([30:2([15:1()([20:0()()])])()])]"
+    );
+    // A key already present is found either while descending past the nodes
+    // that stay above the new one or below the point where it would go.
+    assert!(!tree.insert_ranked(10, 1));
+    assert!(!tree.insert_ranked(20, 2));
+    assert!(!tree.insert_ranked(10, 5));
+    assert_str_eq!(
+      tree.print(),
+      "[12:3([5:1()([10:1()()])])([30:2([15:1()([20:0()()])])()])]"
+    );
+    assert!(!tree.invalid_rank());
+    assert!(!tree.invalid_size());
+    assert!(!tree.invalid_key());
+  }
+  #[test]
+  fn test_zip() {
+    let mut tree: ZipTree<u64> = ZipTree::new(77);
+    tree.insert_ranked(12, 3);
+    tree.insert_ranked(5, 1);
+    tree.insert_ranked(10, 1);
+    tree.insert_ranked(30, 2);
+    tree.insert_ranked(15, 1);
+    tree.insert_ranked(20, 0);
+    assert_eq!(tree.delete(&12), Some(12));
+    assert_eq!(tree.delete(&12), None);
+    // Ties between the zipped spines go to the smaller key.
+    assert_str_eq!(
+      tree.print(),
+      "[30:2([5:1()([10:1()([15:1()([20:0()()])])])])()]"
+    );
+    assert_eq!(tree.delete(&30), Some(30));
+    assert_str_eq!(tree.print(), "[5:1()([10:1()([15:1()([20:0()()])])])]");
+    assert!(!tree.invalid_rank());
+    assert!(!tree.invalid_size());
+  }
+  #[test]
+  fn test_find_borrowed() {
+    conformance::find_borrowed(ZipTree::new(77));
+    conformance::find_borrowed(ZipTree::new_zip_zip(77));
+  }
+  #[test]
+  fn test_same_seed() {
+    let mut a: ZipTree<u64> = ZipTree::new(3);
+    let mut b: ZipTree<u64> = ZipTree::new(3);
+    let mut c: ZipTree<u64> = ZipTree::new_zip_zip(3);
+    let mut d: ZipTree<u64> = ZipTree::new_zip_zip(3);
+    for i in 0..100 {
+      let key = i * 389 % 1000;
+      a.insert(key);
+      b.insert(key);
+      c.insert(key);
+      d.insert(key);
+    }
+    assert_str_eq!(a.print(), b.print());
+    assert_str_eq!(c.print(), d.print());
+  }
+  #[test]
+  fn test_invariants() {
+    for tree in [ZipTree::new(77), ZipTree::new_zip_zip(77)] {
+      let tree = conformance::invariants(tree, |tree| {
+        assert!(!tree.invalid_rank());
+        assert!(!tree.invalid_size());
+        assert!(!tree.invalid_key());
+      });
+      assert!(tree.is_empty());
+    }
+  }
+  #[test]
+  fn test_sorted_insert_large() {
+    for tree in [ZipTree::new(77), ZipTree::new_zip_zip(77)] {
+      let tree = conformance::sorted_insert_large(tree, |tree| {
+        // The expected depth is about 1.5 log2(n) for zip trees and 1.39 log2(n)
+        // for zip-zip trees.
+        assert!(tree.depth() <= 60);
+        assert!(!tree.invalid_size());
+      });
+      assert!(tree.iter().cloned().eq((1..100_000).step_by(2)));
+    }
+  }
+}